@@ -39,6 +39,9 @@ pub enum Error {
     #[snafu(display("Unable to start Spice Runtime servers: {source}"))]
     UnableToStartServers { source: runtime::Error },
 
+    #[snafu(display("Unable to load TLS configuration: {source}"))]
+    UnableToLoadTls { source: runtime::Error },
+
     #[snafu(display("Failed to load dataset: {source}"))]
     UnableToLoadDataset { source: runtime::Error },
 
@@ -99,6 +102,10 @@ pub async fn run(args: Args) -> Result<()> {
 
     rt.load_secrets().await;
 
+    rt.load_auth().await;
+
+    rt.load_tls().await.context(UnableToLoadTlsSnafu)?;
+
     rt.load_datasets().await;
 
     rt.load_models().await;
@@ -20,7 +20,7 @@ use std::path::PathBuf;
 
 use snafu::prelude::*;
 use spicepod::{
-    component::{dataset::Dataset, model::Model, secrets::Secrets},
+    component::{access::Role, auth::Auth, dataset::Dataset, model::Model, secrets::Secrets},
     Spicepod,
 };
 
@@ -30,6 +30,10 @@ pub struct App {
 
     pub secrets: Secrets,
 
+    pub auth: Option<Auth>,
+
+    pub access: Vec<Role>,
+
     pub datasets: Vec<Dataset>,
 
     pub models: Vec<Model>,
@@ -55,6 +59,8 @@ impl App {
         let spicepod_root =
             Spicepod::load(&path).context(UnableToLoadSpicepodSnafu { path: path.clone() })?;
         let secrets = spicepod_root.secrets.clone();
+        let auth = spicepod_root.auth.clone();
+        let access = spicepod_root.access.clone();
         let mut datasets: Vec<Dataset> = vec![];
         let mut models: Vec<Model> = vec![];
         for dataset in &spicepod_root.datasets {
@@ -88,6 +94,8 @@ impl App {
         Ok(App {
             name: root_spicepod_name,
             secrets,
+            auth,
+            access,
             datasets,
             models,
             spicepods,
@@ -26,7 +26,7 @@ use time::{OffsetDateTime, PrimitiveDateTime};
 use sea_query::{
     Alias, ColumnDef, ColumnType, GenericBuilder, Index, InsertStatement, IntoIden,
     IntoIndexColumn, MysqlQueryBuilder, PostgresQueryBuilder, Query, SimpleExpr,
-    SqliteQueryBuilder, Table,
+    SqliteQueryBuilder, Table, TableAlterStatement,
 };
 
 pub struct CreateTableBuilder {
@@ -96,6 +96,82 @@ impl CreateTableBuilder {
     }
 }
 
+/// Builds the `ALTER TABLE` statements that evolve a table from one schema to another, adding the new columns and
+/// changing the type of the columns whose type differs.
+///
+/// Each change is a separate statement, as `SQLite` only supports one change per `ALTER TABLE`.
+pub struct AlterTableBuilder {
+    table_name: String,
+    from: SchemaRef,
+    to: SchemaRef,
+}
+
+impl AlterTableBuilder {
+    #[must_use]
+    pub fn new(table_name: &str, from: SchemaRef, to: SchemaRef) -> Self {
+        Self {
+            table_name: table_name.to_string(),
+            from,
+            to,
+        }
+    }
+
+    #[must_use]
+    pub fn build_postgres(self) -> Vec<String> {
+        self.statements(true, true)
+            .iter()
+            .map(|stmt| stmt.to_string(PostgresQueryBuilder))
+            .collect()
+    }
+
+    /// `SQLite` columns accept values of any type, so only the new columns are added.
+    #[must_use]
+    pub fn build_sqlite(self) -> Vec<String> {
+        self.statements(false, true)
+            .iter()
+            .map(|stmt| stmt.to_string(SqliteQueryBuilder))
+            .collect()
+    }
+
+    /// `DuckDB` takes a single action per `ALTER TABLE` and can't add columns with constraints, so only the column
+    /// types are changed and the existing columns keep their constraints.
+    #[must_use]
+    pub fn build_duckdb(self) -> Vec<String> {
+        self.statements(true, false)
+            .iter()
+            .map(|stmt| stmt.to_string(PostgresQueryBuilder))
+            .collect()
+    }
+
+    fn statements(&self, modify_columns: bool, constraints: bool) -> Vec<TableAlterStatement> {
+        let mut statements = vec![];
+        for field in self.to.fields() {
+            let existing = self.from.field_with_name(field.name()).ok();
+            if existing.is_some_and(|existing| {
+                !modify_columns || existing.data_type() == field.data_type()
+            }) {
+                continue;
+            }
+
+            let column_type = map_data_type_to_column_type(field.data_type());
+            let mut column_def = ColumnDef::new_with_type(Alias::new(field.name()), column_type);
+            if constraints && !field.is_nullable() {
+                column_def.not_null();
+            }
+
+            let mut alter_stmt = Table::alter();
+            alter_stmt.table(Alias::new(self.table_name.as_str()));
+            if existing.is_some() {
+                alter_stmt.modify_column(&mut column_def);
+            } else {
+                alter_stmt.add_column(&mut column_def);
+            }
+            statements.push(alter_stmt);
+        }
+        statements
+    }
+}
+
 macro_rules! push_value {
     ($row_values:expr, $column:expr, $row:expr, $array_type:ident) => {{
         let array = $column.as_any().downcast_ref::<array::$array_type>();
@@ -365,6 +441,46 @@ mod tests {
     use super::*;
     use arrow::datatypes::{DataType, Field, Int32Type, Schema};
 
+    #[test]
+    fn test_alter_table() {
+        let from = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("age", DataType::Int32, true),
+        ]);
+        let to = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("age", DataType::Int64, true),
+            Field::new("email", DataType::Utf8, true),
+        ]);
+
+        let sql = AlterTableBuilder::new("users", Arc::new(from.clone()), Arc::new(to.clone()))
+            .build_postgres();
+        assert_eq!(
+            sql,
+            vec![
+                "ALTER TABLE \"users\" ALTER COLUMN \"age\" TYPE bigint",
+                "ALTER TABLE \"users\" ADD COLUMN \"email\" text",
+            ]
+        );
+
+        let sql = AlterTableBuilder::new("users", Arc::new(from), Arc::new(to)).build_sqlite();
+        assert_eq!(sql, vec!["ALTER TABLE \"users\" ADD COLUMN \"email\" text"]);
+
+        let from = Schema::new(vec![Field::new("id", DataType::Int32, false)]);
+        let to = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("email", DataType::Utf8, true),
+        ]);
+        let sql = AlterTableBuilder::new("users", Arc::new(from), Arc::new(to)).build_duckdb();
+        assert_eq!(
+            sql,
+            vec![
+                "ALTER TABLE \"users\" ALTER COLUMN \"id\" TYPE bigint",
+                "ALTER TABLE \"users\" ADD COLUMN \"email\" text",
+            ]
+        );
+    }
+
     #[test]
     fn test_basic_table_creation() {
         let schema = Schema::new(vec![
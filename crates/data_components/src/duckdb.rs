@@ -16,6 +16,7 @@ limitations under the License.
 
 use crate::{delete::DeletionTableProviderAdapter, Read, ReadWrite};
 use arrow::{array::RecordBatch, datatypes::SchemaRef};
+use arrow_sql_gen::statement::AlterTableBuilder;
 use async_trait::async_trait;
 use datafusion::{
     common::OwnedTableReference,
@@ -64,6 +65,9 @@ pub enum Error {
     #[snafu(display("Unable to create duckdb table: {source}"))]
     UnableToCreateDuckDBTable { source: duckdb::Error },
 
+    #[snafu(display("Unable to alter duckdb table: {source}"))]
+    UnableToAlterDuckDBTable { source: duckdb::Error },
+
     #[snafu(display("Unable to insert into duckdb table: {source}"))]
     UnableToInsertToDuckDBTable { source: duckdb::Error },
 
@@ -151,19 +155,7 @@ impl TableProviderFactory for DuckDBTableProviderFactory {
             .context(UnableToCommitDuckDBTransactionSnafu)
             .map_err(to_datafusion_error)?;
 
-        let dyn_pool: Arc<DynDuckDbConnectionPool> = pool;
-
-        let read_provider = Arc::new(SqlTable::new_with_schema(
-            &dyn_pool,
-            Arc::clone(&schema),
-            OwnedTableReference::bare(name.clone()),
-        ));
-
-        let read_write_provider = DuckDBTableWriter::create(read_provider, duckdb);
-
-        let deleted_table_provider = DeletionTableProviderAdapter::new(read_write_provider);
-
-        Ok(Arc::new(deleted_table_provider))
+        Ok(duckdb.into_table_provider())
     }
 }
 
@@ -280,6 +272,52 @@ impl DuckDB {
         Ok(count)
     }
 
+    /// Returns a `TableProvider` that reads, writes and deletes from this table.
+    fn into_table_provider(self) -> Arc<dyn TableProvider> {
+        let dyn_pool: Arc<DynDuckDbConnectionPool> = Arc::clone(&self.pool);
+
+        let read_provider = Arc::new(SqlTable::new_with_schema(
+            &dyn_pool,
+            Arc::clone(&self.schema),
+            OwnedTableReference::bare(self.table_name.clone()),
+        ));
+
+        let read_write_provider = DuckDBTableWriter::create(read_provider, self);
+
+        Arc::new(DeletionTableProviderAdapter::new(read_write_provider))
+    }
+
+    /// Alters the table to `schema`, adding the new columns and changing the type of the existing ones, and returns
+    /// a `TableProvider` for the altered table.
+    pub(crate) async fn evolve_schema(&self, schema: SchemaRef) -> Result<Arc<dyn TableProvider>> {
+        let mut db_conn = self.connect().await?;
+        let duckdb_conn = Self::duckdb_conn(&mut db_conn)?;
+
+        let tx = duckdb_conn
+            .conn
+            .transaction()
+            .context(UnableToBeginTransactionSnafu)?;
+
+        let statements = AlterTableBuilder::new(
+            &self.table_name,
+            Arc::clone(&self.schema),
+            Arc::clone(&schema),
+        )
+        .build_duckdb();
+        for sql in statements {
+            tracing::trace!("{sql}");
+            tx.execute(&sql, [])
+                .context(UnableToAlterDuckDBTableSnafu)?;
+        }
+
+        tx.commit().context(UnableToCommitTransactionSnafu)?;
+
+        Ok(
+            DuckDB::new(self.table_name.clone(), schema, Arc::clone(&self.pool))
+                .into_table_provider(),
+        )
+    }
+
     fn create_table(&self, transaction: &Transaction<'_>) -> Result<()> {
         let empty_record = RecordBatch::new_empty(Arc::clone(&self.schema));
 
@@ -19,7 +19,7 @@ use arrow::{
     array::RecordBatch,
     datatypes::{Schema, SchemaRef},
 };
-use arrow_sql_gen::statement::{CreateTableBuilder, InsertBuilder};
+use arrow_sql_gen::statement::{AlterTableBuilder, CreateTableBuilder, InsertBuilder};
 use async_trait::async_trait;
 use bb8_postgres::{
     tokio_postgres::{types::ToSql, Transaction},
@@ -81,6 +81,11 @@ pub enum Error {
         source: tokio_postgres::error::Error,
     },
 
+    #[snafu(display("Unable to alter the Postgres table: {source}"))]
+    UnableToAlterPostgresTable {
+        source: tokio_postgres::error::Error,
+    },
+
     #[snafu(display("Unable to commit the Postgres transaction: {source}"))]
     UnableToCommitPostgresTransaction {
         source: tokio_postgres::error::Error,
@@ -221,17 +226,7 @@ impl TableProviderFactory for PostgresTableProviderFactory {
             .context(UnableToCommitPostgresTransactionSnafu)
             .map_err(to_datafusion_error)?;
 
-        let dyn_pool: Arc<DynPostgresConnectionPool> = pool;
-
-        let read_provider = Arc::new(SqlTable::new_with_schema(
-            &dyn_pool,
-            Arc::clone(&schema),
-            OwnedTableReference::bare(name.clone()),
-        ));
-
-        let delete_adapter =
-            DeletionTableProviderAdapter::new(PostgresTableWriter::create(read_provider, postgres));
-        Ok(Arc::new(delete_adapter))
+        Ok(postgres.into_table_provider(schema))
     }
 }
 
@@ -336,6 +331,52 @@ impl Postgres {
         Ok(deleted as u64)
     }
 
+    /// Returns a `TableProvider` that reads, writes and deletes from this table.
+    fn into_table_provider(self, schema: SchemaRef) -> Arc<dyn TableProvider> {
+        let dyn_pool: Arc<DynPostgresConnectionPool> = Arc::clone(&self.pool);
+
+        let read_provider = Arc::new(SqlTable::new_with_schema(
+            &dyn_pool,
+            schema,
+            OwnedTableReference::bare(self.table_name.clone()),
+        ));
+
+        Arc::new(DeletionTableProviderAdapter::new(
+            PostgresTableWriter::create(read_provider, self),
+        ))
+    }
+
+    /// Alters the table from the `from` schema to the `to` schema, adding the new columns and changing the type of
+    /// the existing ones, and returns a `TableProvider` for the altered table.
+    pub(crate) async fn evolve_schema(
+        &self,
+        from: SchemaRef,
+        to: SchemaRef,
+    ) -> Result<Arc<dyn TableProvider>> {
+        let mut db_conn = self.connect().await?;
+        let postgres_conn = Self::postgres_conn(&mut db_conn)?;
+
+        let tx = postgres_conn
+            .conn
+            .transaction()
+            .await
+            .context(UnableToBeginTransactionSnafu)?;
+
+        for sql in AlterTableBuilder::new(&self.table_name, from, Arc::clone(&to)).build_postgres()
+        {
+            tracing::trace!("{sql}");
+            tx.execute(&sql, &[])
+                .await
+                .context(UnableToAlterPostgresTableSnafu)?;
+        }
+
+        tx.commit()
+            .await
+            .context(UnableToCommitPostgresTransactionSnafu)?;
+
+        Ok(self.clone().into_table_provider(to))
+    }
+
     async fn create_table(&self, schema: SchemaRef, transaction: &Transaction<'_>) -> Result<()> {
         let create_table_statement = CreateTableBuilder::new(schema, &self.table_name);
         let sql = create_table_statement.build_postgres();
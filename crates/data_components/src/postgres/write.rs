@@ -47,6 +47,15 @@ impl PostgresTableWriter {
             postgres: Arc::new(postgres),
         })
     }
+
+    /// Alters the table to `schema`, adding the new columns and changing the type of the existing ones, and returns
+    /// a `TableProvider` for the altered table.
+    pub async fn evolve_schema(
+        &self,
+        schema: SchemaRef,
+    ) -> Result<Arc<dyn TableProvider>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.postgres.evolve_schema(self.schema(), schema).await?)
+    }
 }
 
 #[async_trait]
@@ -15,7 +15,7 @@ limitations under the License.
 */
 
 use arrow::{array::RecordBatch, datatypes::SchemaRef};
-use arrow_sql_gen::statement::{CreateTableBuilder, InsertBuilder};
+use arrow_sql_gen::statement::{AlterTableBuilder, CreateTableBuilder, InsertBuilder};
 use async_trait::async_trait;
 use datafusion::{
     common::OwnedTableReference,
@@ -62,6 +62,9 @@ pub enum Error {
     #[snafu(display("Unable to create table in Sqlite: {source}"))]
     UnableToCreateTable { source: tokio_rusqlite::Error },
 
+    #[snafu(display("Unable to alter table in Sqlite: {source}"))]
+    UnableToAlterTable { source: tokio_rusqlite::Error },
+
     #[snafu(display("Unable to insert data into the Sqlite table: {source}"))]
     UnableToInsertIntoTable { source: rusqlite::Error },
 
@@ -143,22 +146,11 @@ impl TableProviderFactory for SqliteTableFactory {
                 .map_err(to_datafusion_error)?;
         }
 
-        let dyn_pool: Arc<DynSqliteConnectionPool> = pool;
-
-        let read_provider = Arc::new(SqlTable::new_with_schema(
-            &dyn_pool,
-            Arc::clone(&schema),
-            OwnedTableReference::bare(name.clone()),
-        ));
-
         let sqlite = Arc::into_inner(sqlite)
             .context(DanglingReferenceToSqliteSnafu)
             .map_err(to_datafusion_error)?;
 
-        let read_write_provider = SqliteTableWriter::create(read_provider, sqlite);
-
-        let delete_adapter = DeletionTableProviderAdapter::new(read_write_provider);
-        Ok(Arc::new(delete_adapter))
+        Ok(sqlite.into_table_provider())
     }
 }
 
@@ -258,6 +250,54 @@ impl Sqlite {
         Ok(count)
     }
 
+    /// Returns a `TableProvider` that reads, writes and deletes from this table.
+    fn into_table_provider(self) -> Arc<dyn TableProvider> {
+        let dyn_pool: Arc<DynSqliteConnectionPool> = Arc::clone(&self.pool);
+
+        let read_provider = Arc::new(SqlTable::new_with_schema(
+            &dyn_pool,
+            Arc::clone(&self.schema),
+            OwnedTableReference::bare(self.table_name.clone()),
+        ));
+
+        let read_write_provider = SqliteTableWriter::create(read_provider, self);
+
+        Arc::new(DeletionTableProviderAdapter::new(read_write_provider))
+    }
+
+    /// Alters the table to `schema`, adding the new columns, and returns a `TableProvider` for the altered table.
+    ///
+    /// Sqlite columns accept values of any type, so columns whose type changed are left as they are.
+    pub(crate) async fn evolve_schema(&self, schema: SchemaRef) -> Result<Arc<dyn TableProvider>> {
+        let mut db_conn = self.connect().await?;
+        let sqlite_conn = Self::sqlite_conn(&mut db_conn)?;
+
+        let statements = AlterTableBuilder::new(
+            &self.table_name,
+            Arc::clone(&self.schema),
+            Arc::clone(&schema),
+        )
+        .build_sqlite();
+        sqlite_conn
+            .conn
+            .call(move |conn| {
+                let transaction = conn.transaction()?;
+                for sql in statements {
+                    tracing::trace!("{sql}");
+                    transaction.execute(&sql, [])?;
+                }
+                transaction.commit()?;
+                Ok(())
+            })
+            .await
+            .context(UnableToAlterTableSnafu)?;
+
+        Ok(
+            Sqlite::new(self.table_name.clone(), schema, Arc::clone(&self.pool))
+                .into_table_provider(),
+        )
+    }
+
     fn create_table(&self, transaction: &Transaction<'_>) -> rusqlite::Result<()> {
         let create_table_statement =
             CreateTableBuilder::new(Arc::clone(&self.schema), &self.table_name);
@@ -49,6 +49,14 @@ impl SqliteTableWriter {
             sqlite: Arc::new(sqlite),
         })
     }
+
+    /// Alters the table to `schema`, adding the new columns, and returns a `TableProvider` for the altered table.
+    pub async fn evolve_schema(
+        &self,
+        schema: SchemaRef,
+    ) -> Result<Arc<dyn TableProvider>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.sqlite.evolve_schema(schema).await?)
+    }
 }
 
 #[async_trait]
@@ -1,8 +1,13 @@
 use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
-use std::{any::Any, sync::Arc, time::Duration};
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use arrow::array::UInt64Array;
 use arrow::datatypes::{DataType, SchemaRef};
+use arrow::record_batch::RecordBatch;
 use async_stream::stream;
 use async_trait::async_trait;
 use data_components::delete::get_deletion_provider;
@@ -10,6 +15,9 @@ use datafusion::common::OwnedTableReference;
 use datafusion::error::Result as DataFusionResult;
 use datafusion::execution::context::SessionState;
 use datafusion::logical_expr::{cast, col, lit, TableProviderFilterPushDown};
+use datafusion::physical_expr::expressions::{cast as cast_expr, Column};
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion::physical_plan::projection::ProjectionExec;
 use datafusion::physical_plan::union::UnionExec;
 use datafusion::physical_plan::{collect, ExecutionPlan, ExecutionPlanProperties};
 use datafusion::scalar::ScalarValue;
@@ -27,14 +35,17 @@ use tokio::task::JoinHandle;
 use tokio::time::interval;
 use url::Url;
 
-use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::{mpsc, OwnedRwLockReadGuard};
 use tokio_stream::wrappers::ReceiverStream;
 
+use crate::changes::{Change, ChangeFeeds, ChangeOperation};
 use crate::datafusion::{Refresh, Retention};
 use crate::execution_plan::slice::SliceExec;
 use crate::execution_plan::tee::TeeExec;
+use crate::schema_evolution::conform_data_update;
 use crate::{
+    dataaccelerator::{self, evolve_accelerator_table},
     dataconnector::{self, get_all_data},
     dataupdate::{DataUpdate, DataUpdateExecutionPlan, UpdateType},
     status,
@@ -61,6 +72,9 @@ pub enum Error {
 
     #[snafu(display("Unable to get unix timestamp: {source}"))]
     UnableToGetUnixTimestamp { source: SystemTimeError },
+
+    #[snafu(display("Unable to evolve the accelerated table schema: {source}"))]
+    UnableToEvolveAccelerator { source: dataaccelerator::Error },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -69,12 +83,60 @@ pub type Result<T> = std::result::Result<T, Error>;
 //
 // The accelerator must support inserts.
 pub(crate) struct AcceleratedTable {
-    accelerator: Arc<dyn TableProvider>,
+    engine: Arc<str>,
+    accelerator: SharedAccelerator,
     federated: Arc<dyn TableProvider>,
     refresh_trigger: Option<mpsc::Sender<()>>,
     handlers: Vec<JoinHandle<()>>,
 }
 
+// The accelerator is shared with the refresh and retention tasks, so they follow the accelerator when it is replaced by
+// a schema evolution.
+//
+// Writers to the accelerator hold a read guard of `writes` while they write, and a schema evolution holds its write
+// guard while it copies the accelerator, so no write lands in the old accelerator after it was copied.
+struct SharedAccelerator {
+    accelerator: Arc<RwLock<Arc<dyn TableProvider>>>,
+    writes: Arc<tokio::sync::RwLock<()>>,
+}
+
+impl SharedAccelerator {
+    fn new(accelerator: Arc<dyn TableProvider>) -> Self {
+        Self {
+            accelerator: Arc::new(RwLock::new(accelerator)),
+            writes: Arc::new(tokio::sync::RwLock::new(())),
+        }
+    }
+
+    async fn write_guard(&self) -> OwnedRwLockReadGuard<()> {
+        Arc::clone(&self.writes).read_owned().await
+    }
+
+    fn get(&self) -> Arc<dyn TableProvider> {
+        match self.accelerator.read() {
+            Ok(accelerator) => Arc::clone(&accelerator),
+            Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+        }
+    }
+
+    fn set(&self, accelerator: Arc<dyn TableProvider>) {
+        let mut current = match self.accelerator.write() {
+            Ok(current) => current,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *current = accelerator;
+    }
+}
+
+impl Clone for SharedAccelerator {
+    fn clone(&self) -> Self {
+        Self {
+            accelerator: Arc::clone(&self.accelerator),
+            writes: Arc::clone(&self.writes),
+        }
+    }
+}
+
 enum AccelerationRefreshMode {
     Full(Receiver<()>),
     Append,
@@ -97,11 +159,14 @@ impl AcceleratedTable {
     pub async fn new(
         dataset_name: String,
         federated: Arc<dyn TableProvider>,
+        engine: Arc<str>,
         accelerator: Arc<dyn TableProvider>,
         refresh: Refresh,
         retention: Option<Retention>,
         object_store: Option<(Url, Arc<dyn ObjectStore + 'static>)>,
+        change_feeds: Arc<ChangeFeeds>,
     ) -> Self {
+        let accelerator = SharedAccelerator::new(accelerator);
         let mut refresh_trigger = None;
         let mut scheduled_refreshes_handle: Option<JoinHandle<()>> = None;
 
@@ -122,8 +187,9 @@ impl AcceleratedTable {
             acceleration_refresh_mode,
             refresh.sql,
             refresh.period,
-            Arc::clone(&accelerator),
+            accelerator.clone(),
             object_store,
+            Arc::clone(&change_feeds),
         ));
 
         let mut handlers = vec![];
@@ -136,13 +202,15 @@ impl AcceleratedTable {
         if let Some(retention) = retention {
             let retention_check_handle = tokio::spawn(Self::start_retention_check(
                 dataset_name,
-                Arc::clone(&accelerator),
+                accelerator.clone(),
                 retention,
+                change_feeds,
             ));
             handlers.push(retention_check_handle);
         }
 
         Self {
+            engine,
             accelerator,
             federated,
             refresh_trigger,
@@ -150,6 +218,39 @@ impl AcceleratedTable {
         }
     }
 
+    pub(crate) fn get_accelerator(&self) -> Arc<dyn TableProvider> {
+        self.accelerator.get()
+    }
+
+    /// Holds off schema evolutions while the guard is alive. Take it before writing to the accelerator.
+    pub(crate) async fn write_guard(&self) -> OwnedRwLockReadGuard<()> {
+        self.accelerator.write_guard().await
+    }
+
+    pub(crate) fn federated_schema(&self) -> SchemaRef {
+        self.federated.schema()
+    }
+
+    /// Evolves the accelerator to `schema`, keeping its data. The federated table keeps its schema, so its columns
+    /// must already cover `schema`.
+    ///
+    /// Writes to the accelerator wait until the evolution is done.
+    pub(crate) async fn evolve_schema(&self, schema: SchemaRef) -> Result<()> {
+        let _guard = self.accelerator.writes.write().await;
+
+        let accelerator = self.accelerator.get();
+        if accelerator.schema() == schema {
+            return Ok(());
+        }
+
+        let evolved = evolve_accelerator_table(&self.engine, accelerator, schema)
+            .await
+            .context(UnableToEvolveAcceleratorSnafu)?;
+        self.accelerator.set(evolved);
+
+        Ok(())
+    }
+
     pub async fn trigger_refresh(&self) -> Result<()> {
         match &self.refresh_trigger {
             Some(refresh_trigger) => {
@@ -194,12 +295,13 @@ impl AcceleratedTable {
     #[allow(clippy::cast_possible_wrap)]
     async fn start_retention_check(
         dataset_name: String,
-        accelerator: Arc<dyn TableProvider>,
+        accelerator: SharedAccelerator,
         retention: Retention,
+        change_feeds: Arc<ChangeFeeds>,
     ) {
         let time_column = retention.time_column;
         let retention_period = retention.period;
-        let schema = accelerator.schema();
+        let schema = accelerator.get().schema();
         let field = schema.column_with_name(time_column.as_str());
 
         let mut interval_timer = tokio::time::interval(retention.check_interval);
@@ -212,6 +314,8 @@ impl AcceleratedTable {
         loop {
             interval_timer.tick().await;
 
+            let _write_guard = accelerator.write_guard().await;
+            let accelerator = accelerator.get();
             if let Some(deleted_table_provider) = get_deletion_provider(Arc::clone(&accelerator)) {
                 let ctx = SessionContext::new();
 
@@ -234,6 +338,18 @@ impl AcceleratedTable {
 
                 tracing::debug!("[retention] Expr {expr:?}");
 
+                let deleted_rows = if change_feeds.has_subscribers(&dataset_name) {
+                    match Self::read_matching_rows(&ctx, &accelerator, expr.clone()).await {
+                        Ok(rows) => Some(rows),
+                        Err(e) => {
+                            tracing::error!("[retention] Unable to read the rows to evict: {e}");
+                            continue;
+                        }
+                    }
+                } else {
+                    None
+                };
+
                 let plan = deleted_table_provider
                     .delete_from(&ctx.state(), &vec![expr])
                     .await;
@@ -254,6 +370,13 @@ impl AcceleratedTable {
                                 tracing::info!(
                                     "[retention] Evicted {result} records for {dataset_name}",
                                 );
+
+                                if let Some(deleted_rows) = deleted_rows {
+                                    change_feeds.publish(
+                                        &dataset_name,
+                                        Change::new(ChangeOperation::Delete, deleted_rows),
+                                    );
+                                }
                             }
                         };
                     }
@@ -267,14 +390,26 @@ impl AcceleratedTable {
         }
     }
 
+    async fn read_matching_rows(
+        ctx: &SessionContext,
+        accelerator: &Arc<dyn TableProvider>,
+        filter: Expr,
+    ) -> DataFusionResult<Vec<RecordBatch>> {
+        ctx.read_table(Arc::clone(accelerator))?
+            .filter(filter)?
+            .collect()
+            .await
+    }
+
     async fn start_refresh(
         dataset_name: String,
         federated: Arc<dyn TableProvider>,
         acceleration_refresh_mode: AccelerationRefreshMode,
         refresh_sql: Option<String>,
         refresh_period: Option<Duration>,
-        accelerator: Arc<dyn TableProvider>,
+        accelerator: SharedAccelerator,
         object_store: Option<(Url, Arc<dyn ObjectStore + 'static>)>,
+        change_feeds: Arc<ChangeFeeds>,
     ) {
         // TODO: handle this in following PR
         _ = refresh_period;
@@ -295,8 +430,21 @@ impl AcceleratedTable {
                     let Ok(data_update) = data_update else {
                         continue;
                     };
+                    let _write_guard = accelerator.write_guard().await;
+                    let accelerator = accelerator.get();
+                    let data_update = match conform_data_update(data_update, accelerator.schema()) {
+                        Ok(data_update) => data_update,
+                        Err(e) => {
+                            tracing::error!("Error adding data for {dataset_name}: {e}");
+                            continue;
+                        }
+                    };
                     let state = ctx.state();
 
+                    let change = change_feeds
+                        .has_subscribers(&dataset_name)
+                        .then(|| Change::from(data_update.clone()));
+
                     let overwrite = data_update.update_type == UpdateType::Overwrite;
                     match accelerator
                         .insert_into(
@@ -306,11 +454,16 @@ impl AcceleratedTable {
                         )
                         .await
                     {
-                        Ok(plan) => {
-                            if let Err(e) = collect(plan, ctx.task_ctx()).await {
+                        Ok(plan) => match collect(plan, ctx.task_ctx()).await {
+                            Ok(_) => {
+                                if let Some(change) = change {
+                                    change_feeds.publish(&dataset_name, change);
+                                }
+                            }
+                            Err(e) => {
                                 tracing::error!("Error adding data for {dataset_name}: {e}");
-                            };
-                        }
+                            }
+                        },
                         Err(e) => {
                             tracing::error!("Error adding data for {dataset_name}: {e}");
                         }
@@ -400,6 +553,34 @@ impl AcceleratedTable {
     }
 }
 
+/// Projects `input` onto `schema`, casting the columns widened in the accelerator back to the federated table types.
+fn project_to_schema(
+    input: Arc<dyn ExecutionPlan>,
+    schema: &SchemaRef,
+) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+    let input_schema = input.schema();
+    if input_schema.fields() == schema.fields() {
+        return Ok(input);
+    }
+
+    let exprs = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let column: Arc<dyn PhysicalExpr> = Arc::new(Column::new(
+                field.name(),
+                input_schema.index_of(field.name())?,
+            ));
+            Ok((
+                cast_expr(column, &input_schema, field.data_type().clone())?,
+                field.name().clone(),
+            ))
+        })
+        .collect::<DataFusionResult<Vec<_>>>()?;
+
+    Ok(Arc::new(ProjectionExec::try_new(exprs, input)?))
+}
+
 fn get_expr_time_format(
     field: Option<(usize, &arrow::datatypes::Field)>,
     time_format: &Option<TimeFormat>,
@@ -443,21 +624,50 @@ fn get_expr_time_format(
     Some(expr_time_format)
 }
 
-#[allow(clippy::cast_possible_wrap)]
 fn get_expr(time_column: &str, timestamp: u64, expr_time_format: ExprTimeFormat) -> Expr {
+    let (column, bound) = get_time_bound(time_column, timestamp, expr_time_format);
+    column.lt(bound)
+}
+
+/// Returns a filter that selects the rows of `schema` where `time_column` is at or after `timestamp`, in seconds since the epoch.
+///
+/// Returns `None` if the time column is missing from the schema or has a type that can't be compared to a timestamp.
+pub(crate) fn get_since_expr(
+    schema: &arrow::datatypes::Schema,
+    time_column: &str,
+    time_format: &Option<TimeFormat>,
+    timestamp: u64,
+) -> Option<Expr> {
+    let expr_time_format = get_expr_time_format(schema.column_with_name(time_column), time_format)?;
+    let (column, bound) = get_time_bound(time_column, timestamp, expr_time_format);
+    Some(column.gt_eq(bound))
+}
+
+#[allow(clippy::cast_possible_wrap)]
+fn get_time_bound(
+    time_column: &str,
+    timestamp: u64,
+    expr_time_format: ExprTimeFormat,
+) -> (Expr, Expr) {
     match expr_time_format {
-        ExprTimeFormat::ISO8601 => cast(
+        ExprTimeFormat::ISO8601 => (
+            cast(
+                col(time_column),
+                DataType::Timestamp(arrow::datatypes::TimeUnit::Millisecond, None),
+            ),
+            Expr::Literal(ScalarValue::TimestampMillisecond(
+                Some((timestamp * 1000) as i64),
+                None,
+            )),
+        ),
+        ExprTimeFormat::UnixTimestamp(format) => (col(time_column), lit(timestamp * format.scale)),
+        ExprTimeFormat::Timestamp => (
             col(time_column),
-            DataType::Timestamp(arrow::datatypes::TimeUnit::Millisecond, None),
-        )
-        .lt(Expr::Literal(ScalarValue::TimestampMillisecond(
-            Some((timestamp * 1000) as i64),
-            None,
-        ))),
-        ExprTimeFormat::UnixTimestamp(format) => col(time_column).lt(lit(timestamp * format.scale)),
-        ExprTimeFormat::Timestamp => col(time_column).lt(Expr::Literal(
-            ScalarValue::TimestampMillisecond(Some((timestamp * 1000) as i64), None),
-        )),
+            Expr::Literal(ScalarValue::TimestampMillisecond(
+                Some((timestamp * 1000) as i64),
+                None,
+            )),
+        ),
     }
 }
 
@@ -483,18 +693,18 @@ impl TableProvider for AcceleratedTable {
     }
 
     fn schema(&self) -> SchemaRef {
-        self.accelerator.schema()
+        self.accelerator.get().schema()
     }
 
     fn table_type(&self) -> TableType {
-        self.accelerator.table_type()
+        self.accelerator.get().table_type()
     }
 
     fn supports_filters_pushdown(
         &self,
         filters: &[&Expr],
     ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
-        self.accelerator.supports_filters_pushdown(filters)
+        self.accelerator.get().supports_filters_pushdown(filters)
     }
 
     async fn scan(
@@ -505,6 +715,7 @@ impl TableProvider for AcceleratedTable {
         limit: Option<usize>,
     ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
         self.accelerator
+            .get()
             .scan(state, projection, filters, limit)
             .await
     }
@@ -522,10 +733,14 @@ impl TableProvider for AcceleratedTable {
         let accelerated_input = Arc::new(SliceExec::new(Arc::clone(&tee_input), 0));
         let accelerated_insert_plan = self
             .accelerator
+            .get()
             .insert_into(state, accelerated_input, overwrite)
             .await?;
 
-        let federated_input = Arc::new(SliceExec::new(tee_input, 1));
+        let federated_input = project_to_schema(
+            Arc::new(SliceExec::new(tee_input, 1)),
+            &self.federated.schema(),
+        )?;
         let federated_insert_plan = self
             .federated
             .insert_into(state, federated_input, overwrite)
@@ -0,0 +1,561 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    collections::HashMap,
+    sync::{PoisonError, RwLock, RwLockReadGuard},
+    time::{Duration, Instant},
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion::datasource::source_as_provider;
+use datafusion::logical_expr::{
+    expr::{Exists, InSubquery},
+    Expr, LogicalPlan,
+};
+use datafusion::sql::TableReference;
+use secrets::{ExposeSecret, Secret, SecretsProvider};
+use snafu::prelude::*;
+use spicepod::component::{
+    access::{Operation, Role},
+    auth::{Auth, AuthProvider},
+};
+use uuid::Uuid;
+
+use crate::datafusion::predict::{PredictInput, PredictTable};
+
+/// How long a bearer token issued by a Flight handshake remains valid.
+const SESSION_TOKEN_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to get auth secret {secret}: {source}"))]
+    UnableToGetAuthSecret {
+        source: Box<dyn std::error::Error + Send + Sync>,
+        secret: String,
+    },
+
+    #[snafu(display("Auth secret {secret} was not found in the secret store"))]
+    AuthSecretNotFound { secret: String },
+
+    #[snafu(display(
+        "{principal} is both an API key name and a basic auth username, principals must be unique"
+    ))]
+    DuplicatePrincipal { principal: String },
+
+    #[snafu(display("{principal} is not allowed to {operation} {dataset}"))]
+    PermissionDenied {
+        principal: String,
+        operation: Operation,
+        dataset: String,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The identity of an authenticated caller: the API key name or the basic auth username.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Principal {
+    pub name: String,
+}
+
+/// Validates the credentials sent to the HTTP and Flight APIs and the datasets callers may access.
+///
+/// Accepts `Bearer <api key>`, `Bearer <session token>` and `Basic <base64 username:password>`
+/// authorization headers. Session tokens are issued by the Flight handshake.
+#[derive(Debug, Default)]
+pub struct Authenticator {
+    credentials: RwLock<Credentials>,
+    session_tokens: RwLock<HashMap<String, (Principal, Instant)>>,
+}
+
+/// The API keys, users and roles requests are checked against, replaced as a whole when they are reloaded.
+#[derive(Debug, Default)]
+struct Credentials {
+    api_keys: Vec<(String, String)>,
+    users: HashMap<String, String>,
+    roles: Vec<Role>,
+}
+
+impl Credentials {
+    /// Loads the API keys and users configured in the spicepod `auth` section from the secret store,
+    /// along with the roles of the spicepod `access` section.
+    async fn load(auth: &Auth, roles: &[Role], secrets_provider: &SecretsProvider) -> Result<Self> {
+        let mut api_keys = vec![];
+        if let Some(provider) = &auth.api_key {
+            let secret = get_auth_secret(provider, secrets_provider).await?;
+            api_keys = secret
+                .iter()
+                .map(|(name, key)| (key.expose_secret().to_string(), name.clone()))
+                .collect();
+        }
+
+        let mut users = HashMap::new();
+        if let Some(provider) = &auth.basic {
+            let secret = get_auth_secret(provider, secrets_provider).await?;
+            users = secret
+                .iter()
+                .map(|(username, password)| {
+                    (username.clone(), password.expose_secret().to_string())
+                })
+                .collect();
+        }
+
+        Self::try_new(api_keys, users, roles.to_vec())
+    }
+
+    /// Roles grant access by principal name, so an API key name and a username can't be the same principal.
+    fn try_new(
+        api_keys: Vec<(String, String)>,
+        users: HashMap<String, String>,
+        roles: Vec<Role>,
+    ) -> Result<Self> {
+        if let Some((_, name)) = api_keys.iter().find(|(_, name)| users.contains_key(name)) {
+            return DuplicatePrincipalSnafu {
+                principal: name.clone(),
+            }
+            .fail();
+        }
+
+        Ok(Self {
+            api_keys,
+            users,
+            roles,
+        })
+    }
+
+    fn has_principal(&self, name: &str) -> bool {
+        self.users.contains_key(name) || self.api_keys.iter().any(|(_, key_name)| key_name == name)
+    }
+}
+
+impl Authenticator {
+    /// Loads the API keys and users configured in the spicepod `auth` section from the secret store,
+    /// along with the roles of the spicepod `access` section.
+    pub async fn load(
+        auth: &Auth,
+        roles: &[Role],
+        secrets_provider: &SecretsProvider,
+    ) -> Result<Self> {
+        Ok(Self {
+            credentials: RwLock::new(Credentials::load(auth, roles, secrets_provider).await?),
+            ..Self::default()
+        })
+    }
+
+    /// Replaces the API keys, users and roles with the ones now configured in the spicepod. Session tokens issued to
+    /// principals that no longer exist are revoked.
+    ///
+    /// The current credentials are kept if the new ones can't be loaded.
+    pub async fn reload(
+        &self,
+        auth: &Auth,
+        roles: &[Role],
+        secrets_provider: &SecretsProvider,
+    ) -> Result<()> {
+        let credentials = Credentials::load(auth, roles, secrets_provider).await?;
+        self.replace_credentials(credentials);
+        Ok(())
+    }
+
+    fn replace_credentials(&self, credentials: Credentials) {
+        self.session_tokens
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|_, (principal, _)| credentials.has_principal(&principal.name));
+        *self
+            .credentials
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = credentials;
+    }
+
+    fn credentials(&self) -> RwLockReadGuard<'_, Credentials> {
+        self.credentials
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// An authenticator without credentials that grants access by `roles`.
+    #[cfg(test)]
+    pub(crate) fn with_roles(roles: Vec<Role>) -> Self {
+        Self {
+            credentials: RwLock::new(Credentials {
+                roles,
+                ..Credentials::default()
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the caller identified by an `authorization` header value, or `None` if the credentials are invalid.
+    #[must_use]
+    pub fn authenticate(&self, authorization: &str) -> Option<Principal> {
+        let (scheme, credentials) = authorization.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            self.authenticate_api_key(credentials)
+                .or_else(|| self.authenticate_session_token(credentials))
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = BASE64_STANDARD.decode(credentials).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            self.authenticate_user(username, password)
+        } else {
+            None
+        }
+    }
+
+    /// Issues a session token that authenticates subsequent requests as `principal`.
+    #[must_use]
+    pub fn issue_session_token(&self, principal: &Principal) -> String {
+        let token = Uuid::new_v4().to_string();
+        let now = Instant::now();
+
+        if let Ok(mut session_tokens) = self.session_tokens.write() {
+            session_tokens.retain(|_, (_, issued)| now.duration_since(*issued) < SESSION_TOKEN_TTL);
+            session_tokens.insert(token.clone(), (principal.clone(), now));
+        }
+
+        token
+    }
+
+    /// Returns true if `principal` may perform `operation` on `dataset`.
+    ///
+    /// A `dataset` of `*` is only allowed by roles that grant access to every dataset.
+    #[must_use]
+    pub fn is_allowed(&self, principal: &Principal, dataset: &str, operation: Operation) -> bool {
+        let credentials = self.credentials();
+        if credentials.roles.is_empty() {
+            return true;
+        }
+
+        credentials.roles.iter().any(|role| {
+            role.principals.contains(&principal.name)
+                && role.operations.contains(&operation)
+                && role
+                    .datasets
+                    .iter()
+                    .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(dataset))
+        })
+    }
+
+    fn authenticate_api_key(&self, key: &str) -> Option<Principal> {
+        self.credentials()
+            .api_keys
+            .iter()
+            .find(|(api_key, _)| constant_time_eq(api_key, key))
+            .map(|(_, name)| Principal { name: name.clone() })
+    }
+
+    fn authenticate_user(&self, username: &str, password: &str) -> Option<Principal> {
+        let credentials = self.credentials();
+        let expected = credentials.users.get(username)?;
+        constant_time_eq(expected, password).then(|| Principal {
+            name: username.to_string(),
+        })
+    }
+
+    fn authenticate_session_token(&self, token: &str) -> Option<Principal> {
+        let session_tokens = self.session_tokens.read().ok()?;
+        let (principal, issued) = session_tokens.get(token)?;
+        (issued.elapsed() < SESSION_TOKEN_TTL).then(|| principal.clone())
+    }
+}
+
+/// Checks that `principal` may perform `operation` on `dataset`.
+///
+/// Everything is allowed when authentication is disabled.
+pub fn authorize(
+    auth: Option<&Authenticator>,
+    principal: Option<&Principal>,
+    dataset: &str,
+    operation: Operation,
+) -> Result<()> {
+    let Some(auth) = auth else {
+        return Ok(());
+    };
+
+    match principal {
+        Some(principal) if auth.is_allowed(principal, dataset, operation) => Ok(()),
+        _ => PermissionDeniedSnafu {
+            principal: principal.map_or("anonymous", |p| p.name.as_str()),
+            operation,
+            dataset,
+        }
+        .fail(),
+    }
+}
+
+/// Checks that `principal` may read every dataset `plan` scans and write every dataset it modifies.
+///
+/// DDL, `COPY` and other statements such as `SET` create or change objects and settings other than
+/// datasets, so they require write access to every dataset.
+pub fn authorize_plan(
+    auth: Option<&Authenticator>,
+    principal: Option<&Principal>,
+    plan: &LogicalPlan,
+) -> Result<()> {
+    if auth.is_none() {
+        return Ok(());
+    }
+
+    let mut accesses = vec![];
+    collect_plan_accesses(plan, &mut accesses);
+
+    for (dataset, operation) in accesses {
+        authorize(auth, principal, &dataset, operation)?;
+    }
+
+    Ok(())
+}
+
+fn collect_plan_accesses(plan: &LogicalPlan, accesses: &mut Vec<(String, Operation)>) {
+    // A `predict` table function reads its input when it is scanned, rather than the function name it is scanned as.
+    if let LogicalPlan::TableScan(scan) = plan {
+        if let Ok(provider) = source_as_provider(&scan.source) {
+            if let Some(predict) = provider.as_any().downcast_ref::<PredictTable>() {
+                match predict.input() {
+                    PredictInput::Dataset(dataset) => {
+                        accesses.push((dataset.clone(), Operation::Read));
+                    }
+                    PredictInput::Plan(input) => collect_plan_accesses(input, accesses),
+                }
+                return;
+            }
+        }
+    }
+
+    match plan {
+        // The information schema only describes the catalog, which Flight SQL clients can list anyway.
+        LogicalPlan::TableScan(scan) if scan.table_name.schema() != Some("information_schema") => {
+            accesses.push((dataset_name(&scan.table_name), Operation::Read));
+        }
+        LogicalPlan::Dml(dml) => {
+            accesses.push((dataset_name(&dml.table_name), Operation::Write));
+        }
+        LogicalPlan::Ddl(_) | LogicalPlan::Copy(_) | LogicalPlan::Statement(_) => {
+            accesses.push(("*".to_string(), Operation::Write));
+        }
+        _ => {}
+    }
+
+    for expr in plan.expressions() {
+        // The closure never returns an error, so neither does `apply`.
+        let _ = expr.apply(&mut |expr| {
+            match expr {
+                Expr::ScalarSubquery(subquery)
+                | Expr::Exists(Exists { subquery, .. })
+                | Expr::InSubquery(InSubquery { subquery, .. }) => {
+                    collect_plan_accesses(&subquery.subquery, accesses);
+                }
+                _ => {}
+            }
+            Ok(TreeNodeRecursion::Continue)
+        });
+    }
+
+    for input in plan.inputs() {
+        collect_plan_accesses(input, accesses);
+    }
+}
+
+/// Datasets are registered in `datafusion.public`, so references to that schema use the bare dataset name.
+fn dataset_name(table_reference: &TableReference) -> String {
+    match (table_reference.catalog(), table_reference.schema()) {
+        (None | Some("datafusion"), None | Some("public")) => table_reference.table().to_string(),
+        _ => table_reference.to_string(),
+    }
+}
+
+async fn get_auth_secret(
+    provider: &AuthProvider,
+    secrets_provider: &SecretsProvider,
+) -> Result<Secret> {
+    secrets_provider
+        .get_secret(&provider.secret)
+        .await
+        .context(UnableToGetAuthSecretSnafu {
+            secret: provider.secret.clone(),
+        })?
+        .context(AuthSecretNotFoundSnafu {
+            secret: provider.secret.clone(),
+        })
+}
+
+/// Compares two credentials without short-circuiting on the first mismatched byte.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(roles: Vec<Role>) -> Credentials {
+        Credentials::try_new(
+            vec![("abc123".to_string(), "analytics".to_string())],
+            HashMap::from([("alice".to_string(), "hunter2".to_string())]),
+            roles,
+        )
+        .expect("principals are unique")
+    }
+
+    fn authenticator() -> Authenticator {
+        Authenticator {
+            credentials: RwLock::new(credentials(vec![])),
+            ..Authenticator::default()
+        }
+    }
+
+    fn principal(name: &str) -> Option<Principal> {
+        Some(Principal {
+            name: name.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let auth = authenticator();
+
+        assert_eq!(auth.authenticate("Bearer abc123"), principal("analytics"));
+        assert_eq!(auth.authenticate("Bearer abc124"), None);
+        // alice:hunter2
+        assert_eq!(
+            auth.authenticate("Basic YWxpY2U6aHVudGVyMg=="),
+            principal("alice")
+        );
+        // alice:hunter3
+        assert_eq!(auth.authenticate("Basic YWxpY2U6aHVudGVyMw=="), None);
+        assert_eq!(auth.authenticate("abc123"), None);
+    }
+
+    #[test]
+    fn test_session_token() {
+        let auth = authenticator();
+        let token = auth.issue_session_token(&Principal {
+            name: "alice".to_string(),
+        });
+
+        assert_eq!(
+            auth.authenticate(&format!("Bearer {token}")),
+            principal("alice")
+        );
+        assert_eq!(auth.authenticate("Bearer not-a-token"), None);
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let auth = authenticator();
+        let alice = Principal {
+            name: "alice".to_string(),
+        };
+        assert!(auth.is_allowed(&alice, "events", Operation::Write));
+
+        auth.replace_credentials(credentials(vec![Role {
+            name: "analysts".to_string(),
+            principals: vec!["alice".to_string()],
+            datasets: vec!["events".to_string()],
+            operations: vec![Operation::Read],
+        }]));
+        assert!(auth.is_allowed(&alice, "Events", Operation::Read));
+        assert!(!auth.is_allowed(&alice, "events", Operation::Write));
+        assert!(!auth.is_allowed(&alice, "orders", Operation::Read));
+        assert!(!auth.is_allowed(&alice, "*", Operation::Read));
+        assert!(!auth.is_allowed(
+            &Principal {
+                name: "bob".to_string()
+            },
+            "events",
+            Operation::Read
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_principal() {
+        let Err(Error::DuplicatePrincipal { principal }) = Credentials::try_new(
+            vec![("abc123".to_string(), "alice".to_string())],
+            HashMap::from([("alice".to_string(), "hunter2".to_string())]),
+            vec![],
+        ) else {
+            panic!("alice can't be both an API key and a user");
+        };
+        assert_eq!(principal, "alice");
+    }
+
+    #[test]
+    fn test_replace_credentials() {
+        let auth = authenticator();
+        let alice_token = auth.issue_session_token(&Principal {
+            name: "alice".to_string(),
+        });
+        let analytics_token = auth.issue_session_token(&Principal {
+            name: "analytics".to_string(),
+        });
+
+        auth.replace_credentials(
+            Credentials::try_new(
+                vec![("def456".to_string(), "analytics".to_string())],
+                HashMap::new(),
+                vec![],
+            )
+            .expect("principals are unique"),
+        );
+
+        assert_eq!(auth.authenticate("Bearer abc123"), None);
+        assert_eq!(auth.authenticate("Bearer def456"), principal("analytics"));
+        // alice:hunter2
+        assert_eq!(auth.authenticate("Basic YWxpY2U6aHVudGVyMg=="), None);
+        assert_eq!(auth.authenticate(&format!("Bearer {alice_token}")), None);
+        assert_eq!(
+            auth.authenticate(&format!("Bearer {analytics_token}")),
+            principal("analytics")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_statements_require_write_on_every_dataset() {
+        let ctx = datafusion::prelude::SessionContext::new();
+        let plan = ctx
+            .state()
+            .create_logical_plan("SET datafusion.execution.batch_size = 1")
+            .await
+            .expect("statement plans");
+        assert!(matches!(plan, LogicalPlan::Statement(_)));
+
+        let role = |datasets: &str, operation: Operation| Role {
+            name: "writers".to_string(),
+            principals: vec!["alice".to_string()],
+            datasets: vec![datasets.to_string()],
+            operations: vec![operation],
+        };
+        let alice = Principal {
+            name: "alice".to_string(),
+        };
+
+        let auth = Authenticator::with_roles(vec![role("events", Operation::Write)]);
+        assert!(authorize_plan(Some(&auth), Some(&alice), &plan).is_err());
+
+        let auth = Authenticator::with_roles(vec![role("*", Operation::Write)]);
+        assert!(authorize_plan(Some(&auth), Some(&alice), &plan).is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Change feeds publish the changes applied to a dataset - writes, refreshes and retention deletes - to subscribers.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use arrow::{
+    array::StringArray,
+    datatypes::{DataType, Field, Schema},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use tokio::sync::broadcast;
+
+use crate::dataupdate::{DataUpdate, UpdateType};
+
+/// The column added to the batches sent to subscribers with the [`ChangeOperation`] of each row.
+pub const OP_COLUMN: &str = "_op";
+
+/// The number of changes held for a subscriber before it starts lagging.
+const CHANGE_FEED_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOperation {
+    /// The rows were added to the dataset.
+    Insert,
+    /// The dataset was replaced by the rows.
+    Overwrite,
+    /// The rows were removed from the dataset.
+    Delete,
+}
+
+impl ChangeOperation {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOperation::Insert => "insert",
+            ChangeOperation::Overwrite => "overwrite",
+            ChangeOperation::Delete => "delete",
+        }
+    }
+}
+
+impl Display for ChangeOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub op: ChangeOperation,
+    pub data: Vec<RecordBatch>,
+    /// The position of the change in its dataset's feed, assigned when it is published. Changes published later
+    /// have higher positions.
+    pub position: u64,
+}
+
+impl Change {
+    #[must_use]
+    pub fn new(op: ChangeOperation, data: Vec<RecordBatch>) -> Self {
+        Self {
+            op,
+            data,
+            position: 0,
+        }
+    }
+}
+
+impl From<DataUpdate> for Change {
+    fn from(data_update: DataUpdate) -> Self {
+        let op = match data_update.update_type {
+            UpdateType::Append => ChangeOperation::Insert,
+            UpdateType::Overwrite => ChangeOperation::Overwrite,
+        };
+        Change::new(op, data_update.data)
+    }
+}
+
+/// Appends the [`OP_COLUMN`] to `batch`, set to `op` for every row.
+pub fn with_op_column(batch: &RecordBatch, op: ChangeOperation) -> Result<RecordBatch, ArrowError> {
+    let schema = batch.schema();
+    let mut fields = schema.fields().to_vec();
+    fields.push(Arc::new(Field::new(OP_COLUMN, DataType::Utf8, false)));
+
+    let mut columns = batch.columns().to_vec();
+    columns.push(Arc::new(StringArray::from(vec![
+        op.as_str();
+        batch.num_rows()
+    ])));
+
+    RecordBatch::try_new(
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+        columns,
+    )
+}
+
+/// The change feeds of every dataset, created when the first subscriber subscribes.
+#[derive(Debug, Default)]
+pub struct ChangeFeeds {
+    feeds: RwLock<HashMap<String, Feed>>,
+}
+
+#[derive(Debug)]
+struct Feed {
+    sender: broadcast::Sender<Change>,
+    /// The position of the last published change.
+    position: AtomicU64,
+}
+
+impl ChangeFeeds {
+    #[must_use]
+    pub fn subscribe(&self, dataset: &str) -> broadcast::Receiver<Change> {
+        if let Ok(feeds) = self.feeds.read() {
+            if let Some(feed) = feeds.get(dataset) {
+                return feed.sender.subscribe();
+            }
+        }
+
+        let mut feeds = match self.feeds.write() {
+            Ok(feeds) => feeds,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        feeds
+            .entry(dataset.to_string())
+            .or_insert_with(|| Feed {
+                sender: broadcast::channel(CHANGE_FEED_CAPACITY).0,
+                position: AtomicU64::new(0),
+            })
+            .sender
+            .subscribe()
+    }
+
+    /// The position of the last change published to `dataset`. A subscriber that reads the dataset after taking the
+    /// position has already seen the changes up to it.
+    #[must_use]
+    pub fn position(&self, dataset: &str) -> u64 {
+        self.feeds.read().map_or(0, |feeds| {
+            feeds
+                .get(dataset)
+                .map_or(0, |feed| feed.position.load(Ordering::SeqCst))
+        })
+    }
+
+    /// Whether any subscriber is following `dataset`, so changes that are expensive to capture can be skipped.
+    #[must_use]
+    pub fn has_subscribers(&self, dataset: &str) -> bool {
+        self.feeds.read().is_ok_and(|feeds| {
+            feeds
+                .get(dataset)
+                .is_some_and(|feed| feed.sender.receiver_count() > 0)
+        })
+    }
+
+    pub fn publish(&self, dataset: &str, mut change: Change) {
+        if change.data.iter().all(|batch| batch.num_rows() == 0)
+            && change.op != ChangeOperation::Overwrite
+        {
+            return;
+        }
+
+        if let Ok(feeds) = self.feeds.read() {
+            if let Some(feed) = feeds.get(dataset) {
+                change.position = feed.position.fetch_add(1, Ordering::SeqCst) + 1;
+                // Sending only fails when there are no subscribers.
+                let _ = feed.sender.send(change);
+            }
+        }
+    }
+
+    pub fn remove(&self, dataset: &str) {
+        if let Ok(mut feeds) = self.feeds.write() {
+            feeds.remove(dataset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use arrow::array::{Array, Int32Array, Int64Array};
+    use data_components::{arrow::write::MemTable, delete::DeletionTableProviderAdapter};
+    use datafusion::{datasource::TableProvider, logical_expr::col, prelude::lit};
+    use spicepod::component::dataset::{acceleration::RefreshMode, TimeFormat};
+
+    use super::*;
+    use crate::{
+        accelerated_table::AcceleratedTable,
+        datafusion::{DataFusion, Refresh, Retention},
+    };
+
+    fn events(ids: Vec<i64>, times: Vec<i64>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("ts", DataType::Int64, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(ids)),
+                Arc::new(Int64Array::from(times)),
+            ],
+        )
+        .expect("valid batch")
+    }
+
+    fn writable_table(batches: Vec<RecordBatch>) -> Arc<dyn TableProvider> {
+        let schema = events(vec![], vec![]).schema();
+        let table = MemTable::try_new(schema, vec![batches]).expect("valid table");
+        Arc::new(DeletionTableProviderAdapter::new(Arc::new(table)))
+    }
+
+    async fn next_change(rx: &mut broadcast::Receiver<Change>) -> Change {
+        tokio::time::timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .expect("a change is published")
+            .expect("the feed is open")
+    }
+
+    fn ids(change: &Change) -> Vec<i64> {
+        change
+            .data
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .expect("id column")
+                    .values()
+                    .to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_with_op_column() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1, 2]))])
+            .expect("valid batch");
+
+        let batch = with_op_column(&batch, ChangeOperation::Delete).expect("op column appended");
+
+        assert_eq!(batch.num_columns(), 2);
+        assert_eq!(batch.schema().field(1).name(), OP_COLUMN);
+        let ops = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .expect("string column");
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops.value(0), "delete");
+    }
+
+    #[test]
+    fn test_publish_positions() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1]))])
+            .expect("valid batch");
+        let feeds = ChangeFeeds::default();
+
+        // Nothing is published without a subscriber.
+        feeds.publish(
+            "t",
+            Change::new(ChangeOperation::Insert, vec![batch.clone()]),
+        );
+        assert_eq!(feeds.position("t"), 0);
+
+        let mut rx = feeds.subscribe("t");
+        feeds.publish(
+            "t",
+            Change::new(ChangeOperation::Insert, vec![batch.clone()]),
+        );
+        feeds.publish("t", Change::new(ChangeOperation::Delete, vec![batch]));
+        assert_eq!(feeds.position("t"), 2);
+
+        assert_eq!(rx.try_recv().expect("a change").position, 1);
+        assert_eq!(rx.try_recv().expect("a change").position, 2);
+    }
+
+    #[tokio::test]
+    async fn test_writes_and_deletes_publish() {
+        let mut df = DataFusion::new();
+        df.register_writable_table("t", writable_table(vec![]))
+            .expect("table registers");
+        let mut rx = df.change_feeds().subscribe("t");
+
+        let batch = events(vec![1, 2], vec![0, 0]);
+        df.write_data(
+            "t",
+            DataUpdate {
+                schema: batch.schema(),
+                data: vec![batch],
+                update_type: UpdateType::Append,
+            },
+            None,
+        )
+        .await
+        .expect("data written");
+
+        let change = next_change(&mut rx).await;
+        assert_eq!(change.op, ChangeOperation::Insert);
+        assert_eq!(ids(&change), vec![1, 2]);
+
+        df.delete_data("t", &[col("id").eq(lit(1_i64))])
+            .await
+            .expect("rows deleted");
+
+        let change = next_change(&mut rx).await;
+        assert_eq!(change.op, ChangeOperation::Delete);
+        assert_eq!(ids(&change), vec![1]);
+        assert!(change.position > 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_publishes() {
+        let feeds = Arc::new(ChangeFeeds::default());
+        let mut rx = feeds.subscribe("t");
+
+        let _table = AcceleratedTable::new(
+            "t".to_string(),
+            writable_table(vec![events(vec![1, 2, 3], vec![0, 0, 0])]),
+            "arrow".into(),
+            writable_table(vec![]),
+            Refresh::new(None, None, RefreshMode::Full, None),
+            None,
+            None,
+            Arc::clone(&feeds),
+        )
+        .await;
+
+        let change = next_change(&mut rx).await;
+        assert_eq!(change.op, ChangeOperation::Overwrite);
+        assert_eq!(ids(&change), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_retention_publishes() {
+        let feeds = Arc::new(ChangeFeeds::default());
+        let mut rx = feeds.subscribe("t");
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time after the epoch")
+            .as_secs();
+        let now = i64::try_from(now).expect("timestamp fits");
+
+        // The federated table is empty, so the append refresh publishes nothing.
+        let _table = AcceleratedTable::new(
+            "t".to_string(),
+            writable_table(vec![]),
+            "arrow".into(),
+            writable_table(vec![events(vec![1, 2], vec![0, now])]),
+            Refresh::new(None, None, RefreshMode::Append, None),
+            Some(Retention {
+                time_column: "ts".to_string(),
+                time_format: Some(TimeFormat::UnixSeconds),
+                period: Duration::from_secs(3600),
+                check_interval: Duration::from_secs(3600),
+            }),
+            None,
+            Arc::clone(&feeds),
+        )
+        .await;
+
+        let change = next_change(&mut rx).await;
+        assert_eq!(change.op, ChangeOperation::Delete);
+        assert_eq!(ids(&change), vec![1]);
+    }
+}
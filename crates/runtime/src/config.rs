@@ -14,7 +14,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf};
 
 #[derive(Debug, Clone, clap::Parser)]
 pub struct Config {
@@ -44,4 +44,42 @@ pub struct Config {
         action
     )]
     pub open_telemetry_bind_address: SocketAddr,
+
+    /// Serve the HTTP, Flight and OpenTelemetry endpoints over TLS with this PEM certificate chain.
+    #[arg(
+        long = "tls-cert",
+        value_name = "TLS_CERT",
+        requires = "tls_key_source",
+        help_heading = "TLS"
+    )]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM private key for the TLS certificate.
+    #[arg(
+        long = "tls-key",
+        value_name = "TLS_KEY",
+        group = "tls_key_source",
+        requires = "tls_cert",
+        help_heading = "TLS"
+    )]
+    pub tls_key: Option<PathBuf>,
+
+    /// Load the PEM private key for the TLS certificate from the `key` field of this secret.
+    #[arg(
+        long = "tls-key-secret",
+        value_name = "SECRET_NAME",
+        group = "tls_key_source",
+        requires = "tls_cert",
+        help_heading = "TLS"
+    )]
+    pub tls_key_secret: Option<String>,
+
+    /// Require clients to present a certificate signed by a CA in this PEM bundle (mutual TLS).
+    #[arg(
+        long = "tls-client-ca",
+        value_name = "TLS_CLIENT_CA",
+        requires = "tls_cert",
+        help_heading = "TLS"
+    )]
+    pub tls_client_ca: Option<PathBuf>,
 }
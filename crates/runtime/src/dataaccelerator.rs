@@ -60,6 +60,11 @@ pub enum Error {
     AccelerationCreationFailed {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    #[snafu(display("Acceleration schema evolution failed: {source}"))]
+    AccelerationEvolutionFailed {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -106,6 +111,16 @@ pub trait DataAccelerator: Send + Sync {
         &self,
         cmd: &CreateExternalTable,
     ) -> Result<Arc<dyn TableProvider>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Evolves a table created by this accelerator to `schema`, keeping its data, and returns a `TableProvider` for the
+    /// evolved table.
+    ///
+    /// `schema` only adds nullable columns to the table's schema or widens the type of its columns.
+    async fn evolve_table(
+        &self,
+        table: Arc<dyn TableProvider>,
+        schema: SchemaRef,
+    ) -> Result<Arc<dyn TableProvider>, Box<dyn std::error::Error + Send + Sync>>;
 }
 
 pub struct AcceleratorExternalTableBuilder {
@@ -249,3 +264,18 @@ pub async fn create_accelerator_table(
 
     Ok(table_provider)
 }
+
+pub async fn evolve_accelerator_table(
+    engine: &str,
+    table: Arc<dyn TableProvider>,
+    schema: SchemaRef,
+) -> Result<Arc<dyn TableProvider>> {
+    let accelerator = get_accelerator_engine(engine)
+        .await
+        .context(UnknownEngineSnafu { engine })?;
+
+    accelerator
+        .evolve_table(table, schema)
+        .await
+        .context(AccelerationEvolutionFailedSnafu)
+}
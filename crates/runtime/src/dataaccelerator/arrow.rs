@@ -14,8 +14,12 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use arrow::datatypes::SchemaRef;
 use async_trait::async_trait;
-use data_components::arrow::ArrowFactory;
+use data_components::{
+    arrow::{write::MemTable, ArrowFactory},
+    delete::DeletionTableProviderAdapter,
+};
 use datafusion::{
     datasource::{provider::TableProviderFactory, TableProvider},
     execution::context::SessionContext,
@@ -24,6 +28,8 @@ use datafusion::{
 use snafu::prelude::*;
 use std::{any::Any, sync::Arc};
 
+use crate::schema_evolution::conform_batch;
+
 use super::DataAccelerator;
 
 #[allow(clippy::module_name_repetitions)]
@@ -62,4 +68,25 @@ impl DataAccelerator for ArrowAccelerator {
             .await
             .boxed()
     }
+
+    /// Copies the data of `table` into a new in-memory table with `schema`.
+    async fn evolve_table(
+        &self,
+        table: Arc<dyn TableProvider>,
+        schema: SchemaRef,
+    ) -> Result<Arc<dyn TableProvider>, Box<dyn std::error::Error + Send + Sync>> {
+        let ctx = SessionContext::new();
+        let batches = ctx
+            .read_table(table)?
+            .collect()
+            .await?
+            .iter()
+            .map(|batch| conform_batch(batch, &schema))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mem_table = MemTable::try_new(schema, vec![batches])?;
+        Ok(Arc::new(DeletionTableProviderAdapter::new(Arc::new(
+            mem_table,
+        ))))
+    }
 }
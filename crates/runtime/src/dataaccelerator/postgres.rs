@@ -14,8 +14,10 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use arrow::datatypes::SchemaRef;
 use async_trait::async_trait;
-use data_components::postgres::PostgresTableProviderFactory;
+use data_components::delete::get_deletion_provider;
+use data_components::postgres::{write::PostgresTableWriter, PostgresTableProviderFactory};
 use datafusion::{
     datasource::{provider::TableProviderFactory, TableProvider},
     execution::context::SessionContext,
@@ -32,6 +34,9 @@ pub enum Error {
     UnableToCreateTable {
         source: datafusion::error::DataFusionError,
     },
+
+    #[snafu(display("The table wasn't created by the Postgres accelerator"))]
+    NotAPostgresTable {},
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -73,4 +78,18 @@ impl DataAccelerator for PostgresAccelerator {
             .context(UnableToCreateTableSnafu)
             .boxed()
     }
+
+    async fn evolve_table(
+        &self,
+        table: Arc<dyn TableProvider>,
+        schema: SchemaRef,
+    ) -> Result<Arc<dyn TableProvider>, Box<dyn std::error::Error + Send + Sync>> {
+        let deletion_provider = get_deletion_provider(table).context(NotAPostgresTableSnafu)?;
+        let writer = deletion_provider
+            .as_any()
+            .downcast_ref::<PostgresTableWriter>()
+            .context(NotAPostgresTableSnafu)?;
+
+        writer.evolve_schema(schema).await
+    }
 }
@@ -15,20 +15,27 @@ limitations under the License.
 */
 
 use std::borrow::Borrow;
-use std::collections::HashSet;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::accelerated_table::AcceleratedTable;
+use crate::changes::{Change, ChangeFeeds, ChangeOperation};
 use crate::dataaccelerator::{self, create_accelerator_table};
 use crate::dataconnector::DataConnector;
 use crate::dataupdate::{DataUpdate, DataUpdateExecutionPlan, UpdateType};
+use crate::deduplication::{self, Deduplicator, Reserved};
 use crate::get_dependent_table_names;
-use arrow::datatypes::Schema;
+use crate::schema_evolution::{self, conform_data_update, evolve_schema};
+use arrow::array::UInt64Array;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use data_components::delete::get_deletion_provider;
 use datafusion::common::OwnedTableReference;
-use datafusion::datasource::ViewTable;
+use datafusion::datasource::{TableProvider, ViewTable};
 use datafusion::error::DataFusionError;
 use datafusion::execution::context::{SessionConfig, SessionContext};
+use datafusion::logical_expr::Expr;
 use datafusion::physical_plan::collect;
 use datafusion::sql::parser::DFParser;
 use datafusion::sql::sqlparser;
@@ -36,10 +43,13 @@ use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use secrets::Secret;
 use snafu::prelude::*;
 use spicepod::component::dataset::acceleration::RefreshMode;
+use spicepod::component::dataset::schema_evolution::SchemaEvolution;
 use spicepod::component::dataset::{Dataset, Mode, TimeFormat};
 use tokio::spawn;
 use tokio::time::{sleep, Instant};
 
+pub mod explain;
+pub mod predict;
 pub mod refresh_sql;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -106,6 +116,27 @@ pub enum Error {
         source: DataFusionError,
     },
 
+    #[snafu(display("The table {table_name} does not support deletes"))]
+    DeleteNotSupported { table_name: String },
+
+    #[snafu(display("Unable to plan the table delete for {table_name}: {source}"))]
+    UnableToPlanTableDelete {
+        table_name: String,
+        source: DataFusionError,
+    },
+
+    #[snafu(display("Unable to execute the table delete for {table_name}: {source}"))]
+    UnableToExecuteTableDelete {
+        table_name: String,
+        source: DataFusionError,
+    },
+
+    #[snafu(display("Unable to read the rows to delete from {table_name}: {source}"))]
+    UnableToReadDeletedRows {
+        table_name: String,
+        source: DataFusionError,
+    },
+
     #[snafu(display("Unable to trigger refresh for {table_name}: {source}"))]
     UnableToTriggerRefresh {
         table_name: String,
@@ -114,6 +145,64 @@ pub enum Error {
 
     #[snafu(display("Table {table_name} is not accelerated"))]
     NotAcceleratedTable { table_name: String },
+
+    #[snafu(display("The data doesn't match the schema of {table_name}: {source}"))]
+    IncompatibleSchema {
+        table_name: String,
+        source: schema_evolution::Error,
+    },
+
+    #[snafu(display("Unable to evolve the schema of {table_name}: {source}"))]
+    UnableToEvolveSchema {
+        table_name: String,
+        source: crate::accelerated_table::Error,
+    },
+
+    #[snafu(display("The schema of {table_name} can only evolve when it is accelerated"))]
+    SchemaEvolutionNotSupported { table_name: String },
+
+    #[snafu(display(
+        "The schema of {table_name} can't evolve: its source doesn't have the columns {columns}"
+    ))]
+    SourceMissingColumns { table_name: String, columns: String },
+
+    #[snafu(display("Unable to write to {table_name}: {source}. Retry once it is done."))]
+    WriteInProgress {
+        table_name: String,
+        source: deduplication::Error,
+    },
+
+    #[snafu(display("Unable to deduplicate the data written to {table_name}: {source}"))]
+    UnableToDeduplicate {
+        table_name: String,
+        source: deduplication::Error,
+    },
+}
+
+/// Why a write failed, for the write endpoints to respond with a matching status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteFailure {
+    /// The data can't be written to the table.
+    InvalidData,
+    /// The table can't accept the data in its current state.
+    Conflict,
+    /// A write with the same idempotency token or deduplication keys is in progress, so the write can be retried.
+    InProgress,
+    Internal,
+}
+
+impl Error {
+    #[must_use]
+    pub fn write_failure(&self) -> WriteFailure {
+        match self {
+            Error::IncompatibleSchema { .. } => WriteFailure::InvalidData,
+            Error::SchemaEvolutionNotSupported { .. } | Error::SourceMissingColumns { .. } => {
+                WriteFailure::Conflict
+            }
+            Error::WriteInProgress { .. } => WriteFailure::InProgress,
+            _ => WriteFailure::Internal,
+        }
+    }
 }
 
 pub enum Table {
@@ -128,6 +217,31 @@ pub enum Table {
 pub struct DataFusion {
     pub ctx: Arc<SessionContext>,
     data_writers: HashSet<String>,
+    time_columns: HashMap<String, TimeColumn>,
+    schema_evolution: HashMap<String, SchemaEvolution>,
+    write_sequences: Mutex<HashMap<String, u64>>,
+    deduplicators: HashMap<String, Arc<Deduplicator>>,
+    change_feeds: Arc<ChangeFeeds>,
+}
+
+/// The outcome of a write to a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteResult {
+    /// The number of rows written, after dropping duplicates.
+    pub rows: usize,
+
+    /// The write sequence number of the table, which counts the writes committed since it was registered, starting at 1.
+    ///
+    /// The sequence is kept in memory, so it restarts when the runtime restarts or the table is re-registered. It
+    /// orders the writes acknowledged by one runtime process, and can't be used to deduplicate writes across restarts.
+    pub sequence: u64,
+}
+
+/// The column of a dataset that records when each row occurred.
+#[derive(Debug, Clone)]
+pub(crate) struct TimeColumn {
+    pub(crate) name: String,
+    pub(crate) format: Option<TimeFormat>,
 }
 
 pub(crate) struct Retention {
@@ -196,6 +310,11 @@ impl DataFusion {
         DataFusion {
             ctx: Arc::new(SessionContext::new_with_config(df_config)),
             data_writers: HashSet::new(),
+            time_columns: HashMap::new(),
+            schema_evolution: HashMap::new(),
+            write_sequences: Mutex::new(HashMap::new()),
+            deduplicators: HashMap::new(),
+            change_feeds: Arc::new(ChangeFeeds::default()),
         }
     }
 
@@ -205,6 +324,7 @@ impl DataFusion {
         table: Table,
     ) -> Result<()> {
         let dataset = dataset.borrow();
+        let mode = dataset.mode();
         match table {
             Table::Accelerated {
                 source,
@@ -217,15 +337,80 @@ impl DataFusion {
             Table::View(sql) => self.register_view(&dataset.name, sql)?,
         }
 
+        if mode == Mode::ReadWrite {
+            self.data_writers.insert(dataset.name.clone());
+        }
+
+        if let Some(time_column) = &dataset.time_column {
+            self.time_columns.insert(
+                dataset.name.clone(),
+                TimeColumn {
+                    name: time_column.clone(),
+                    format: dataset.time_format.clone(),
+                },
+            );
+        }
+
+        if let Some(schema_evolution) = &dataset.schema_evolution {
+            self.schema_evolution
+                .insert(dataset.name.clone(), schema_evolution.clone());
+        }
+
+        if let Some(deduplication) = &dataset.deduplication {
+            self.deduplicators.insert(
+                dataset.name.clone(),
+                Arc::new(Deduplicator::new(
+                    deduplication.key.clone(),
+                    dataset
+                        .deduplication_window()
+                        .unwrap_or(deduplication::DEFAULT_WINDOW),
+                )),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Registers `table` as a writable table without a data connector.
+    #[cfg(test)]
+    pub(crate) fn register_writable_table(
+        &mut self,
+        table_name: &str,
+        table: Arc<dyn TableProvider>,
+    ) -> Result<()> {
+        self.ctx
+            .register_table(table_name, table)
+            .context(UnableToRegisterTableToDataFusionSnafu)?;
+        self.data_writers.insert(table_name.to_string());
         Ok(())
     }
 
+    /// The change feeds of the registered tables, published to on writes, refreshes and retention deletes.
+    #[must_use]
+    pub fn change_feeds(&self) -> Arc<ChangeFeeds> {
+        Arc::clone(&self.change_feeds)
+    }
+
+    #[must_use]
+    pub(crate) fn time_column(&self, table_name: &str) -> Option<&TimeColumn> {
+        self.time_columns.get(table_name)
+    }
+
     #[must_use]
     pub fn is_writable(&self, table_name: &str) -> bool {
         self.data_writers.iter().any(|s| s.as_str() == table_name)
     }
 
-    pub async fn write_data(&self, table_name: &str, data_update: DataUpdate) -> Result<()> {
+    /// Writes `data_update` to a writable table in a single insert.
+    ///
+    /// If the table deduplicates writes, a write with an `idempotency_token` already written within the window is
+    /// skipped and returns the result of the earlier write, and rows whose key was already written are dropped.
+    pub async fn write_data(
+        &self,
+        table_name: &str,
+        data_update: DataUpdate,
+        idempotency_token: Option<&str>,
+    ) -> Result<WriteResult> {
         if !self.is_writable(table_name) {
             TableNotWritableSnafu {
                 table_name: table_name.to_string(),
@@ -233,13 +418,74 @@ impl DataFusion {
             .fail()?;
         }
 
+        // The reservation holds the token and the written keys as in flight until the write is committed, and
+        // releases them if the write fails.
+        let mut reservation = match self.deduplicators.get(table_name) {
+            Some(deduplicator) => match deduplicator
+                .reserve(idempotency_token)
+                .context(WriteInProgressSnafu { table_name })?
+            {
+                Reserved::Written(result) => {
+                    tracing::debug!("Skipping a repeated write to {table_name}");
+                    return Ok(result);
+                }
+                Reserved::Write(reservation) => Some(reservation),
+            },
+            None => None,
+        };
+
         let table_provider = self
             .ctx
             .table_provider(OwnedTableReference::bare(table_name.to_string()))
             .await
             .context(UnableToGetTableSnafu)?;
 
+        let table_provider = self
+            .evolve_table_schema(table_name, table_provider, &data_update.schema)
+            .await?;
+
+        // Hold off schema evolutions until the data is written, so their copy of the accelerator doesn't miss it.
+        let _write_guard = match table_provider.as_any().downcast_ref::<AcceleratedTable>() {
+            Some(accelerated_table) => Some(accelerated_table.write_guard().await),
+            None => None,
+        };
+
+        let mut data_update = conform_data_update(data_update, table_provider.schema())
+            .context(IncompatibleSchemaSnafu { table_name })?;
+
+        if let Some(reservation) = &mut reservation {
+            data_update.data = reservation.drop_duplicate_rows(&data_update.data).map_err(
+                |source| match source {
+                    deduplication::Error::KeyInFlight {} => Error::WriteInProgress {
+                        table_name: table_name.to_string(),
+                        source,
+                    },
+                    _ => Error::UnableToDeduplicate {
+                        table_name: table_name.to_string(),
+                        source,
+                    },
+                },
+            )?;
+        }
+
+        let rows = data_update.data.iter().map(RecordBatch::num_rows).sum();
         let overwrite = data_update.update_type == UpdateType::Overwrite;
+        if rows == 0 && !overwrite {
+            let result = WriteResult {
+                rows,
+                sequence: self.write_sequence(table_name, false),
+            };
+            if let Some(reservation) = reservation {
+                reservation.commit(result);
+            }
+            return Ok(result);
+        }
+
+        let change = self
+            .change_feeds
+            .has_subscribers(table_name)
+            .then(|| Change::from(data_update.clone()));
+
         let insert_plan = table_provider
             .insert_into(
                 &self.ctx.state(),
@@ -257,7 +503,163 @@ impl DataFusion {
             },
         )?;
 
-        Ok(())
+        let result = WriteResult {
+            rows,
+            sequence: self.write_sequence(table_name, true),
+        };
+
+        if let Some(reservation) = reservation {
+            reservation.commit(result);
+        }
+
+        if let Some(change) = change {
+            self.change_feeds.publish(table_name, change);
+        }
+
+        Ok(result)
+    }
+
+    /// The write sequence number of `table_name`, advanced first if a write was `committed`.
+    fn write_sequence(&self, table_name: &str, committed: bool) -> u64 {
+        let mut write_sequences = match self.write_sequences.lock() {
+            Ok(write_sequences) => write_sequences,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let sequence = write_sequences.entry(table_name.to_string()).or_default();
+        if committed {
+            *sequence += 1;
+        }
+        *sequence
+    }
+
+    /// Evolves the schema of `table_provider` to accept data with the `incoming` schema, following the dataset's
+    /// `schema_evolution` policy. Returns the table provider to write to.
+    async fn evolve_table_schema(
+        &self,
+        table_name: &str,
+        table_provider: Arc<dyn TableProvider>,
+        incoming: &Schema,
+    ) -> Result<Arc<dyn TableProvider>> {
+        let policy = self
+            .schema_evolution
+            .get(table_name)
+            .cloned()
+            .unwrap_or_default();
+        let Some(schema) = evolve_schema(&table_provider.schema(), incoming, &policy)
+            .context(IncompatibleSchemaSnafu { table_name })?
+        else {
+            return Ok(table_provider);
+        };
+
+        let Some(accelerated_table) = table_provider.as_any().downcast_ref::<AcceleratedTable>()
+        else {
+            return SchemaEvolutionNotSupportedSnafu { table_name }.fail();
+        };
+
+        // Writes go to the federated source too, so it must already have every column of the evolved schema.
+        let source_schema = accelerated_table.federated_schema();
+        let missing_columns: Vec<&str> = schema
+            .fields()
+            .iter()
+            .filter(|field| source_schema.field_with_name(field.name()).is_err())
+            .map(|field| field.name().as_str())
+            .collect();
+        if !missing_columns.is_empty() {
+            return SourceMissingColumnsSnafu {
+                table_name,
+                columns: missing_columns.join(", "),
+            }
+            .fail();
+        }
+
+        tracing::info!("Evolving the schema of {table_name}");
+        accelerated_table
+            .evolve_schema(schema)
+            .await
+            .context(UnableToEvolveSchemaSnafu { table_name })?;
+
+        Ok(table_provider)
+    }
+
+    /// Deletes the rows matching `filters` from a writable table, returning the number of rows deleted.
+    ///
+    /// Deletes from an accelerated table are applied to the accelerator.
+    pub async fn delete_data(&self, table_name: &str, filters: &[Expr]) -> Result<u64> {
+        if !self.is_writable(table_name) {
+            TableNotWritableSnafu {
+                table_name: table_name.to_string(),
+            }
+            .fail()?;
+        }
+
+        let table_provider = self
+            .ctx
+            .table_provider(OwnedTableReference::bare(table_name.to_string()))
+            .await
+            .context(UnableToGetTableSnafu)?;
+
+        let accelerated_table = table_provider.as_any().downcast_ref::<AcceleratedTable>();
+        let _write_guard = match accelerated_table {
+            Some(accelerated_table) => Some(accelerated_table.write_guard().await),
+            None => None,
+        };
+        let deletion_provider = match accelerated_table {
+            Some(accelerated_table) => get_deletion_provider(accelerated_table.get_accelerator()),
+            None => get_deletion_provider(Arc::clone(&table_provider)),
+        }
+        .context(DeleteNotSupportedSnafu {
+            table_name: table_name.to_string(),
+        })?;
+
+        let deleted_rows = if self.change_feeds.has_subscribers(table_name) {
+            Some(self.read_matching_rows(table_name, filters).await.context(
+                UnableToReadDeletedRowsSnafu {
+                    table_name: table_name.to_string(),
+                },
+            )?)
+        } else {
+            None
+        };
+
+        let delete_plan = deletion_provider
+            .delete_from(&self.ctx.state(), filters)
+            .await
+            .context(UnableToPlanTableDeleteSnafu {
+                table_name: table_name.to_string(),
+            })?;
+
+        let results = collect(delete_plan, self.ctx.task_ctx()).await.context(
+            UnableToExecuteTableDeleteSnafu {
+                table_name: table_name.to_string(),
+            },
+        )?;
+
+        if let Some(deleted_rows) = deleted_rows {
+            self.change_feeds.publish(
+                table_name,
+                Change::new(ChangeOperation::Delete, deleted_rows),
+            );
+        }
+
+        Ok(results.first().map_or(0, |batch| {
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .map_or(0, |v| v.values().first().map_or(0, |count| *count))
+        }))
+    }
+
+    async fn read_matching_rows(
+        &self,
+        table_name: &str,
+        filters: &[Expr],
+    ) -> Result<Vec<RecordBatch>, DataFusionError> {
+        let mut data_frame = self.ctx.table(table_name).await?;
+        if let Some(filter) = filters.iter().cloned().reduce(Expr::and) {
+            data_frame = data_frame.filter(filter)?;
+        }
+        data_frame.collect().await
     }
 
     pub async fn get_arrow_schema(&self, dataset: &str) -> Result<Schema> {
@@ -290,6 +692,14 @@ impl DataFusion {
             self.data_writers.remove(dataset_name);
         }
 
+        self.time_columns.remove(dataset_name);
+        self.schema_evolution.remove(dataset_name);
+        self.deduplicators.remove(dataset_name);
+        if let Ok(mut write_sequences) = self.write_sequences.lock() {
+            write_sequences.remove(dataset_name);
+        }
+        self.change_feeds.remove(dataset_name);
+
         Ok(())
     }
 
@@ -349,6 +759,7 @@ impl DataFusion {
         let accelerated_table = AcceleratedTable::new(
             dataset.name.to_string(),
             source_table_provider,
+            acceleration_settings.engine(),
             accelerated_table_provider,
             Refresh::new(
                 dataset.refresh_check_interval(),
@@ -364,6 +775,7 @@ impl DataFusion {
                 acceleration_settings.retention_check_enabled,
             ),
             obj_store,
+            Arc::clone(&self.change_feeds),
         )
         .await;
 
@@ -0,0 +1,208 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use datafusion::datasource::source_as_provider;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::display::DisplayableExecutionPlan;
+use datafusion::physical_plan::{collect, displayable, ExecutionPlan};
+use snafu::prelude::*;
+
+use crate::accelerated_table::AcceleratedTable;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Only queries can be explained"))]
+    NotAQuery {},
+
+    #[snafu(display("Unable to plan the query: {source}"))]
+    UnableToPlanQuery { source: DataFusionError },
+
+    #[snafu(display("Unable to optimize the query plan: {source}"))]
+    UnableToOptimizePlan { source: DataFusionError },
+
+    #[snafu(display("Unable to create the physical plan: {source}"))]
+    UnableToCreatePhysicalPlan { source: DataFusionError },
+
+    #[snafu(display("Unable to execute the query: {source}"))]
+    UnableToExecuteQuery { source: DataFusionError },
+}
+
+/// The execution plan nodes that indicate where the data for a query came from.
+const SOURCE_NODES: [&str; 4] = [
+    "SqlExec",
+    "FlightExec",
+    "FlightSqlExec",
+    "SparkConnectExecutionPlan",
+];
+
+/// A table that was scanned by a query, and whether it was served from an accelerator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedTable {
+    pub name: String,
+    pub accelerated: bool,
+}
+
+/// An execution plan node that reads from a data source, along with the SQL pushed down to it (if any).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceNode {
+    pub node: String,
+    pub sql: Option<String>,
+    pub details: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub logical_plan: String,
+    pub physical_plan: String,
+    pub tables: Vec<ScannedTable>,
+    pub source_nodes: Vec<SourceNode>,
+}
+
+/// Describes the logical and physical plans of the query `plan`. Other statements are refused, as planning them
+/// into a `DataFrame` would run them.
+///
+/// If `analyze` is true, the query is executed and the physical plan is annotated with the collected metrics.
+pub async fn explain(
+    ctx: &SessionContext,
+    plan: LogicalPlan,
+    analyze: bool,
+) -> Result<Explanation> {
+    ensure!(is_query(&plan), NotAQuerySnafu);
+    let data_frame = ctx
+        .execute_logical_plan(plan)
+        .await
+        .context(UnableToPlanQuerySnafu)?;
+
+    let logical_plan = data_frame
+        .clone()
+        .into_optimized_plan()
+        .context(UnableToOptimizePlanSnafu)?;
+
+    let physical_plan = data_frame
+        .create_physical_plan()
+        .await
+        .context(UnableToCreatePhysicalPlanSnafu)?;
+
+    let physical_plan_display = if analyze {
+        collect(Arc::clone(&physical_plan), ctx.task_ctx())
+            .await
+            .context(UnableToExecuteQuerySnafu)?;
+        DisplayableExecutionPlan::with_metrics(physical_plan.as_ref())
+            .indent(true)
+            .to_string()
+    } else {
+        displayable(physical_plan.as_ref()).indent(true).to_string()
+    };
+
+    let mut tables = vec![];
+    collect_scanned_tables(&logical_plan, &mut tables);
+
+    let mut source_nodes = vec![];
+    collect_source_nodes(&physical_plan, &mut source_nodes);
+
+    Ok(Explanation {
+        logical_plan: logical_plan.display_indent().to_string(),
+        physical_plan: physical_plan_display,
+        tables,
+        source_nodes,
+    })
+}
+
+/// Whether `plan` only reads data, rather than changing tables, the catalog or the session.
+fn is_query(plan: &LogicalPlan) -> bool {
+    !matches!(
+        plan,
+        LogicalPlan::Ddl(_)
+            | LogicalPlan::Dml(_)
+            | LogicalPlan::Copy(_)
+            | LogicalPlan::Statement(_)
+            | LogicalPlan::DescribeTable(_)
+            | LogicalPlan::Explain(_)
+            | LogicalPlan::Analyze(_)
+    )
+}
+
+fn collect_scanned_tables(plan: &LogicalPlan, tables: &mut Vec<ScannedTable>) {
+    if let LogicalPlan::TableScan(scan) = plan {
+        let accelerated = source_as_provider(&scan.source).is_ok_and(|provider| {
+            provider
+                .as_any()
+                .downcast_ref::<AcceleratedTable>()
+                .is_some()
+        });
+        let table = ScannedTable {
+            name: scan.table_name.to_string(),
+            accelerated,
+        };
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+
+    for input in plan.inputs() {
+        collect_scanned_tables(input, tables);
+    }
+}
+
+fn collect_source_nodes(plan: &Arc<dyn ExecutionPlan>, nodes: &mut Vec<SourceNode>) {
+    let details = displayable(plan.as_ref()).one_line().to_string();
+    let details = details.trim();
+    if let Some(node) = SOURCE_NODES.iter().find(|node| details.starts_with(*node)) {
+        nodes.push(SourceNode {
+            node: (*node).to_string(),
+            sql: pushed_down_sql(details),
+            details: details.to_string(),
+        });
+    }
+
+    for child in plan.children() {
+        collect_source_nodes(&child, nodes);
+    }
+}
+
+/// Extracts the SQL from the `sql=` annotation that `SqlExec`, `FlightExec` and `FlightSqlExec` include in their display.
+fn pushed_down_sql(details: &str) -> Option<String> {
+    details
+        .split_once("sql=")
+        .map(|(_, sql)| sql.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pushed_down_sql() {
+        assert_eq!(
+            pushed_down_sql(r#"SqlExec sql=SELECT "a", "b" FROM "t" WHERE ("a" > 1) "#),
+            Some(r#"SELECT "a", "b" FROM "t" WHERE ("a" > 1)"#.to_string())
+        );
+        assert_eq!(
+            pushed_down_sql(r#"FlightSqlExec sql=SELECT * FROM "t""#),
+            Some(r#"SELECT * FROM "t""#.to_string())
+        );
+        assert_eq!(
+            pushed_down_sql("SparkConnectExecutionPlan projection=[a] filters=[]"),
+            None
+        );
+    }
+}
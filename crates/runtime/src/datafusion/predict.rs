@@ -0,0 +1,510 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `predict` table function, which runs a loaded model within a query, i.e.
+//! `SELECT * FROM predict('my_model', (SELECT * FROM sensors WHERE device_id = 'abc'))`.
+//!
+//! Without a subquery, the model runs on the dataset it is bound to. A model that predicts a row for each input row
+//! returns the input columns followed by its predictions.
+
+use std::{any::Any, collections::HashMap, fmt, sync::Arc};
+
+use arrow::{
+    compute::concat_batches,
+    datatypes::{Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use async_trait::async_trait;
+use datafusion::{
+    common::{plan_err, DataFusionError},
+    datasource::{function::TableFunctionImpl, TableProvider, TableType},
+    error::Result,
+    execution::{context::SessionState, SendableRecordBatchStream, TaskContext},
+    logical_expr::{Expr, LogicalPlan},
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        collect, stream::RecordBatchStreamAdapter, DisplayAs, DisplayFormatType, ExecutionMode,
+        ExecutionPlan, Partitioning, PlanProperties,
+    },
+    scalar::ScalarValue,
+};
+use futures::stream;
+use tokio::sync::RwLock;
+
+use crate::{
+    model::{dataset_query, Model, ModelVersion},
+    schema_evolution::conform_batch,
+};
+
+pub const PREDICT_FUNCTION_NAME: &str = "predict";
+
+pub(crate) struct PredictFunction {
+    models: Arc<RwLock<HashMap<String, Model>>>,
+}
+
+impl PredictFunction {
+    pub(crate) fn new(models: Arc<RwLock<HashMap<String, Model>>>) -> Self {
+        Self { models }
+    }
+}
+
+impl TableFunctionImpl for PredictFunction {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let (model_name, input) = match args {
+            [Expr::Literal(ScalarValue::Utf8(Some(model_name)))] => (model_name, None),
+            [Expr::Literal(ScalarValue::Utf8(Some(model_name))), Expr::ScalarSubquery(subquery)] => {
+                (model_name, Some(subquery.subquery.as_ref().clone()))
+            }
+            _ => {
+                return plan_err!(
+                    "{PREDICT_FUNCTION_NAME} expects a model name and an optional subquery, i.e. {PREDICT_FUNCTION_NAME}('my_model', (SELECT * FROM my_dataset))"
+                )
+            }
+        };
+
+        // Table functions are planned synchronously. The models are only locked for writing while one is swapped in or
+        // out.
+        let Ok(models) = self.models.try_read() else {
+            return plan_err!("The models are being updated, retry the query");
+        };
+        let Some(model) = models.get(model_name) else {
+            return plan_err!("Model {model_name} not found");
+        };
+
+        // The version is picked when the query is planned, so the table's schema is the schema of the version it runs.
+        let version = model.route();
+        let input = match input {
+            Some(plan) => PredictInput::Plan(plan),
+            None => match version.datasets().first() {
+                Some(dataset) => PredictInput::Dataset(dataset.clone()),
+                None => {
+                    return plan_err!(
+                    "Model {model_name} is not bound to a dataset, so it needs a subquery as input"
+                )
+                }
+            },
+        };
+
+        // A model that predicts a row for each input row returns its input columns with the predictions, so the
+        // predictions can be joined back to the input.
+        let input_schema = if version.is_row_wise() {
+            match &input {
+                PredictInput::Dataset(_) => version.dataset_schema(),
+                PredictInput::Plan(plan) => Some(Arc::new(Schema::from(plan.schema().as_ref()))),
+            }
+        } else {
+            None
+        };
+
+        let output_schema = version.output_schema();
+        let schema = match &input_schema {
+            Some(input_schema) => {
+                if let Some(field) = output_schema
+                    .fields()
+                    .iter()
+                    .find(|field| input_schema.field_with_name(field.name()).is_ok())
+                {
+                    return plan_err!(
+                        "The input of model {model_name} has a column {} like its predictions, rename it",
+                        field.name()
+                    );
+                }
+                Arc::new(Schema::new(
+                    input_schema
+                        .fields()
+                        .iter()
+                        .chain(output_schema.fields().iter())
+                        .map(Arc::clone)
+                        .collect::<Vec<_>>(),
+                ))
+            }
+            None => output_schema,
+        };
+
+        Ok(Arc::new(PredictTable {
+            schema,
+            version,
+            input,
+            input_schema,
+        }))
+    }
+}
+
+/// The rows a `predict` call runs the model on.
+pub(crate) enum PredictInput {
+    /// The dataset the model is bound to.
+    Dataset(String),
+
+    /// The result of the subquery passed to `predict`.
+    Plan(LogicalPlan),
+}
+
+/// The results of running a model on its input, computed when the scan is executed.
+pub(crate) struct PredictTable {
+    version: Arc<ModelVersion>,
+    schema: SchemaRef,
+    input: PredictInput,
+    /// The input columns returned with the predictions, for a model that predicts a row for each input row.
+    input_schema: Option<SchemaRef>,
+}
+
+impl PredictTable {
+    pub(crate) fn input(&self) -> &PredictInput {
+        &self.input
+    }
+}
+
+#[async_trait]
+impl TableProvider for PredictTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let plan = match &self.input {
+            PredictInput::Dataset(dataset) => {
+                state.create_logical_plan(&dataset_query(dataset)).await?
+            }
+            PredictInput::Plan(plan) => plan.clone(),
+        };
+
+        Ok(Arc::new(PredictExec::try_new(
+            Arc::clone(&self.version),
+            state.create_physical_plan(&plan).await?,
+            self.input_schema.as_ref().map(Arc::clone),
+            self.schema(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Runs a model on the rows of its input plan once it is executed, so planning and explaining a `predict` call doesn't
+/// run the model. The model runs on the inference worker pool.
+struct PredictExec {
+    version: Arc<ModelVersion>,
+    input: Arc<dyn ExecutionPlan>,
+    input_schema: Option<SchemaRef>,
+    /// The schema of the input columns followed by the predictions, before the projection.
+    schema: SchemaRef,
+    projection: Option<Vec<usize>>,
+    properties: PlanProperties,
+}
+
+impl PredictExec {
+    fn try_new(
+        version: Arc<ModelVersion>,
+        input: Arc<dyn ExecutionPlan>,
+        input_schema: Option<SchemaRef>,
+        schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        let projected_schema = match &projection {
+            Some(projection) => Arc::new(schema.project(projection)?),
+            None => Arc::clone(&schema),
+        };
+        Ok(Self {
+            version,
+            input,
+            input_schema,
+            schema,
+            projection,
+            properties: PlanProperties::new(
+                EquivalenceProperties::new(projected_schema),
+                Partitioning::UnknownPartitioning(1),
+                ExecutionMode::Bounded,
+            ),
+        })
+    }
+}
+
+impl fmt::Debug for PredictExec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PredictExec version: {}", self.version.name)
+    }
+}
+
+impl DisplayAs for PredictExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PredictExec version: {}", self.version.name)
+    }
+}
+
+impl ExecutionPlan for PredictExec {
+    fn name(&self) -> &'static str {
+        "PredictExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![Arc::clone(&self.input)]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.as_slice() {
+            [input] => Ok(Arc::new(PredictExec::try_new(
+                Arc::clone(&self.version),
+                Arc::clone(input),
+                self.input_schema.as_ref().map(Arc::clone),
+                Arc::clone(&self.schema),
+                self.projection.clone(),
+            )?)),
+            _ => Err(DataFusionError::Execution(
+                "PredictExec expects exactly one input".to_string(),
+            )),
+        }
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        if partition > 0 {
+            return Err(DataFusionError::Execution(format!(
+                "PredictExec only supports 1 partition, but partition {partition} was requested",
+            )));
+        }
+
+        let version = Arc::clone(&self.version);
+        let input = Arc::clone(&self.input);
+        let input_schema = self.input_schema.as_ref().map(Arc::clone);
+        let schema = Arc::clone(&self.schema);
+        let projection = self.projection.clone();
+
+        let predictions = stream::once(async move {
+            let input_rows = input.schema();
+            let data = collect(input, context).await?;
+            let output = version
+                .infer(data.clone())
+                .await
+                .output
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+            let mut columns = match input_schema {
+                Some(input_schema) => {
+                    let input = conform_batch(&concat_batches(&input_rows, &data)?, &input_schema)
+                        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                    if input.num_rows() != output.num_rows() {
+                        return Err(DataFusionError::Execution(format!(
+                            "The model returned {} predictions for {} input rows",
+                            output.num_rows(),
+                            input.num_rows()
+                        )));
+                    }
+                    input.columns().to_vec()
+                }
+                None => vec![],
+            };
+            columns.extend(output.columns().iter().map(Arc::clone));
+
+            let batch = RecordBatch::try_new(schema, columns)?;
+            match projection {
+                Some(projection) => Ok(batch.project(&projection)?),
+                None => Ok(batch),
+            }
+        });
+
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            predictions,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{AsArray, Float64Array, Int64Array},
+        compute::kernels::numeric::mul,
+        datatypes::{DataType, Field, Float64Type},
+    };
+    use datafusion::{datasource::MemTable, prelude::SessionContext};
+
+    use super::*;
+    use crate::modelruntime::{self, Runnable};
+
+    /// Predicts `y` as twice the `x` of each input row.
+    struct Double;
+
+    impl Runnable for Double {
+        fn run(&self, input: Vec<RecordBatch>) -> Result<RecordBatch, modelruntime::Error> {
+            let x: Vec<_> = input
+                .iter()
+                .filter_map(|batch| batch.column_by_name("x"))
+                .map(|x| mul(x, &Float64Array::new_scalar(2.0)))
+                .collect::<Result<_, _>>()?;
+            let x: Vec<&dyn arrow::array::Array> = x.iter().map(AsRef::as_ref).collect();
+            let y = arrow::compute::concat(&x)?;
+            Ok(RecordBatch::try_new(self.output_schema(), vec![y])?)
+        }
+
+        fn output_schema(&self) -> SchemaRef {
+            Arc::new(Schema::new(vec![Field::new("y", DataType::Float64, false)]))
+        }
+
+        fn is_row_wise(&self) -> bool {
+            true
+        }
+    }
+
+    fn sensors_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("ts", DataType::Int64, false),
+            Field::new("x", DataType::Float64, false),
+        ]))
+    }
+
+    fn model(name: &str, datasets: &[&str]) -> Model {
+        let model = serde_json::from_value(serde_json::json!({
+            "from": format!("file:/models/{name}.onnx"),
+            "name": name,
+            "datasets": datasets,
+        }))
+        .expect("valid model");
+        let dataset_schema = (!datasets.is_empty()).then(sensors_schema);
+        Model::for_tests(model, Box::new(Double), dataset_schema)
+    }
+
+    fn context() -> SessionContext {
+        let batch = RecordBatch::try_new(
+            sensors_schema(),
+            vec![
+                Arc::new(Int64Array::from(vec![3, 1, 2])),
+                Arc::new(Float64Array::from(vec![3.0, 1.0, 2.0])),
+            ],
+        )
+        .expect("valid batch");
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "sensors",
+            Arc::new(MemTable::try_new(sensors_schema(), vec![vec![batch]]).expect("valid table")),
+        )
+        .expect("table registers");
+
+        let models = HashMap::from([
+            ("m".to_string(), model("m", &["sensors"])),
+            ("unbound".to_string(), model("unbound", &[])),
+        ]);
+        ctx.register_udtf(
+            PREDICT_FUNCTION_NAME,
+            Arc::new(PredictFunction::new(Arc::new(RwLock::new(models)))),
+        );
+        ctx
+    }
+
+    async fn query(ctx: &SessionContext, sql: &str) -> Result<RecordBatch> {
+        let batches = ctx.sql(sql).await?.collect().await?;
+        let schema = batches
+            .first()
+            .map_or_else(|| Arc::new(Schema::empty()), RecordBatch::schema);
+        Ok(concat_batches(&schema, &batches)?)
+    }
+
+    fn float_column(batch: &RecordBatch, name: &str) -> Vec<f64> {
+        batch
+            .column_by_name(name)
+            .expect("column exists")
+            .as_primitive::<Float64Type>()
+            .values()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_predict_on_bound_dataset() {
+        let ctx = context();
+        let batch = query(&ctx, "SELECT * FROM predict('m')")
+            .await
+            .expect("predictions");
+
+        let schema = batch.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["ts", "x", "y"]);
+        // The dataset is read ordered by `ts`.
+        assert_eq!(float_column(&batch, "x"), vec![1.0, 2.0, 3.0]);
+        assert_eq!(float_column(&batch, "y"), vec![2.0, 4.0, 6.0]);
+    }
+
+    #[tokio::test]
+    async fn test_predict_on_subquery() {
+        let ctx = context();
+        let batch = query(
+            &ctx,
+            "SELECT x, y FROM predict('m', (SELECT x FROM sensors WHERE ts > 1 ORDER BY ts)) ORDER BY x",
+        )
+        .await
+        .expect("predictions");
+
+        assert_eq!(float_column(&batch, "x"), vec![2.0, 3.0]);
+        assert_eq!(float_column(&batch, "y"), vec![4.0, 6.0]);
+
+        // A model that isn't bound to a dataset runs on its subquery.
+        let batch = query(
+            &ctx,
+            "SELECT y FROM predict('unbound', (SELECT x FROM sensors))",
+        )
+        .await
+        .expect("predictions");
+        assert_eq!(batch.num_rows(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_predict_errors() {
+        let ctx = context();
+        for (sql, message) in [
+            (
+                "SELECT * FROM predict('missing')",
+                "Model missing not found",
+            ),
+            (
+                "SELECT * FROM predict('m', (SELECT x, x AS y FROM sensors))",
+                "has a column y like its predictions",
+            ),
+            (
+                "SELECT * FROM predict('unbound')",
+                "is not bound to a dataset",
+            ),
+        ] {
+            let Err(e) = query(&ctx, sql).await else {
+                panic!("expected {sql} to fail");
+            };
+            assert!(e.to_string().contains(message), "{sql}: {e}");
+        }
+    }
+}
@@ -0,0 +1,486 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Drops the writes to a dataset that repeat a recent write, by idempotency token or by key columns.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use arrow::{
+    array::{ArrayRef, BooleanArray},
+    compute::filter_record_batch,
+    datatypes::DataType,
+    error::ArrowError,
+    record_batch::RecordBatch,
+    row::{OwnedRow, RowConverter, SortField},
+};
+use snafu::prelude::*;
+
+use crate::datafusion::WriteResult;
+
+/// The request header carrying the idempotency token of a write.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// How long tokens and keys are remembered when the dataset doesn't set a window.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The number of tokens or keys remembered, after which the oldest are forgotten before their window expires.
+const MAX_WINDOW_ENTRIES: usize = 1_000_000;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("The deduplication key column \"{column}\" is missing from the data"))]
+    MissingKeyColumn { column: String },
+
+    #[snafu(display("Unable to compare the deduplication keys: {source}"))]
+    UnableToCompareKeys { source: ArrowError },
+
+    #[snafu(display("A write with the idempotency token {token} is in progress"))]
+    TokenInFlight { token: String },
+
+    #[snafu(display("A write of rows with the same deduplication key is in progress"))]
+    KeyInFlight {},
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Remembers the idempotency tokens and row keys written to a dataset within a window.
+pub struct Deduplicator {
+    key: Vec<String>,
+    window: Duration,
+    tokens: Mutex<Window<String, Entry<WriteResult>>>,
+    keys: Mutex<KeyWindow>,
+}
+
+/// A token or key that is being written, or was written within the window.
+#[derive(Clone, Copy)]
+enum Entry<V> {
+    InFlight,
+    Written(V),
+}
+
+struct KeyWindow {
+    // The key values are compared in the row format, which depends on the types of the key columns.
+    key_types: Vec<DataType>,
+    converter: Option<Arc<RowConverter>>,
+    seen: Window<OwnedRow, Entry<()>>,
+}
+
+/// The outcome of [`Deduplicator::reserve`].
+pub enum Reserved<'a> {
+    /// The token was written within the window, with this result.
+    Written(WriteResult),
+    /// The write can go ahead.
+    Write(Reservation<'a>),
+}
+
+/// A write reserved with [`Deduplicator::reserve`]. Its token and keys are held as in flight, so concurrent writes
+/// repeating them fail, until the write is committed with [`Reservation::commit`]. Dropping the reservation without
+/// committing it releases them, so a failed write can be retried.
+pub struct Reservation<'a> {
+    deduplicator: &'a Deduplicator,
+    token: Option<String>,
+    key_types: Vec<DataType>,
+    keys: Vec<OwnedRow>,
+    committed: bool,
+}
+
+impl Deduplicator {
+    #[must_use]
+    pub fn new(key: Vec<String>, window: Duration) -> Self {
+        Self {
+            key,
+            window,
+            tokens: Mutex::new(Window::default()),
+            keys: Mutex::new(KeyWindow {
+                key_types: vec![],
+                converter: None,
+                seen: Window::default(),
+            }),
+        }
+    }
+
+    /// Reserves a write with the idempotency `token`, returning the result of the earlier write if the token was
+    /// written within the window.
+    pub fn reserve(&self, token: Option<&str>) -> Result<Reserved<'_>> {
+        if let Some(token) = token {
+            let mut tokens = lock(&self.tokens);
+            tokens.evict(self.window);
+            match tokens.get(token).copied() {
+                Some(Entry::Written(result)) => return Ok(Reserved::Written(result)),
+                Some(Entry::InFlight) => {
+                    return TokenInFlightSnafu { token }.fail();
+                }
+                None => tokens.insert(token.to_string(), Entry::InFlight),
+            }
+        }
+
+        Ok(Reserved::Write(Reservation {
+            deduplicator: self,
+            token: token.map(ToString::to_string),
+            key_types: vec![],
+            keys: vec![],
+            committed: false,
+        }))
+    }
+
+    fn commit(&self, reservation: &Reservation<'_>, result: WriteResult) {
+        if let Some(token) = &reservation.token {
+            lock(&self.tokens).insert(token.clone(), Entry::Written(result));
+        }
+
+        if reservation.keys.is_empty() {
+            return;
+        }
+        let mut keys = lock(&self.keys);
+        // Keys encoded for other key types can't be compared, which only happens if the schema evolved between
+        // dropping the duplicates and the write.
+        if keys.key_types != reservation.key_types {
+            return;
+        }
+        for key in &reservation.keys {
+            keys.seen.insert(key.clone(), Entry::Written(()));
+        }
+    }
+
+    fn release(&self, reservation: &Reservation<'_>) {
+        if let Some(token) = &reservation.token {
+            lock(&self.tokens).remove(token);
+        }
+
+        if reservation.keys.is_empty() {
+            return;
+        }
+        let mut keys = lock(&self.keys);
+        if keys.key_types != reservation.key_types {
+            return;
+        }
+        for key in &reservation.keys {
+            keys.seen.remove(key);
+        }
+    }
+}
+
+impl Reservation<'_> {
+    /// Drops the rows whose key was written within the window, or repeats a key earlier in `batches`, and reserves
+    /// the keys of the remaining rows.
+    ///
+    /// Fails if a row's key is reserved by a write in progress.
+    pub fn drop_duplicate_rows(&mut self, batches: &[RecordBatch]) -> Result<Vec<RecordBatch>> {
+        let deduplicator = self.deduplicator;
+        if deduplicator.key.is_empty() {
+            return Ok(batches.to_vec());
+        }
+
+        let mut keys = lock(&deduplicator.keys);
+        keys.seen.evict(deduplicator.window);
+
+        let mut key_types = vec![];
+        let mut batch_keys = HashSet::new();
+        let mut kept_keys = vec![];
+        let mut kept_batches = Vec::with_capacity(batches.len());
+
+        for batch in batches {
+            let columns = deduplicator
+                .key
+                .iter()
+                .map(|column| {
+                    batch
+                        .column_by_name(column)
+                        .map(Arc::clone)
+                        .context(MissingKeyColumnSnafu { column })
+                })
+                .collect::<Result<Vec<ArrayRef>>>()?;
+
+            key_types = columns
+                .iter()
+                .map(|column| column.data_type().clone())
+                .collect();
+            let converter = keys.converter(&key_types)?;
+
+            let rows = converter
+                .convert_columns(&columns)
+                .context(UnableToCompareKeysSnafu)?;
+
+            let mut keep = Vec::with_capacity(rows.num_rows());
+            for row in &rows {
+                let row = row.owned();
+                let duplicate = match keys.seen.get(&row) {
+                    Some(Entry::InFlight) => return KeyInFlightSnafu.fail(),
+                    Some(Entry::Written(())) => true,
+                    None => !batch_keys.insert(row.clone()),
+                };
+                if !duplicate {
+                    kept_keys.push(row);
+                }
+                keep.push(!duplicate);
+            }
+
+            let dropped = keep.iter().filter(|keep| !**keep).count();
+            if dropped == 0 {
+                kept_batches.push(batch.clone());
+                continue;
+            }
+
+            tracing::debug!("Dropping {dropped} duplicate rows");
+            kept_batches.push(
+                filter_record_batch(batch, &BooleanArray::from(keep))
+                    .context(UnableToCompareKeysSnafu)?,
+            );
+        }
+
+        for key in &kept_keys {
+            keys.seen.insert(key.clone(), Entry::InFlight);
+        }
+        self.key_types = key_types;
+        self.keys = kept_keys;
+
+        Ok(kept_batches)
+    }
+
+    /// Remembers the successful write, so it is dropped if it is repeated within the window.
+    pub fn commit(mut self, result: WriteResult) {
+        self.deduplicator.commit(&self, result);
+        self.committed = true;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.deduplicator.release(self);
+        }
+    }
+}
+
+impl KeyWindow {
+    fn converter(&mut self, key_types: &[DataType]) -> Result<Arc<RowConverter>> {
+        if let Some(converter) = &self.converter {
+            if self.key_types == key_types {
+                return Ok(Arc::clone(converter));
+            }
+            // The key columns were widened by a schema evolution, so the remembered keys no longer compare.
+            tracing::warn!("The deduplication key types changed, forgetting the written keys");
+            self.seen = Window::default();
+        }
+
+        let converter = Arc::new(
+            RowConverter::new(
+                key_types
+                    .iter()
+                    .map(|data_type| SortField::new(data_type.clone()))
+                    .collect(),
+            )
+            .context(UnableToCompareKeysSnafu)?,
+        );
+        self.key_types = key_types.to_vec();
+        self.converter = Some(Arc::clone(&converter));
+        Ok(converter)
+    }
+}
+
+/// Entries that expire once they are older than the window, evicted in the order they were inserted.
+struct Window<K, V> {
+    entries: HashMap<K, (Instant, V)>,
+    insertions: VecDeque<(Instant, K)>,
+    max_entries: usize,
+}
+
+impl<K, V> Default for Window<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            insertions: VecDeque::new(),
+            max_entries: MAX_WINDOW_ENTRIES,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Window<K, V> {
+    fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.get(key).map(|(_, value)| value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        let now = Instant::now();
+        self.insertions.push_back((now, key.clone()));
+        self.entries.insert(key, (now, value));
+
+        while self.entries.len() > self.max_entries {
+            let Some((inserted, key)) = self.insertions.pop_front() else {
+                break;
+            };
+            self.remove_insertion(&key, inserted);
+        }
+    }
+
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.remove(key);
+    }
+
+    fn evict(&mut self, window: Duration) {
+        let now = Instant::now();
+        while let Some((inserted, _)) = self.insertions.front() {
+            if now.duration_since(*inserted) < window {
+                break;
+            }
+            let Some((inserted, key)) = self.insertions.pop_front() else {
+                break;
+            };
+            self.remove_insertion(&key, inserted);
+        }
+    }
+
+    fn remove_insertion(&mut self, key: &K, inserted: Instant) {
+        // A key inserted again since is kept until its latest insertion is removed.
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|(latest, _)| *latest == inserted)
+        {
+            self.entries.remove(key);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::{Field, Schema};
+
+    fn batch(ids: Vec<i64>, values: Vec<&str>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("value", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(ids)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )
+        .expect("valid batch")
+    }
+
+    fn reserve<'a>(dedup: &'a Deduplicator, token: Option<&str>) -> Reservation<'a> {
+        match dedup.reserve(token).expect("reserved") {
+            Reserved::Write(reservation) => reservation,
+            Reserved::Written(_) => panic!("the token was already written"),
+        }
+    }
+
+    #[test]
+    fn test_drop_duplicate_rows() {
+        let dedup = Deduplicator::new(vec!["id".to_string()], DEFAULT_WINDOW);
+        let result = WriteResult {
+            rows: 2,
+            sequence: 1,
+        };
+
+        let mut first = reserve(&dedup, Some("token"));
+        let kept = first
+            .drop_duplicate_rows(&[batch(vec![1, 2, 1], vec!["a", "b", "c"])])
+            .expect("rows deduplicated");
+        assert_eq!(kept[0].num_rows(), 2);
+
+        // The token and keys are in flight until the write is committed.
+        assert!(matches!(
+            dedup.reserve(Some("token")),
+            Err(Error::TokenInFlight { .. })
+        ));
+        assert!(matches!(
+            reserve(&dedup, None).drop_duplicate_rows(&[batch(vec![1], vec!["a"])]),
+            Err(Error::KeyInFlight {})
+        ));
+
+        first.commit(result);
+        let kept = reserve(&dedup, None)
+            .drop_duplicate_rows(&[batch(vec![2, 3], vec!["b", "c"])])
+            .expect("rows deduplicated");
+        assert_eq!(kept[0].num_rows(), 1);
+        assert!(matches!(
+            dedup.reserve(Some("token")),
+            Ok(Reserved::Written(WriteResult { sequence: 1, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_release_failed_write() {
+        let dedup = Deduplicator::new(vec!["id".to_string()], DEFAULT_WINDOW);
+
+        let mut failed = reserve(&dedup, Some("token"));
+        failed
+            .drop_duplicate_rows(&[batch(vec![1], vec!["a"])])
+            .expect("rows deduplicated");
+        drop(failed);
+
+        // A write that wasn't committed can be retried.
+        let kept = reserve(&dedup, Some("token"))
+            .drop_duplicate_rows(&[batch(vec![1], vec!["a"])])
+            .expect("rows deduplicated");
+        assert_eq!(kept[0].num_rows(), 1);
+    }
+
+    #[test]
+    fn test_window_expiry() {
+        let dedup = Deduplicator::new(vec![], Duration::ZERO);
+        reserve(&dedup, Some("token")).commit(WriteResult {
+            rows: 1,
+            sequence: 1,
+        });
+        assert!(matches!(
+            dedup.reserve(Some("token")),
+            Ok(Reserved::Write(_))
+        ));
+    }
+
+    #[test]
+    fn test_window_size_cap() {
+        let mut window = Window {
+            max_entries: 2,
+            ..Window::default()
+        };
+        window.insert("a", ());
+        window.insert("b", ());
+        window.insert("a", ());
+        window.insert("c", ());
+
+        assert!(window.get("a").is_some());
+        assert!(window.get("b").is_none());
+        assert!(window.get("c").is_some());
+    }
+}
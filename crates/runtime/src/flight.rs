@@ -14,9 +14,10 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::datafusion::DataFusion;
-use crate::dataupdate::DataUpdate;
+use crate::auth::{self, Authenticator, Principal};
+use crate::datafusion::{DataFusion, WriteFailure};
 use crate::measure_scope_ms;
+use crate::tls::TlsAcceptor;
 use arrow::array::RecordBatch;
 use arrow::datatypes::Schema;
 use arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator};
@@ -24,15 +25,18 @@ use arrow_flight::encode::FlightDataEncoderBuilder;
 use arrow_flight::{Action, ActionType, Criteria, IpcMessage, PollInfo, SchemaResult};
 use arrow_ipc::writer::IpcWriteOptions;
 use bytes::Bytes;
+use datafusion::dataframe::DataFrame;
 use datafusion::error::DataFusionError;
 use datafusion::execution::SendableRecordBatchStream;
+use datafusion::logical_expr::LogicalPlan;
 use datafusion::sql::sqlparser::parser::ParserError;
 use futures::stream::{self, BoxStream, StreamExt};
 use futures::{Stream, TryStreamExt};
 use snafu::prelude::*;
+use spicepod::component::access::Operation;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::broadcast::Sender;
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status, Streaming};
@@ -43,7 +47,11 @@ mod do_get;
 mod do_put;
 mod flightsql;
 mod get_flight_info;
+mod get_schema;
 mod handshake;
+mod list_flights;
+
+use flightsql::prepared_statement_query::PreparedStatement;
 
 use arrow_flight::{
     flight_service_server::{FlightService, FlightServiceServer},
@@ -53,7 +61,8 @@ use arrow_flight::{
 
 pub struct Service {
     datafusion: Arc<RwLock<DataFusion>>,
-    channel_map: Arc<RwLock<HashMap<String, Arc<Sender<DataUpdate>>>>>,
+    prepared_statements: Arc<RwLock<HashMap<String, PreparedStatement>>>,
+    auth: Option<Arc<Authenticator>>,
 }
 
 #[tonic::async_trait]
@@ -68,19 +77,18 @@ impl FlightService for Service {
 
     async fn handshake(
         &self,
-        _request: Request<Streaming<HandshakeRequest>>,
+        request: Request<Streaming<HandshakeRequest>>,
     ) -> Result<Response<Self::HandshakeStream>, Status> {
         metrics::counter!("flight_handshake_requests").increment(1);
-        handshake::handle()
+        handshake::handle(self.auth.as_deref(), &request)
     }
 
     async fn list_flights(
         &self,
-        _request: Request<Criteria>,
+        request: Request<Criteria>,
     ) -> Result<Response<Self::ListFlightsStream>, Status> {
         metrics::counter!("flight_list_flights_requests").increment(1);
-        tracing::trace!("list_flights - unimplemented");
-        Err(Status::unimplemented("Not yet implemented"))
+        Box::pin(list_flights::handle(self, request)).await
     }
 
     async fn get_flight_info(
@@ -94,18 +102,20 @@ impl FlightService for Service {
 
     async fn poll_flight_info(
         &self,
-        _request: Request<FlightDescriptor>,
+        request: Request<FlightDescriptor>,
     ) -> Result<Response<PollInfo>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        measure_scope_ms!("flight_poll_flight_info_request_duration_ms");
+        metrics::counter!("flight_poll_flight_info_requests").increment(1);
+        Box::pin(get_flight_info::poll(self, request)).await
     }
 
     async fn get_schema(
         &self,
-        _request: Request<FlightDescriptor>,
+        request: Request<FlightDescriptor>,
     ) -> Result<Response<SchemaResult>, Status> {
+        measure_scope_ms!("flight_get_schema_request_duration_ms");
         metrics::counter!("flight_get_schema_requests").increment(1);
-        tracing::trace!("get_schema - unimplemented");
-        Err(Status::unimplemented("Not yet implemented"))
+        Box::pin(get_schema::handle(self, request)).await
     }
 
     async fn do_get(
@@ -150,18 +160,51 @@ impl FlightService for Service {
 }
 
 impl Service {
+    #[cfg(test)]
+    pub(crate) fn for_tests(df: DataFusion) -> Self {
+        Self {
+            datafusion: Arc::new(RwLock::new(df)),
+            prepared_statements: Arc::new(RwLock::new(HashMap::new())),
+            auth: None,
+        }
+    }
+
+    /// The schema of the result of `sql`, if the caller may access every dataset it reads.
     async fn get_arrow_schema(
-        datafusion: Arc<RwLock<DataFusion>>,
+        &self,
+        principal: Option<&Principal>,
         sql: String,
     ) -> Result<Schema, Status> {
-        let df = datafusion
-            .read()
-            .await
-            .ctx
-            .sql(&sql)
+        // Only plan the query: `SessionContext::sql` would also execute DDL statements.
+        let ctx = Arc::clone(&self.datafusion.read().await.ctx);
+        let plan = ctx
+            .state()
+            .create_logical_plan(&sql)
             .await
             .map_err(handle_datafusion_error)?;
-        Ok(df.schema().into())
+        self.authorize_plan(principal, &plan)?;
+        Ok(plan.schema().as_ref().into())
+    }
+
+    /// Checks that the caller may perform `operation` on `dataset`.
+    fn authorize(
+        &self,
+        principal: Option<&Principal>,
+        dataset: &str,
+        operation: Operation,
+    ) -> Result<(), Status> {
+        auth::authorize(self.auth.as_deref(), principal, dataset, operation)
+            .map_err(|e| Status::permission_denied(e.to_string()))
+    }
+
+    /// Checks that the caller may access every dataset that `plan` reads or writes.
+    fn authorize_plan(
+        &self,
+        principal: Option<&Principal>,
+        plan: &LogicalPlan,
+    ) -> Result<(), Status> {
+        auth::authorize_plan(self.auth.as_deref(), principal, plan)
+            .map_err(|e| Status::permission_denied(e.to_string()))
     }
 
     fn serialize_schema(schema: &Schema) -> Result<Bytes, Status> {
@@ -174,23 +217,40 @@ impl Service {
     }
 
     async fn sql_to_flight_stream(
-        datafusion: Arc<RwLock<DataFusion>>,
+        &self,
+        principal: Option<&Principal>,
         sql: String,
     ) -> Result<BoxStream<'static, Result<FlightData, Status>>, Status> {
-        let df = datafusion
-            .read()
+        let ctx = Arc::clone(&self.datafusion.read().await.ctx);
+        let plan = ctx
+            .state()
+            .create_logical_plan(&sql)
             .await
-            .ctx
-            .sql(&sql)
+            .map_err(handle_datafusion_error)?;
+        self.authorize_plan(principal, &plan)?;
+
+        let df = ctx
+            .execute_logical_plan(plan)
             .await
             .map_err(handle_datafusion_error)?;
-        let schema = df.schema().clone().into();
-        let options = datafusion::arrow::ipc::writer::IpcWriteOptions::default();
-        let schema_as_ipc = SchemaAsIpc::new(&schema, &options);
-        let schema_flight_data = FlightData::from(schema_as_ipc);
+        Self::dataframe_to_flight_stream(df).await
+    }
 
+    async fn dataframe_to_flight_stream(
+        df: DataFrame,
+    ) -> Result<BoxStream<'static, Result<FlightData, Status>>, Status> {
         let batches_stream: SendableRecordBatchStream =
             df.execute_stream().await.map_err(to_tonic_err)?;
+        Ok(Self::batch_stream_to_flight_stream(batches_stream))
+    }
+
+    /// Encodes a stream of record batches as Flight data, starting with its schema.
+    fn batch_stream_to_flight_stream(
+        batches_stream: SendableRecordBatchStream,
+    ) -> BoxStream<'static, Result<FlightData, Status>> {
+        let options = datafusion::arrow::ipc::writer::IpcWriteOptions::default();
+        let schema_as_ipc = SchemaAsIpc::new(&batches_stream.schema(), &options);
+        let schema_flight_data = FlightData::from(schema_as_ipc);
 
         let batches_stream = batches_stream
             .then(move |batch_result| {
@@ -225,10 +285,38 @@ impl Service {
 
         let flights_stream = stream::once(async { Ok(schema_flight_data) }).chain(batches_stream);
 
-        Ok(flights_stream.boxed())
+        flights_stream.boxed()
     }
 }
 
+/// The number of bytes of a `DoPut` stream that is buffered in memory before it is written, i.e. a transactional put.
+const MAX_BUFFERED_PUT_BYTES: usize = 256 * 1024 * 1024;
+
+/// Reads the remainder of a `DoPut` stream and decodes it into record batches. The first message,
+/// already read by the caller to inspect the `FlightDescriptor`, carries the schema.
+///
+/// Fails with `RESOURCE_EXHAUSTED` once the stream is larger than [`MAX_BUFFERED_PUT_BYTES`].
+async fn decode_put_stream(
+    first_message: FlightData,
+    mut streaming_flight: Streaming<FlightData>,
+) -> Result<Vec<RecordBatch>, Status> {
+    let message_size = |message: &FlightData| message.data_header.len() + message.data_body.len();
+    let mut size = message_size(&first_message);
+    let mut messages = vec![first_message];
+    while let Some(message) = streaming_flight.message().await? {
+        size += message_size(&message);
+        if size > MAX_BUFFERED_PUT_BYTES {
+            return Err(Status::resource_exhausted(format!(
+                "The stream is larger than the {MAX_BUFFERED_PUT_BYTES} bytes that can be written at once"
+            )));
+        }
+        messages.push(message);
+    }
+
+    arrow_flight::utils::flight_data_to_batches(&messages)
+        .map_err(|e| Status::invalid_argument(format!("Unable to decode flight data: {e}")))
+}
+
 fn record_batches_to_flight_stream(
     record_batches: Vec<RecordBatch>,
 ) -> impl Stream<Item = Result<FlightData, Status>> {
@@ -237,6 +325,63 @@ fn record_batches_to_flight_stream(
         .map_err(to_tonic_err)
 }
 
+/// Collects a `DoGet` response into its schema and record batches.
+#[cfg(test)]
+async fn collect_flight_stream(
+    response: Response<BoxStream<'static, Result<FlightData, Status>>>,
+) -> (Schema, Vec<RecordBatch>) {
+    let messages: Vec<FlightData> = response
+        .into_inner()
+        .map(|message| message.expect("valid message"))
+        .collect()
+        .await;
+    let schema = messages
+        .first()
+        .map(|message| Schema::try_from(message).expect("schema message"))
+        .expect("a schema message");
+    let batches = arrow_flight::utils::flight_data_to_batches(&messages).expect("valid batches");
+    (schema, batches)
+}
+
+/// Serves the Flight API of `service` on a local port, authenticating every call as `principal`, and returns a
+/// client connected to it.
+#[cfg(test)]
+async fn serve_for_tests(
+    service: &Service,
+    principal: Option<Principal>,
+) -> arrow_flight::FlightClient {
+    let service = Service {
+        datafusion: Arc::clone(&service.datafusion),
+        prepared_statements: Arc::clone(&service.prepared_statements),
+        auth: service.auth.as_ref().map(Arc::clone),
+    };
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("local port binds");
+    let address = listener.local_addr().expect("listener has an address");
+    let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+        .expect("listener accepts connections");
+    let server = Server::builder()
+        .add_service(FlightServiceServer::with_interceptor(
+            service,
+            move |mut request: Request<()>| {
+                if let Some(principal) = &principal {
+                    request.extensions_mut().insert(principal.clone());
+                }
+                Ok(request)
+            },
+        ))
+        .serve_with_incoming(incoming);
+    tokio::spawn(server);
+
+    let channel = tonic::transport::Endpoint::from_shared(format!("http://{address}"))
+        .expect("valid endpoint")
+        .connect()
+        .await
+        .expect("server accepts connections");
+    arrow_flight::FlightClient::new(channel)
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn to_tonic_err<E>(e: E) -> Status
 where
@@ -249,6 +394,17 @@ where
     }
 }
 
+/// Maps a failed write to a dataset to the status reported to the client.
+#[allow(clippy::needless_pass_by_value)]
+fn handle_write_error(e: crate::datafusion::Error) -> Status {
+    match e.write_failure() {
+        WriteFailure::InvalidData => Status::invalid_argument(e.to_string()),
+        WriteFailure::Conflict => Status::failed_precondition(e.to_string()),
+        WriteFailure::InProgress => Status::aborted(e.to_string()),
+        WriteFailure::Internal => Status::internal(format!("Error writing data: {e}")),
+    }
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn handle_datafusion_error(e: DataFusionError) -> Status {
     match e {
@@ -282,25 +438,68 @@ pub enum Error {
 
     #[snafu(display("Unable to start Flight server: {source}"))]
     UnableToStartFlightServer { source: tonic::transport::Error },
+
+    #[snafu(display("Unable to bind to address: {source}"))]
+    UnableToBindServerToPort { source: std::io::Error },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-pub async fn start(bind_address: std::net::SocketAddr, df: Arc<RwLock<DataFusion>>) -> Result<()> {
+pub async fn start(
+    bind_address: std::net::SocketAddr,
+    df: Arc<RwLock<DataFusion>>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<Arc<TlsAcceptor>>,
+) -> Result<()> {
     let service = Service {
         datafusion: Arc::clone(&df),
-        channel_map: Arc::new(RwLock::new(HashMap::new())),
+        prepared_statements: Arc::new(RwLock::new(HashMap::new())),
+        auth: auth.as_ref().map(Arc::clone),
     };
-    let svc = FlightServiceServer::new(service);
 
     tracing::info!("Spice Runtime Flight listening on {bind_address}");
     metrics::counter!("spiced_runtime_flight_server_start").increment(1);
 
-    Server::builder()
-        .add_service(svc)
-        .serve(bind_address)
-        .await
-        .context(UnableToStartFlightServerSnafu)?;
+    let mut server = Server::builder();
+    let router = match auth {
+        Some(auth) => server.add_service(FlightServiceServer::with_interceptor(
+            service,
+            move |request| authenticate(&auth, request),
+        )),
+        None => server.add_service(FlightServiceServer::new(service)),
+    };
+
+    match tls {
+        Some(tls) => {
+            let listener = TcpListener::bind(bind_address)
+                .await
+                .context(UnableToBindServerToPortSnafu)?;
+            router.serve_with_incoming(tls.incoming(listener)).await
+        }
+        None => router.serve(bind_address).await,
+    }
+    .context(UnableToStartFlightServerSnafu)?;
 
     Ok(())
 }
+
+/// Verifies the `authorization` header sent with every Flight call, including the handshake.
+///
+/// Clients authenticate the handshake with basic auth or an API key and use the bearer token it
+/// returns for subsequent calls. The authenticated [`Principal`](crate::auth::Principal) is added
+/// to the request extensions.
+fn authenticate(auth: &Authenticator, mut request: Request<()>) -> Result<Request<()>, Status> {
+    let authorization = request
+        .metadata()
+        .get("authorization")
+        .ok_or_else(|| Status::unauthenticated("Missing authorization header"))?
+        .to_str()
+        .map_err(|_| Status::unauthenticated("Invalid authorization header"))?;
+
+    let principal = auth
+        .authenticate(authorization)
+        .ok_or_else(|| Status::unauthenticated("Invalid credentials"))?;
+
+    request.extensions_mut().insert(principal);
+    Ok(request)
+}
@@ -20,6 +20,7 @@ use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::{
+    auth::Principal,
     flight::{flightsql::prepared_statement_query, to_tonic_err, Service},
     timing::{TimeMeasurement, TimedStream},
 };
@@ -111,15 +112,26 @@ pub(crate) async fn do_action(
                         "Unable to unpack ActionCreatePreparedStatementRequest.",
                     )
                 })?;
-            let stmt =
-                prepared_statement_query::do_action_create_prepared_statement(flight_svc, cmd)
-                    .await?;
+            let principal = request.extensions().get::<Principal>();
+            let stmt = prepared_statement_query::do_action_create_prepared_statement(
+                flight_svc, principal, cmd,
+            )
+            .await?;
             futures::stream::iter(vec![Ok(arrow_flight::Result {
                 body: stmt.as_any().encode_to_vec().into(),
             })])
         }
         ActionType::ClosePreparedStatement => {
             tracing::trace!("do_action: ClosePreparedStatement");
+            let any = Any::decode(&*request.get_ref().body).map_err(to_tonic_err)?;
+
+            let cmd: sql::ActionClosePreparedStatementRequest =
+                any.unpack().map_err(to_tonic_err)?.ok_or_else(|| {
+                    Status::invalid_argument(
+                        "Unable to unpack ActionClosePreparedStatementRequest.",
+                    )
+                })?;
+            prepared_statement_query::do_action_close_prepared_statement(flight_svc, cmd).await?;
             futures::stream::iter(vec![Ok(arrow_flight::Result::default())])
         }
         ActionType::Unknown => return Err(Status::invalid_argument("Unknown action type")),
@@ -14,23 +14,217 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
+use arrow::{error::ArrowError, record_batch::RecordBatch};
 use arrow_flight::{flight_service_server::FlightService, FlightData, SchemaAsIpc};
 use arrow_ipc::writer::{self, DictionaryTracker, IpcDataGenerator};
-use futures::{stream, StreamExt};
-use tokio::sync::broadcast;
+use datafusion::{
+    common::ToDFSchema, dataframe::DataFrame, error::DataFusionError,
+    execution::context::SessionContext, logical_expr::Expr,
+};
+use futures::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use spicepod::component::access::Operation;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc::{self, error::TrySendError},
+};
 use tonic::{Request, Response, Status, Streaming};
 
-use crate::dataupdate::{DataUpdate, UpdateType};
+use crate::{
+    accelerated_table::get_since_expr,
+    auth::Principal,
+    changes::{with_op_column, Change, ChangeOperation},
+};
 
 use super::Service;
 
+mod spill;
+
+/// The number of updates held in memory for a subscriber with [`LagPolicy::Buffer`] before spilling to disk.
+const IN_MEMORY_BUFFER_SIZE: usize = 16;
+
+/// The number of bytes a subscriber with [`LagPolicy::Buffer`] can spill to disk before it is disconnected.
+const MAX_SPILL_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Options a subscriber can send as JSON in the `FlightDescriptor` cmd to narrow the data pushed to it, i.e.
+/// `{"columns": ["device_id", "temperature"], "filter": "device_id = 'abc'", "since": 1714521600}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscriptionOptions {
+    /// The columns to send. All columns are sent if not set.
+    columns: Option<Vec<String>>,
+
+    /// A SQL predicate that rows must match to be sent.
+    filter: Option<String>,
+
+    /// Only replay the rows whose time column is at or after this Unix timestamp, in seconds.
+    /// The full dataset is replayed if not set.
+    since: Option<u64>,
+
+    /// What to do when the subscriber falls behind the updates to the dataset.
+    #[serde(default)]
+    on_lag: LagPolicy,
+
+    /// Add an `_op` column with the operation of each row: `insert`, `overwrite` or `delete`. Replayed rows are
+    /// inserts.
+    #[serde(default)]
+    include_op: bool,
+}
+
+/// How a subscriber that can't keep up with the updates to its dataset is handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LagPolicy {
+    /// Drop the missed updates and send a message with `{"lagged": <number of dropped updates>}` as its `app_metadata`.
+    #[default]
+    Notify,
+
+    /// End the stream with a `DATA_LOSS` status.
+    Disconnect,
+
+    /// Buffer the updates on disk until the subscriber catches up, or end the stream with a `DATA_LOSS` status
+    /// if the buffer grows past 1 GiB.
+    Buffer,
+}
+
+/// A compiled [`SubscriptionOptions`] that is applied to the replayed data and to every update.
+struct Subscription {
+    columns: Option<Vec<String>>,
+    filter: Option<Expr>,
+    include_op: bool,
+}
+
+impl Subscription {
+    fn is_passthrough(&self) -> bool {
+        self.columns.is_none() && self.filter.is_none()
+    }
+
+    fn apply(&self, df: DataFrame) -> Result<DataFrame, DataFusionError> {
+        let df = match &self.filter {
+            Some(filter) => df.filter(filter.clone())?,
+            None => df,
+        };
+        match &self.columns {
+            Some(columns) => {
+                let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+                df.select_columns(&columns)
+            }
+            None => Ok(df),
+        }
+    }
+
+    async fn apply_to_change(
+        &self,
+        ctx: &SessionContext,
+        change: Change,
+    ) -> Result<Vec<RecordBatch>, DataFusionError> {
+        let batches = if self.is_passthrough() || change.data.is_empty() {
+            change.data
+        } else {
+            let df = ctx.read_batches(change.data)?;
+            self.apply(df)?.collect().await?
+        };
+
+        batches
+            .into_iter()
+            .map(|batch| self.with_op(batch, change.op))
+            .collect()
+    }
+
+    fn with_op(
+        &self,
+        batch: RecordBatch,
+        op: ChangeOperation,
+    ) -> Result<RecordBatch, DataFusionError> {
+        if self.include_op {
+            Ok(with_op_column(&batch, op)?)
+        } else {
+            Ok(batch)
+        }
+    }
+}
+
+/// An event sent to a subscriber after the replay.
+enum SubscriberEvent {
+    Change(Change),
+    Lagged(u64),
+    Error(Status),
+}
+
+/// Where a subscriber reads its events from: directly from the dataset's broadcast channel, or from a task that
+/// buffers the broadcast updates for it.
+enum UpdateSource {
+    Broadcast(broadcast::Receiver<Change>),
+    Buffered(mpsc::Receiver<SubscriberEvent>),
+}
+
+impl UpdateSource {
+    async fn next(&mut self) -> Option<SubscriberEvent> {
+        match self {
+            UpdateSource::Broadcast(rx) => match rx.recv().await {
+                Ok(change) => Some(SubscriberEvent::Change(change)),
+                Err(RecvError::Lagged(skipped)) => Some(SubscriberEvent::Lagged(skipped)),
+                Err(RecvError::Closed) => None,
+            },
+            UpdateSource::Buffered(rx) => rx.recv().await,
+        }
+    }
+}
+
+/// The metrics of a single subscriber, added to the metrics of its dataset.
+struct SubscriberMetrics {
+    dataset: String,
+    /// The number of updates this subscriber holds in its buffer, included in the dataset's buffered gauge.
+    buffered: AtomicUsize,
+}
+
+impl SubscriberMetrics {
+    fn new(dataset: &str) -> Self {
+        metrics::gauge!("flight_do_exchange_subscribers", "dataset" => dataset.to_string())
+            .increment(1.0);
+        Self {
+            dataset: dataset.to_string(),
+            buffered: AtomicUsize::new(0),
+        }
+    }
+
+    fn sent(&self, flights: usize) {
+        metrics::counter!("flight_do_exchange_data_updates_sent", "dataset" => self.dataset.clone())
+            .increment(flights as u64);
+    }
+
+    fn dropped(&self, updates: u64) {
+        metrics::counter!("flight_do_exchange_data_updates_dropped", "dataset" => self.dataset.clone())
+            .increment(updates);
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn buffered(&self, updates: usize) {
+        let previous = self.buffered.swap(updates, Ordering::Relaxed);
+        metrics::gauge!("flight_do_exchange_data_updates_buffered", "dataset" => self.dataset.clone())
+            .increment(updates as f64 - previous as f64);
+    }
+}
+
+impl Drop for SubscriberMetrics {
+    fn drop(&mut self) {
+        self.buffered(0);
+        metrics::gauge!("flight_do_exchange_subscribers", "dataset" => self.dataset.clone())
+            .decrement(1.0);
+    }
+}
+
 #[allow(clippy::too_many_lines)]
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Streaming<FlightData>>,
 ) -> Result<Response<<Service as FlightService>::DoExchangeStream>, Status> {
+    let principal = request.extensions().get::<Principal>().cloned();
     let mut streaming_request = request.into_inner();
     let req = streaming_request.next().await;
     let Some(subscription_request) = req else {
@@ -63,96 +257,340 @@ pub(crate) async fn handle(
 
     let data_path = flight_descriptor.path.join(".");
 
-    if !flight_svc.datafusion.read().await.is_writable(&data_path) {
-        return Err(Status::invalid_argument(format!(
-            r#"Unknown dataset: "{data_path}""#,
-        )));
+    flight_svc.authorize(principal.as_ref(), &data_path, Operation::Read)?;
+
+    let options: SubscriptionOptions = if flight_descriptor.cmd.is_empty() {
+        SubscriptionOptions::default()
+    } else {
+        serde_json::from_slice(&flight_descriptor.cmd).map_err(|e| {
+            Status::invalid_argument(format!("Unable to parse subscription options: {e}"))
+        })?
     };
 
-    let channel_map = Arc::clone(&flight_svc.channel_map);
-    let channel_map_read = channel_map.read().await;
-    let (tx, rx) = if let Some(channel) = channel_map_read.get(&data_path) {
-        (Arc::clone(channel), channel.subscribe())
+    let on_lag = options.on_lag;
+    let (ctx, subscription, replay) = {
+        let df = flight_svc.datafusion.read().await;
+        if !df.table_exists(&data_path) {
+            return Err(Status::invalid_argument(format!(
+                r#"Unknown dataset: "{data_path}""#,
+            )));
+        };
+
+        let table = df
+            .ctx
+            .table(&data_path)
+            .await
+            .map_err(|e| Status::internal(format!("Unable to read {data_path}: {e}")))?;
+        let schema = Arc::clone(table.schema().inner());
+
+        let filter = match &options.filter {
+            Some(filter) => {
+                // Resolve the filter against the unqualified schema so it also applies to the updates.
+                let df_schema = Arc::clone(&schema)
+                    .to_dfschema()
+                    .map_err(|e| Status::internal(e.to_string()))?;
+                Some(
+                    df.ctx
+                        .state()
+                        .create_logical_expr(filter, &df_schema)
+                        .map_err(|e| {
+                            Status::invalid_argument(format!("Invalid subscription filter: {e}"))
+                        })?,
+                )
+            }
+            None => None,
+        };
+
+        let subscription = Subscription {
+            columns: options.columns,
+            filter,
+            include_op: options.include_op,
+        };
+
+        let table = match options.since {
+            Some(since) => {
+                let Some(time_column) = df.time_column(&data_path) else {
+                    return Err(Status::invalid_argument(format!(
+                        r#"Dataset "{data_path}" has no time_column to replay since {since}"#,
+                    )));
+                };
+                let Some(since_expr) =
+                    get_since_expr(&schema, &time_column.name, &time_column.format, since)
+                else {
+                    return Err(Status::invalid_argument(format!(
+                        r#"Unable to compare the time_column "{}" of "{data_path}" to a timestamp"#,
+                        time_column.name
+                    )));
+                };
+                table
+                    .filter(since_expr)
+                    .map_err(|e| Status::internal(e.to_string()))?
+            }
+            None => table,
+        };
+
+        let replay = subscription
+            .apply(table)
+            .map_err(|e| Status::invalid_argument(format!("Invalid subscription: {e}")))?;
+
+        // The filter can reference other datasets through subqueries, so authorize the whole replay plan.
+        flight_svc.authorize_plan(principal.as_ref(), replay.logical_plan())?;
+
+        (Arc::clone(&df.ctx), Arc::new(subscription), replay)
+    };
+
+    // Subscribe before replaying so the changes made during the replay are held for the subscriber. The changes
+    // published before the replay starts reading are part of it, and are skipped when they arrive from the feed.
+    let change_feeds = flight_svc.datafusion.read().await.change_feeds();
+    let rx = change_feeds.subscribe(&data_path);
+    let replayed_position = change_feeds.position(&data_path);
+
+    let metrics = Arc::new(SubscriberMetrics::new(&data_path));
+
+    let source = if on_lag == LagPolicy::Buffer {
+        let (tx, buffered_rx) = mpsc::channel(IN_MEMORY_BUFFER_SIZE);
+        tokio::spawn(buffer_updates(rx, tx, Arc::clone(&metrics)));
+        UpdateSource::Buffered(buffered_rx)
     } else {
-        drop(channel_map_read);
-        let mut channel_map_write = channel_map.write().await;
-        let (tx, rx) = broadcast::channel(100);
-        let tx = Arc::new(tx);
-        channel_map_write.insert(data_path.clone(), Arc::clone(&tx));
-        (tx, rx)
+        UpdateSource::Broadcast(rx)
     };
 
-    let response_stream = stream::unfold(rx, move |mut rx| {
-        let encoder = IpcDataGenerator::default();
-        let mut tracker = DictionaryTracker::new(false);
-        let write_options = writer::IpcWriteOptions::default();
-        async move {
-            match rx.recv().await {
-                Ok(data_update) => {
-                    let mut schema_sent: bool = false;
-
-                    let mut flights = vec![];
-
-                    for batch in &data_update.data {
-                        if !schema_sent {
-                            let schema = batch.schema();
-                            flights
-                                .push(FlightData::from(SchemaAsIpc::new(&schema, &write_options)));
-                            schema_sent = true;
-                        }
-                        let Ok((flight_dictionaries, flight_batch)) =
-                            encoder.encoded_batch(batch, &mut tracker, &write_options)
-                        else {
-                            panic!("Unable to encode batch")
-                        };
+    // Replay the existing data to this subscriber as inserts before following the changes.
+    let replayed = replay
+        .execute_stream()
+        .await
+        .map_err(|e| Status::internal(format!("Unable to replay {data_path}: {e}")))?;
+    let replay_stream = encode_replay(
+        replayed,
+        Arc::clone(&subscription),
+        Arc::clone(&metrics),
+        data_path.clone(),
+    );
 
-                        flights.extend(flight_dictionaries.into_iter().map(Into::into));
-                        flights.push(flight_batch.into());
+    let updates_stream = stream::unfold(
+        (Some(source), ctx, subscription, metrics),
+        move |(source, ctx, subscription, metrics)| async move {
+            let mut source = source?;
+            let flights = match source.next().await? {
+                SubscriberEvent::Change(change) if change.position <= replayed_position => vec![],
+                SubscriberEvent::Change(change) => {
+                    match subscription.apply_to_change(&ctx, change).await {
+                        Ok(batches) => encode_flights(&batches, &metrics),
+                        Err(e) => vec![Err(Status::internal(format!(
+                            "Unable to apply subscription to change: {e}"
+                        )))],
+                    }
+                }
+                SubscriberEvent::Lagged(skipped) => {
+                    metrics.dropped(skipped);
+                    if on_lag == LagPolicy::Disconnect {
+                        let status = Status::data_loss(format!(
+                            "Subscriber fell behind and missed {skipped} updates"
+                        ));
+                        return Some((
+                            stream::iter(vec![Err(status)]),
+                            (None, ctx, subscription, metrics),
+                        ));
                     }
+                    vec![Ok(
+                        FlightData::new().with_app_metadata(format!(r#"{{"lagged":{skipped}}}"#))
+                    )]
+                }
+                SubscriberEvent::Error(status) => {
+                    return Some((
+                        stream::iter(vec![Err(status)]),
+                        (None, ctx, subscription, metrics),
+                    ));
+                }
+            };
+            Some((
+                stream::iter(flights),
+                (Some(source), ctx, subscription, metrics),
+            ))
+        },
+    )
+    .flatten();
 
-                    metrics::counter!("flight_do_exchange_data_updates_sent")
-                        .increment(flights.len() as u64);
-                    let output = futures::stream::iter(flights.into_iter().map(Ok));
+    Ok(Response::new(replay_stream.chain(updates_stream).boxed()))
+}
+
+/// Forwards the updates from `rx` to `tx`, spilling them to disk while `tx` is full so the subscriber
+/// doesn't lag behind the broadcast channel.
+async fn buffer_updates(
+    mut rx: broadcast::Receiver<Change>,
+    tx: mpsc::Sender<SubscriberEvent>,
+    metrics: Arc<SubscriberMetrics>,
+) {
+    let mut spill = match spill::SpillBuffer::new(MAX_SPILL_BYTES) {
+        Ok(spill) => spill,
+        Err(e) => {
+            let _ = tx
+                .send(SubscriberEvent::Error(Status::internal(e.to_string())))
+                .await;
+            return;
+        }
+    };
 
-                    Some((output, rx))
+    loop {
+        tokio::select! {
+            () = tx.closed() => return,
+            update = rx.recv() => match update {
+                Ok(change) => {
+                    if spill.is_empty() {
+                        match tx.try_reserve() {
+                            Ok(permit) => {
+                                permit.send(SubscriberEvent::Change(change));
+                                continue;
+                            }
+                            Err(TrySendError::Closed(())) => return,
+                            Err(TrySendError::Full(())) => {}
+                        }
+                    }
+                    if let Err(e) = spill.push(change).await {
+                        let status = match e {
+                            spill::Error::SpillBufferFull { .. } => Status::data_loss(format!(
+                                "Subscriber fell behind and its buffer is full: {e}"
+                            )),
+                            _ => Status::internal(e.to_string()),
+                        };
+                        let _ = tx.send(SubscriberEvent::Error(status)).await;
+                        return;
+                    }
+                    metrics.buffered(spill.len());
                 }
-                Err(_e) => {
-                    let output = futures::stream::iter(vec![].into_iter().map(Ok));
-                    Some((output, rx))
+                Err(RecvError::Lagged(skipped)) => {
+                    if tx.send(SubscriberEvent::Lagged(skipped)).await.is_err() {
+                        return;
+                    }
                 }
+                Err(RecvError::Closed) => break,
+            },
+            permit = tx.reserve(), if !spill.is_empty() => {
+                let Ok(permit) = permit else {
+                    return;
+                };
+                match spill.pop().await {
+                    Some(Ok(change)) => permit.send(SubscriberEvent::Change(change)),
+                    Some(Err(e)) => {
+                        permit.send(SubscriberEvent::Error(Status::internal(e.to_string())));
+                        return;
+                    }
+                    None => {}
+                }
+                metrics.buffered(spill.len());
             }
         }
-    })
-    .flat_map(|x| x);
+    }
 
-    let datafusion = Arc::clone(&flight_svc.datafusion);
-    tokio::spawn(async move {
-        let Ok(df) = datafusion
-            .read()
-            .await
-            .ctx
-            .sql(&format!(r#"SELECT * FROM "{data_path}""#))
-            .await
-        else {
-            return;
-        };
-        let Ok(results) = df.collect().await else {
-            return;
+    while let Some(change) = spill.pop().await {
+        let event = match change {
+            Ok(change) => SubscriberEvent::Change(change),
+            Err(e) => SubscriberEvent::Error(Status::internal(e.to_string())),
         };
-        if results.is_empty() {
+        if tx.send(event).await.is_err() {
             return;
         }
+        metrics.buffered(spill.len());
+    }
+}
 
-        for batch in &results {
+/// Encodes the replayed rows for a subscriber as they are read, ending the stream with an error status if a batch
+/// can't be read or encoded.
+fn encode_replay(
+    replayed: impl Stream<Item = Result<RecordBatch, DataFusionError>> + Send + 'static,
+    subscription: Arc<Subscription>,
+    metrics: Arc<SubscriberMetrics>,
+    data_path: String,
+) -> impl Stream<Item = Result<FlightData, Status>> + Send + 'static {
+    let mut encoder = BatchEncoder::default();
+    replayed
+        .map(move |batch| {
+            let flights = batch
+                .and_then(|batch| subscription.with_op(batch, ChangeOperation::Insert))
+                .map_err(|e| Status::internal(format!("Unable to replay {data_path}: {e}")))
+                .and_then(|batch| {
+                    encoder
+                        .encode(&batch)
+                        .map_err(|e| Status::internal(format!("Unable to encode batch: {e}")))
+                });
+            match flights {
+                Ok(flights) => {
+                    metrics.sent(flights.len());
+                    flights.into_iter().map(Ok).collect()
+                }
+                Err(status) => vec![Err(status)],
+            }
+        })
+        .flat_map(stream::iter)
+}
+
+/// Encodes `batches` for a subscriber, returning an error status if a batch can't be encoded.
+fn encode_flights(
+    batches: &[RecordBatch],
+    metrics: &SubscriberMetrics,
+) -> Vec<Result<FlightData, Status>> {
+    match encode_batches(batches) {
+        Ok(flights) => {
+            metrics.sent(flights.len());
+            flights.into_iter().map(Ok).collect()
+        }
+        Err(e) => vec![Err(Status::internal(format!(
+            "Unable to encode batch: {e}"
+        )))],
+    }
+}
+
+/// Encodes `batches` as a schema message followed by the batches, skipping batches without rows.
+fn encode_batches(batches: &[RecordBatch]) -> Result<Vec<FlightData>, ArrowError> {
+    let mut encoder = BatchEncoder::default();
+    let mut flights = vec![];
+    for batch in batches {
+        flights.extend(encoder.encode(batch)?);
+    }
+    Ok(flights)
+}
+
+/// Encodes a sequence of batches, sending the schema before the first batch with rows and skipping batches without
+/// rows.
+struct BatchEncoder {
+    encoder: IpcDataGenerator,
+    tracker: DictionaryTracker,
+    write_options: writer::IpcWriteOptions,
+    schema_sent: bool,
+}
+
+impl Default for BatchEncoder {
+    fn default() -> Self {
+        Self {
+            encoder: IpcDataGenerator::default(),
+            tracker: DictionaryTracker::new(false),
+            write_options: writer::IpcWriteOptions::default(),
+            schema_sent: false,
+        }
+    }
+}
+
+impl BatchEncoder {
+    fn encode(&mut self, batch: &RecordBatch) -> Result<Vec<FlightData>, ArrowError> {
+        let mut flights = vec![];
+        if batch.num_rows() == 0 {
+            return Ok(flights);
+        }
+
+        if !self.schema_sent {
             let schema = batch.schema();
-            let data_update = DataUpdate {
-                data: vec![batch.clone()],
-                schema,
-                update_type: UpdateType::Append,
-            };
-            let _ = tx.send(data_update);
+            flights.push(FlightData::from(SchemaAsIpc::new(
+                &schema,
+                &self.write_options,
+            )));
+            self.schema_sent = true;
         }
-    });
+        let (flight_dictionaries, flight_batch) =
+            self.encoder
+                .encoded_batch(batch, &mut self.tracker, &self.write_options)?;
 
-    Ok(Response::new(response_stream.boxed()))
+        flights.extend(flight_dictionaries.into_iter().map(Into::into));
+        flights.push(flight_batch.into());
+        Ok(flights)
+    }
 }
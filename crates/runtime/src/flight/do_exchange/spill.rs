@@ -0,0 +1,297 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use arrow::{error::ArrowError, record_batch::RecordBatch};
+use arrow_ipc::{reader::FileReader, writer::FileWriter};
+use snafu::prelude::*;
+use uuid::Uuid;
+
+use crate::changes::{Change, ChangeOperation};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to create the spill directory {}: {source}", path.display()))]
+    UnableToCreateSpillDirectory {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to access the spill file {}: {source}", path.display()))]
+    UnableToAccessSpillFile {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to write the spill file {}: {source}", path.display()))]
+    UnableToWriteSpillFile { source: ArrowError, path: PathBuf },
+
+    #[snafu(display("Unable to read the spill file {}: {source}", path.display()))]
+    UnableToReadSpillFile { source: ArrowError, path: PathBuf },
+
+    #[snafu(display("The spill buffer is full: {used} of {limit} bytes are used"))]
+    SpillBufferFull { used: u64, limit: u64 },
+
+    #[snafu(display("The spill task failed: {source}"))]
+    SpillTaskFailed { source: tokio::task::JoinError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A FIFO queue of changes stored as Arrow IPC files, for a subscriber that can't keep up with its dataset.
+///
+/// The files are removed when they are read back, and the directory is removed when the buffer is dropped.
+/// Once the files take up `max_bytes`, pushing another change fails with [`Error::SpillBufferFull`].
+pub(crate) struct SpillBuffer {
+    dir: PathBuf,
+    /// The file and its size of each change, or `None` for a change without data, with its operation and position.
+    pending: VecDeque<(Option<(PathBuf, u64)>, ChangeOperation, u64)>,
+    next_id: u64,
+    bytes: u64,
+    max_bytes: u64,
+}
+
+impl SpillBuffer {
+    pub(crate) fn new(max_bytes: u64) -> Result<Self> {
+        let dir = std::env::temp_dir()
+            .join("spice")
+            .join("do_exchange")
+            .join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)
+            .context(UnableToCreateSpillDirectorySnafu { path: dir.clone() })?;
+
+        Ok(Self {
+            dir,
+            pending: VecDeque::new(),
+            next_id: 0,
+            bytes: 0,
+            max_bytes,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub(crate) async fn push(&mut self, change: Change) -> Result<()> {
+        if change.data.is_empty() {
+            self.pending.push_back((None, change.op, change.position));
+            return Ok(());
+        }
+
+        ensure!(
+            self.bytes < self.max_bytes,
+            SpillBufferFullSnafu {
+                used: self.bytes,
+                limit: self.max_bytes,
+            }
+        );
+
+        let path = self.dir.join(format!("{}.arrow", self.next_id));
+        self.next_id += 1;
+
+        let (op, position) = (change.op, change.position);
+        let file_path = path.clone();
+        let size = tokio::task::spawn_blocking(move || write_batches(&file_path, &change.data))
+            .await
+            .context(SpillTaskFailedSnafu)??;
+
+        self.bytes += size;
+        self.pending.push_back((Some((path, size)), op, position));
+        Ok(())
+    }
+
+    pub(crate) async fn pop(&mut self) -> Option<Result<Change>> {
+        let (path, op, position) = self.pending.pop_front()?;
+        let Some((path, size)) = path else {
+            return Some(Ok(Change {
+                op,
+                data: vec![],
+                position,
+            }));
+        };
+        self.bytes -= size;
+
+        let data = tokio::task::spawn_blocking(move || {
+            let data = read_batches(&path);
+            let _ = std::fs::remove_file(&path);
+            data
+        })
+        .await
+        .context(SpillTaskFailedSnafu);
+
+        Some(
+            data.and_then(|data| data)
+                .map(|data| Change { op, data, position }),
+        )
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!(
+                "Unable to remove spill directory {}: {e}",
+                self.dir.display()
+            );
+        }
+    }
+}
+
+/// Writes `batches` to `path`, returning the size of the file.
+fn write_batches(path: &Path, batches: &[RecordBatch]) -> Result<u64> {
+    let Some(first) = batches.first() else {
+        return Ok(0);
+    };
+
+    let file = File::create(path).context(UnableToAccessSpillFileSnafu {
+        path: path.to_path_buf(),
+    })?;
+    let mut writer =
+        FileWriter::try_new(file, &first.schema()).context(UnableToWriteSpillFileSnafu {
+            path: path.to_path_buf(),
+        })?;
+    for batch in batches {
+        writer.write(batch).context(UnableToWriteSpillFileSnafu {
+            path: path.to_path_buf(),
+        })?;
+    }
+    writer.finish().context(UnableToWriteSpillFileSnafu {
+        path: path.to_path_buf(),
+    })?;
+
+    let metadata = std::fs::metadata(path).context(UnableToAccessSpillFileSnafu {
+        path: path.to_path_buf(),
+    })?;
+    Ok(metadata.len())
+}
+
+fn read_batches(path: &Path) -> Result<Vec<RecordBatch>> {
+    let file = File::open(path).context(UnableToAccessSpillFileSnafu {
+        path: path.to_path_buf(),
+    })?;
+    let reader = FileReader::try_new(file, None).context(UnableToReadSpillFileSnafu {
+        path: path.to_path_buf(),
+    })?;
+    reader
+        .collect::<Result<Vec<_>, _>>()
+        .context(UnableToReadSpillFileSnafu {
+            path: path.to_path_buf(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::Int64Array,
+        datatypes::{DataType, Field, Schema},
+    };
+
+    use super::*;
+
+    fn change(op: ChangeOperation, values: Vec<i64>) -> Change {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(values))])
+            .expect("valid batch");
+        Change::new(op, vec![batch])
+    }
+
+    fn ids(change: &Change) -> Vec<i64> {
+        change
+            .data
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .expect("Int64 column")
+                    .values()
+                    .to_vec()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_spill_and_drain() {
+        let mut spill = SpillBuffer::new(u64::MAX).expect("spill buffer");
+        let dir = spill.dir.clone();
+
+        spill
+            .push(change(ChangeOperation::Insert, vec![1, 2]))
+            .await
+            .expect("spilled");
+        spill
+            .push(Change::new(ChangeOperation::Overwrite, vec![]))
+            .await
+            .expect("spilled");
+        spill
+            .push(change(ChangeOperation::Delete, vec![3]))
+            .await
+            .expect("spilled");
+        assert_eq!(spill.len(), 3);
+        assert!(spill.bytes > 0);
+
+        let first = spill.pop().await.expect("change").expect("readable");
+        assert_eq!(first.op, ChangeOperation::Insert);
+        assert_eq!(ids(&first), vec![1, 2]);
+
+        let second = spill.pop().await.expect("change").expect("readable");
+        assert_eq!(second.op, ChangeOperation::Overwrite);
+        assert!(second.data.is_empty());
+
+        let third = spill.pop().await.expect("change").expect("readable");
+        assert_eq!(third.op, ChangeOperation::Delete);
+        assert_eq!(ids(&third), vec![3]);
+
+        assert!(spill.pop().await.is_none());
+        assert!(spill.is_empty());
+        assert_eq!(spill.bytes, 0);
+        assert_eq!(std::fs::read_dir(&dir).expect("spill dir").count(), 0);
+
+        drop(spill);
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_spill_limit() {
+        let mut spill = SpillBuffer::new(1).expect("spill buffer");
+
+        spill
+            .push(change(ChangeOperation::Insert, vec![1]))
+            .await
+            .expect("spilled");
+        let result = spill.push(change(ChangeOperation::Insert, vec![2])).await;
+        assert!(matches!(result, Err(Error::SpillBufferFull { .. })));
+
+        spill.pop().await.expect("change").expect("readable");
+        spill
+            .push(change(ChangeOperation::Insert, vec![3]))
+            .await
+            .expect("spilled after draining");
+    }
+}
@@ -14,8 +14,6 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::sync::Arc;
-
 use arrow_flight::{
     flight_service_server::FlightService,
     sql::{Any, Command},
@@ -24,7 +22,10 @@ use arrow_flight::{
 use prost::Message;
 use tonic::{Request, Response, Status};
 
-use crate::timing::{TimeMeasurement, TimedStream};
+use crate::{
+    auth::Principal,
+    timing::{TimeMeasurement, TimedStream},
+};
 
 use super::{flightsql, to_tonic_err, Service};
 
@@ -32,18 +33,28 @@ pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Ticket>,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let principal = request.extensions().get::<Principal>().cloned();
     let msg: Any = match Message::decode(&*request.get_ref().ticket) {
         Ok(msg) => msg,
-        Err(_) => return Box::pin(do_get_simple(flight_svc, request)).await,
+        Err(_) => {
+            return Box::pin(do_get_simple(flight_svc, principal.as_ref(), request)).await;
+        }
     };
 
     match Command::try_from(msg).map_err(to_tonic_err)? {
         Command::CommandStatementQuery(command) => {
-            Box::pin(flightsql::statement_query::do_get(flight_svc, command)).await
+            Box::pin(flightsql::statement_query::do_get(
+                flight_svc,
+                principal.as_ref(),
+                command,
+            ))
+            .await
         }
         Command::CommandPreparedStatementQuery(command) => {
             Box::pin(flightsql::prepared_statement_query::do_get(
-                flight_svc, command,
+                flight_svc,
+                principal.as_ref(),
+                command,
             ))
             .await
         }
@@ -58,22 +69,29 @@ pub(crate) async fn handle(
         }
         Command::CommandGetTableTypes(command) => flightsql::get_table_types::do_get(&command),
         Command::CommandGetSqlInfo(command) => flightsql::get_sql_info::do_get(command),
+        Command::CommandGetPrimaryKeys(command) => {
+            flightsql::get_primary_keys::do_get(flight_svc, principal.as_ref(), command).await
+        }
+        Command::CommandGetImportedKeys(command) => flightsql::get_foreign_keys::do_get(&command),
+        Command::CommandGetExportedKeys(command) => flightsql::get_foreign_keys::do_get(&command),
+        Command::CommandGetCrossReference(command) => flightsql::get_foreign_keys::do_get(&command),
+        Command::CommandGetXdbcTypeInfo(command) => flightsql::get_xdbc_type_info::do_get(command),
         _ => Err(Status::unimplemented("Not yet implemented")),
     }
 }
 
 async fn do_get_simple(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     request: Request<Ticket>,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
-    let datafusion = Arc::clone(&flight_svc.datafusion);
     let ticket = request.into_inner();
     tracing::trace!("do_get_simple: {ticket:?}");
     match std::str::from_utf8(&ticket.ticket) {
         Ok(sql) => {
             let start = TimeMeasurement::new("flight_do_get_simple_duration_ms", vec![]);
             let output =
-                Box::pin(Service::sql_to_flight_stream(datafusion, sql.to_owned())).await?;
+                Box::pin(flight_svc.sql_to_flight_stream(principal, sql.to_owned())).await?;
 
             let timed_output = TimedStream::new(output, move || start);
 
@@ -16,30 +16,50 @@ limitations under the License.
 
 use std::{collections::HashMap, sync::Arc};
 
-use arrow_flight::{flight_service_server::FlightService, FlightData, PutResult};
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{Any, Command},
+    FlightData, PutResult,
+};
 use arrow_ipc::convert::try_schema_from_flatbuffer_bytes;
-use futures::stream;
-use tokio::sync::{broadcast::Sender, RwLock};
+use futures::{stream, StreamExt};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use spicepod::component::access::Operation;
+use tokio::sync::RwLock;
 use tonic::{Request, Response, Status, Streaming};
 
 use crate::{
+    auth::Principal,
+    datafusion::{DataFusion, WriteResult},
     dataupdate::{DataUpdate, UpdateType},
+    deduplication::IDEMPOTENCY_KEY_HEADER,
     timing::{TimeMeasurement, TimedStream},
 };
 
-use super::Service;
+use super::{decode_put_stream, flightsql, handle_write_error, to_tonic_err, Service};
 
-async fn get_sender_channel(
-    channel_map: Arc<RwLock<HashMap<String, Arc<Sender<DataUpdate>>>>>,
-    path: &str,
-) -> Option<Arc<Sender<DataUpdate>>> {
-    let channel_map_read = channel_map.read().await;
-    if channel_map_read.contains_key(path) {
-        let channel = channel_map_read.get(path)?;
-        Some(Arc::clone(channel))
-    } else {
-        None
-    }
+/// Options a publisher can send as JSON in the `FlightDescriptor` cmd, i.e. `{"transactional": true}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PutOptions {
+    /// Buffer the whole stream and write it in a single transaction once the stream ends, so a stream that fails
+    /// halfway writes nothing. Otherwise each message is written as it arrives. A transactional stream is limited to
+    /// 256 MiB.
+    #[serde(default)]
+    transactional: bool,
+}
+
+/// The `app_metadata` of each `PutResult`, acknowledging a write.
+#[derive(Debug, Serialize)]
+struct WriteAck {
+    /// The number of rows written, after dropping duplicates.
+    rows: usize,
+
+    /// The write sequence number of the dataset, which increases with every committed write. It is per runtime
+    /// process and restarts at 1, see [`WriteResult::sequence`].
+    sequence: u64,
 }
 
 pub(crate) async fn handle(
@@ -47,6 +67,12 @@ pub(crate) async fn handle(
     request: Request<Streaming<FlightData>>,
 ) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
     let mut duration_metric = TimeMeasurement::new("flight_do_put_duration_ms", vec![]);
+    let principal = request.extensions().get::<Principal>().cloned();
+    let idempotency_token = request
+        .metadata()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .map(ToString::to_string);
     let mut streaming_flight = request.into_inner();
 
     let Ok(Some(message)) = streaming_flight.message().await else {
@@ -55,14 +81,66 @@ pub(crate) async fn handle(
     let Some(fd) = &message.flight_descriptor else {
         return Err(Status::invalid_argument("No flight descriptor provided"));
     };
+
+    if !fd.cmd.is_empty() {
+        if let Ok(any) = Any::decode(&*fd.cmd) {
+            return match Command::try_from(any).map_err(to_tonic_err)? {
+                Command::CommandPreparedStatementQuery(command) => {
+                    flightsql::prepared_statement_query::do_put(
+                        flight_svc,
+                        principal.as_ref(),
+                        command,
+                        message,
+                        streaming_flight,
+                    )
+                    .await
+                }
+                Command::CommandPreparedStatementUpdate(command) => {
+                    flightsql::prepared_statement_query::do_put_update(
+                        flight_svc,
+                        principal.as_ref(),
+                        command,
+                        message,
+                        streaming_flight,
+                    )
+                    .await
+                }
+                Command::CommandStatementUpdate(command) => {
+                    flightsql::statement_update::do_put(flight_svc, principal.as_ref(), command)
+                        .await
+                }
+                Command::CommandStatementIngest(command) => {
+                    flightsql::statement_ingest::do_put(
+                        flight_svc,
+                        principal.as_ref(),
+                        command,
+                        message,
+                        streaming_flight,
+                    )
+                    .await
+                }
+                _ => Err(Status::unimplemented("Not yet implemented")),
+            };
+        }
+    }
+
     if fd.path.is_empty() {
         return Err(Status::invalid_argument("No path provided"));
     };
 
+    let options: PutOptions = if fd.cmd.is_empty() {
+        PutOptions::default()
+    } else {
+        serde_json::from_slice(&fd.cmd)
+            .map_err(|e| Status::invalid_argument(format!("Unable to parse put options: {e}")))?
+    };
+
     let path = fd.path.join(".");
 
     duration_metric.with_labels(vec![("path", path.clone())]);
 
+    flight_svc.authorize(principal.as_ref(), &path, Operation::Write)?;
+
     let df = flight_svc.datafusion.read().await;
 
     if !df.is_writable(&path) {
@@ -75,6 +153,16 @@ pub(crate) async fn handle(
     let schema = try_schema_from_flatbuffer_bytes(&message.data_header)
         .map_err(|e| Status::internal(format!("Failed to get schema from data header: {e}")))?;
     let schema = Arc::new(schema);
+    let df = Arc::clone(&flight_svc.datafusion);
+
+    if options.transactional {
+        let batches = decode_put_stream(message, streaming_flight).await?;
+        let put_result = write(&df, &path, schema, batches, idempotency_token.as_deref()).await?;
+        let timed_stream =
+            TimedStream::new(stream::iter(vec![Ok(put_result)]), move || duration_metric);
+        return Ok(Response::new(Box::pin(timed_stream)));
+    }
+
     let dictionaries_by_id = Arc::new(HashMap::new());
 
     // Sometimes the first message only contains the schema and no data
@@ -85,69 +173,105 @@ pub(crate) async fn handle(
     )
     .ok();
 
-    let mut batches = vec![];
-    if let Some(first_batch) = first_batch {
-        batches.push(first_batch);
-    }
+    // Each message is a separate write, so a retried stream is deduplicated message by message.
+    let message_token = move |index: u64| {
+        idempotency_token
+            .as_ref()
+            .map(|token| format!("{token}:{index}"))
+    };
 
-    let channel_map = Arc::clone(&flight_svc.channel_map);
-    let df = Arc::clone(&flight_svc.datafusion);
+    let mut next_index = 0;
+    let first_put = match first_batch {
+        Some(first_batch) => {
+            next_index += 1;
+            let token = message_token(0);
+            let put_result = write(
+                &df,
+                &path,
+                Arc::clone(&schema),
+                vec![first_batch],
+                token.as_deref(),
+            )
+            .await?;
+            Some(put_result)
+        }
+        None => None,
+    };
 
-    let response_stream = stream::unfold(streaming_flight, move |mut flight| {
-        let schema = Arc::clone(&schema);
-        let df = Arc::clone(&df);
-        let dictionaries_by_id = Arc::clone(&dictionaries_by_id);
-        let path = path.clone();
-        let channel_map = Arc::clone(&channel_map);
-        async move {
-            match flight.message().await {
-                Ok(Some(message)) => {
-                    let new_batch = match arrow_flight::utils::flight_data_to_arrow_batch(
-                        &message,
-                        Arc::clone(&schema),
-                        &dictionaries_by_id,
-                    ) {
-                        Ok(batches) => batches,
-                        Err(e) => {
-                            tracing::error!("Failed to convert flight data to batches: {e}");
-                            return None;
-                        }
-                    };
-                    tracing::trace!("Received batch with {} rows", new_batch.num_rows());
-
-                    let data_update = DataUpdate {
-                        data: vec![new_batch],
-                        schema: Arc::clone(&schema),
-                        update_type: UpdateType::Append,
-                    };
-
-                    if let Some(channel) = get_sender_channel(channel_map, &path).await {
-                        let _ = channel.send(data_update.clone());
-                    };
-
-                    let df_guard = df.read().await;
-                    if let Err(e) = df_guard.write_data(&path, data_update).await {
-                        return Some((
-                            Err(Status::internal(format!("Error writing data: {e}"))),
-                            flight,
-                        ));
-                    };
-
-                    Some((Ok(PutResult::default()), flight))
-                }
-                Ok(None) => {
-                    // End of the stream
-                    None
+    let response_stream = stream::unfold(
+        (streaming_flight, next_index),
+        move |(mut flight, index)| {
+            let schema = Arc::clone(&schema);
+            let df = Arc::clone(&df);
+            let dictionaries_by_id = Arc::clone(&dictionaries_by_id);
+            let path = path.clone();
+            let token = message_token(index);
+            async move {
+                match flight.message().await {
+                    Ok(Some(message)) => {
+                        let new_batch = match arrow_flight::utils::flight_data_to_arrow_batch(
+                            &message,
+                            Arc::clone(&schema),
+                            &dictionaries_by_id,
+                        ) {
+                            Ok(batches) => batches,
+                            Err(e) => {
+                                tracing::error!("Failed to convert flight data to batches: {e}");
+                                return None;
+                            }
+                        };
+                        tracing::trace!("Received batch with {} rows", new_batch.num_rows());
+
+                        let put_result =
+                            write(&df, &path, schema, vec![new_batch], token.as_deref()).await;
+                        Some((put_result, (flight, index + 1)))
+                    }
+                    Ok(None) => {
+                        // End of the stream
+                        None
+                    }
+                    Err(e) => Some((
+                        Err(Status::internal(format!("Error reading message: {e}"))),
+                        (flight, index),
+                    )),
                 }
-                Err(e) => Some((
-                    Err(Status::internal(format!("Error reading message: {e}"))),
-                    flight,
-                )),
             }
-        }
-    });
+        },
+    );
 
+    let response_stream = stream::iter(first_put.map(Ok)).chain(response_stream);
     let timed_stream = TimedStream::new(response_stream, move || duration_metric);
 
     Ok(Response::new(Box::pin(timed_stream)))
 }
+
+/// Writes `batches` to the dataset at `path` in a single transaction, acknowledging the write with the rows written
+/// and the dataset's write sequence number.
+async fn write(
+    df: &RwLock<DataFusion>,
+    path: &str,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    idempotency_token: Option<&str>,
+) -> Result<PutResult, Status> {
+    let data_update = DataUpdate {
+        data: batches,
+        schema,
+        update_type: UpdateType::Append,
+    };
+
+    let WriteResult { rows, sequence } = df
+        .read()
+        .await
+        .write_data(path, data_update, idempotency_token)
+        .await
+        .map_err(handle_write_error)?;
+
+    let ack = serde_json::to_vec(&WriteAck { rows, sequence }).map_err(|e| {
+        Status::internal(format!("Unable to encode the write acknowledgement: {e}"))
+    })?;
+
+    Ok(PutResult {
+        app_metadata: ack.into(),
+    })
+}
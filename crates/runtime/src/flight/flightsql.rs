@@ -15,9 +15,14 @@ limitations under the License.
 */
 
 pub(crate) mod get_catalogs;
+pub(crate) mod get_foreign_keys;
+pub(crate) mod get_primary_keys;
 pub(crate) mod get_schemas;
 pub(crate) mod get_sql_info;
 pub(crate) mod get_table_types;
 pub(crate) mod get_tables;
+pub(crate) mod get_xdbc_type_info;
 pub(crate) mod prepared_statement_query;
+pub(crate) mod statement_ingest;
 pub(crate) mod statement_query;
+pub(crate) mod statement_update;
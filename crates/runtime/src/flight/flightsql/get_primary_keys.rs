@@ -0,0 +1,246 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Int32Array, RecordBatch, StringArray},
+    datatypes::{DataType, Field, Schema},
+};
+use arrow_flight::{
+    flight_service_server::FlightService, sql, FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+};
+use datafusion::{common::Constraint, datasource::TableProvider};
+use spicepod::component::access::Operation;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    auth::Principal,
+    flight::{record_batches_to_flight_stream, to_tonic_err, Service},
+    timing::{TimeMeasurement, TimedStream},
+};
+
+pub(crate) fn get_flight_info(
+    query: &sql::CommandGetPrimaryKeys,
+    request: Request<FlightDescriptor>,
+) -> Response<FlightInfo> {
+    let fd = request.into_inner();
+    tracing::trace!("get_flight_info: {query:?}");
+    Response::new(FlightInfo {
+        flight_descriptor: Some(fd.clone()),
+        endpoint: vec![FlightEndpoint {
+            ticket: Some(Ticket { ticket: fd.cmd }),
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+pub(crate) async fn do_get(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    query: sql::CommandGetPrimaryKeys,
+) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let start = TimeMeasurement::new("flight_do_get_primary_keys_duration_ms", vec![]);
+    tracing::trace!("do_get_primary_keys: {query:?}");
+
+    let tables = find_tables(
+        flight_svc,
+        query.catalog.as_deref(),
+        query.db_schema.as_deref(),
+        &query.table,
+    )
+    .await?;
+
+    let mut catalog_names = vec![];
+    let mut db_schema_names = vec![];
+    let mut table_names = vec![];
+    let mut column_names = vec![];
+    let mut key_sequences = vec![];
+
+    for (catalog_name, schema_name, table_provider) in tables {
+        let dataset = if catalog_name == "datafusion" && schema_name == "public" {
+            query.table.clone()
+        } else {
+            format!("{catalog_name}.{schema_name}.{}", query.table)
+        };
+        if flight_svc
+            .authorize(principal, &dataset, Operation::Read)
+            .is_err()
+        {
+            continue;
+        }
+
+        let Some(constraints) = table_provider.constraints() else {
+            continue;
+        };
+        let schema = table_provider.schema();
+
+        for constraint in constraints.iter() {
+            let Constraint::PrimaryKey(indices) = constraint else {
+                continue;
+            };
+
+            for (sequence, index) in indices.iter().enumerate() {
+                let Some(field) = schema.fields().get(*index) else {
+                    continue;
+                };
+                catalog_names.push(catalog_name.clone());
+                db_schema_names.push(schema_name.clone());
+                table_names.push(query.table.clone());
+                column_names.push(field.name().clone());
+                key_sequences.push(i32::try_from(sequence + 1).map_err(to_tonic_err)?);
+            }
+        }
+    }
+
+    let key_names: Vec<Option<String>> = vec![None; column_names.len()];
+
+    let record_batch = RecordBatch::try_new(
+        Arc::new(primary_keys_schema()),
+        vec![
+            Arc::new(StringArray::from(catalog_names)),
+            Arc::new(StringArray::from(db_schema_names)),
+            Arc::new(StringArray::from(table_names)),
+            Arc::new(StringArray::from(column_names)),
+            Arc::new(StringArray::from(key_names)),
+            Arc::new(Int32Array::from(key_sequences)),
+        ],
+    )
+    .map_err(to_tonic_err)?;
+
+    Ok(Response::new(Box::pin(TimedStream::new(
+        record_batches_to_flight_stream(vec![record_batch]),
+        move || start,
+    ))
+        as <Service as FlightService>::DoGetStream))
+}
+
+/// Finds every table named `table_name`, optionally restricted to a catalog and schema.
+///
+/// Returns the catalog name, schema name and provider of each matching table.
+pub(crate) async fn find_tables(
+    flight_svc: &Service,
+    catalog: Option<&str>,
+    db_schema: Option<&str>,
+    table_name: &str,
+) -> Result<Vec<(String, String, Arc<dyn TableProvider>)>, Status> {
+    let ctx = Arc::clone(&flight_svc.datafusion.read().await.ctx);
+
+    let catalog_names = match catalog {
+        Some(catalog) => vec![catalog.to_string()],
+        None => ctx.catalog_names(),
+    };
+
+    let mut tables = vec![];
+    for catalog_name in catalog_names {
+        let Some(catalog_provider) = ctx.catalog(&catalog_name) else {
+            continue;
+        };
+
+        let schema_names = match db_schema {
+            Some(db_schema) => vec![db_schema.to_string()],
+            None => catalog_provider.schema_names(),
+        };
+
+        for schema_name in schema_names {
+            let Some(schema_provider) = catalog_provider.schema(&schema_name) else {
+                continue;
+            };
+
+            if let Some(table_provider) = schema_provider
+                .table(table_name)
+                .await
+                .map_err(to_tonic_err)?
+            {
+                tables.push((catalog_name.clone(), schema_name, table_provider));
+            }
+        }
+    }
+
+    Ok(tables)
+}
+
+/// The schema of the `CommandGetPrimaryKeys` result, as defined by the Flight SQL protocol.
+fn primary_keys_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("catalog_name", DataType::Utf8, true),
+        Field::new("db_schema_name", DataType::Utf8, true),
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("column_name", DataType::Utf8, false),
+        Field::new("key_name", DataType::Utf8, true),
+        Field::new("key_sequence", DataType::Int32, false),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{datafusion::DataFusion, flight::collect_flight_stream};
+    use arrow::array::Array;
+    use datafusion::{common::Constraints, datasource::MemTable};
+
+    #[tokio::test]
+    async fn test_primary_keys() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("tenant", DataType::Utf8, false),
+            Field::new("value", DataType::Int32, true),
+            Field::new("id", DataType::Int32, false),
+        ]));
+        let table = MemTable::try_new(schema, vec![vec![]])
+            .expect("valid table")
+            .with_constraints(Constraints::new_unverified(vec![Constraint::PrimaryKey(
+                vec![2, 0],
+            )]));
+        let df = DataFusion::new();
+        df.ctx
+            .register_table("t", Arc::new(table))
+            .expect("table registers");
+        let service = Service::for_tests(df);
+
+        let (schema, batches) = collect_flight_stream(
+            do_get(
+                &service,
+                None,
+                sql::CommandGetPrimaryKeys {
+                    catalog: None,
+                    db_schema: None,
+                    table: "t".to_string(),
+                },
+            )
+            .await
+            .expect("primary keys"),
+        )
+        .await;
+        assert_eq!(schema, primary_keys_schema());
+
+        let batch = batches.first().expect("a batch");
+        let column_names = batch
+            .column_by_name("column_name")
+            .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+            .expect("column_name column");
+        let key_sequences = batch
+            .column_by_name("key_sequence")
+            .and_then(|column| column.as_any().downcast_ref::<Int32Array>())
+            .expect("key_sequence column");
+        assert_eq!(
+            (0..column_names.len())
+                .map(|row| (column_names.value(row), key_sequences.value(row)))
+                .collect::<Vec<_>>(),
+            vec![("id", 1), ("tenant", 2)]
+        );
+    }
+}
@@ -383,7 +383,7 @@ const SQL_INFO_DATE_TIME_FUNCTIONS: &[&str] = &[
 
 const SQL_INFO_SYSTEM_FUNCTIONS: &[&str] = &["array", "arrow_typeof", "struct"];
 
-static SQL_DATA_TYPE_TO_ARROW_DATA_TYPE: Lazy<HashMap<SqlSupportsConvert, DataType>> =
+pub(crate) static SQL_DATA_TYPE_TO_ARROW_DATA_TYPE: Lazy<HashMap<SqlSupportsConvert, DataType>> =
     Lazy::new(|| {
         [
             // Referenced from DataFusion data types
@@ -0,0 +1,280 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use arrow::{datatypes::DataType, error::ArrowError};
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{
+        self,
+        metadata::{XdbcTypeInfo, XdbcTypeInfoData, XdbcTypeInfoDataBuilder},
+        Nullable, Searchable, SqlSupportsConvert, XdbcDataType, XdbcDatetimeSubcode,
+    },
+    FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    flight::{
+        flightsql::get_sql_info::SQL_DATA_TYPE_TO_ARROW_DATA_TYPE, record_batches_to_flight_stream,
+        to_tonic_err, Service,
+    },
+    timing::{TimeMeasurement, TimedStream},
+};
+
+pub(crate) fn get_flight_info(
+    query: &sql::CommandGetXdbcTypeInfo,
+    request: Request<FlightDescriptor>,
+) -> Response<FlightInfo> {
+    let fd = request.into_inner();
+    tracing::trace!("get_flight_info: {query:?}");
+    Response::new(FlightInfo {
+        flight_descriptor: Some(fd.clone()),
+        endpoint: vec![FlightEndpoint {
+            ticket: Some(Ticket { ticket: fd.cmd }),
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+pub(crate) fn do_get(
+    query: sql::CommandGetXdbcTypeInfo,
+) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let start = TimeMeasurement::new("flight_do_get_xdbc_type_info_duration_ms", vec![]);
+    tracing::trace!("do_get_xdbc_type_info: {query:?}");
+
+    let type_info = xdbc_type_info_data()
+        .map_err(|e| Status::internal(format!("Unable to build the XDBC type info: {e}")))?;
+    let record_batch = query
+        .into_builder(&type_info)
+        .build()
+        .map_err(to_tonic_err)?;
+
+    Ok(Response::new(Box::pin(TimedStream::new(
+        record_batches_to_flight_stream(vec![record_batch]),
+        move || start,
+    ))
+        as <Service as FlightService>::DoGetStream))
+}
+
+/// The SQL types advertised through `SqlInfo`, described as XDBC types.
+fn xdbc_type_info_data() -> Result<XdbcTypeInfoData, ArrowError> {
+    let mut types: Vec<_> = SQL_DATA_TYPE_TO_ARROW_DATA_TYPE
+        .iter()
+        .filter_map(|(sql_type, arrow_type)| xdbc_type_info(*sql_type, arrow_type))
+        .collect();
+    types.sort_by(|a, b| a.type_name.cmp(&b.type_name));
+
+    let mut builder = XdbcTypeInfoDataBuilder::new();
+    for type_info in types {
+        builder.append(type_info);
+    }
+
+    builder.build()
+}
+
+fn xdbc_type_info(sql_type: SqlSupportsConvert, arrow_type: &DataType) -> Option<XdbcTypeInfo> {
+    let (type_name, data_type, sql_data_type, datetime_subcode) = match sql_type {
+        SqlSupportsConvert::SqlConvertBigint => (
+            "BIGINT",
+            XdbcDataType::XdbcBigint,
+            XdbcDataType::XdbcBigint,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertBit => (
+            "BOOLEAN",
+            XdbcDataType::XdbcBit,
+            XdbcDataType::XdbcBit,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertChar => {
+            ("CHAR", XdbcDataType::XdbcChar, XdbcDataType::XdbcChar, None)
+        }
+        SqlSupportsConvert::SqlConvertDate => (
+            "DATE",
+            XdbcDataType::XdbcDate,
+            XdbcDataType::XdbcDatetime,
+            Some(XdbcDatetimeSubcode::XdbcSubcodeDate),
+        ),
+        SqlSupportsConvert::SqlConvertDecimal => (
+            "DECIMAL",
+            XdbcDataType::XdbcDecimal,
+            XdbcDataType::XdbcDecimal,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertFloat => (
+            "FLOAT",
+            XdbcDataType::XdbcFloat,
+            XdbcDataType::XdbcFloat,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertInteger => (
+            "INTEGER",
+            XdbcDataType::XdbcInteger,
+            XdbcDataType::XdbcInteger,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertIntervalDayTime => (
+            "INTERVAL DAY TO SECOND",
+            XdbcDataType::XdbcInterval,
+            XdbcDataType::XdbcInterval,
+            Some(XdbcDatetimeSubcode::XdbcSubcodeDayToSecond),
+        ),
+        SqlSupportsConvert::SqlConvertIntervalYearMonth => (
+            "INTERVAL YEAR TO MONTH",
+            XdbcDataType::XdbcInterval,
+            XdbcDataType::XdbcInterval,
+            Some(XdbcDatetimeSubcode::XdbcSubcodeYearToMonth),
+        ),
+        SqlSupportsConvert::SqlConvertLongvarchar => (
+            "LONG VARCHAR",
+            XdbcDataType::XdbcLongvarchar,
+            XdbcDataType::XdbcLongvarchar,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertNumeric => (
+            "NUMERIC",
+            XdbcDataType::XdbcNumeric,
+            XdbcDataType::XdbcNumeric,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertReal => {
+            ("REAL", XdbcDataType::XdbcReal, XdbcDataType::XdbcReal, None)
+        }
+        SqlSupportsConvert::SqlConvertSmallint => (
+            "SMALLINT",
+            XdbcDataType::XdbcSmallint,
+            XdbcDataType::XdbcSmallint,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertTime => (
+            "TIME",
+            XdbcDataType::XdbcTime,
+            XdbcDataType::XdbcDatetime,
+            Some(XdbcDatetimeSubcode::XdbcSubcodeTime),
+        ),
+        SqlSupportsConvert::SqlConvertTimestamp => (
+            "TIMESTAMP",
+            XdbcDataType::XdbcTimestamp,
+            XdbcDataType::XdbcDatetime,
+            Some(XdbcDatetimeSubcode::XdbcSubcodeTimestamp),
+        ),
+        SqlSupportsConvert::SqlConvertTinyint => (
+            "TINYINT",
+            XdbcDataType::XdbcTinyint,
+            XdbcDataType::XdbcTinyint,
+            None,
+        ),
+        SqlSupportsConvert::SqlConvertVarchar => (
+            "VARCHAR",
+            XdbcDataType::XdbcVarchar,
+            XdbcDataType::XdbcVarchar,
+            None,
+        ),
+        _ => return None,
+    };
+
+    let is_character = matches!(arrow_type, DataType::Utf8 | DataType::LargeUtf8);
+    let is_numeric = arrow_type.is_numeric();
+    let literal_quote = (is_character || datetime_subcode.is_some()).then(|| "'".to_string());
+    let (column_size, minimum_scale, maximum_scale) = match arrow_type {
+        DataType::Decimal128(precision, scale) => (
+            Some(i32::from(*precision)),
+            Some(0),
+            Some(i32::from(*scale)),
+        ),
+        _ => (numeric_precision(arrow_type), None, None),
+    };
+
+    Some(XdbcTypeInfo {
+        type_name: type_name.to_string(),
+        data_type,
+        column_size,
+        literal_prefix: literal_quote.clone(),
+        literal_suffix: literal_quote,
+        create_params: matches!(arrow_type, DataType::Decimal128(..))
+            .then(|| vec!["precision".to_string(), "scale".to_string()]),
+        nullable: Nullable::NullabilityNullable,
+        case_sensitive: is_character,
+        searchable: if is_character {
+            Searchable::Full
+        } else {
+            Searchable::Basic
+        },
+        unsigned_attribute: is_numeric.then_some(false),
+        fixed_prec_scale: matches!(arrow_type, DataType::Decimal128(..)),
+        auto_increment: is_numeric.then_some(false),
+        local_type_name: Some(type_name.to_string()),
+        minimum_scale,
+        maximum_scale,
+        sql_data_type,
+        datetime_subcode,
+        num_prec_radix: is_numeric.then_some(if matches!(arrow_type, DataType::Decimal128(..)) {
+            10
+        } else {
+            2
+        }),
+        interval_precision: None,
+    })
+}
+
+/// The binary precision of a numeric type: the bit width for integers and the mantissa bits for floats.
+fn numeric_precision(data_type: &DataType) -> Option<i32> {
+    match data_type {
+        DataType::Int8 => Some(8),
+        DataType::Int16 => Some(16),
+        DataType::Float32 => Some(24),
+        DataType::Int32 => Some(32),
+        DataType::Float64 => Some(53),
+        DataType::Int64 => Some(64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, StringArray};
+
+    #[test]
+    fn test_xdbc_type_info() {
+        let type_info = xdbc_type_info_data().expect("type info builds");
+
+        let all = sql::CommandGetXdbcTypeInfo { data_type: None }
+            .into_builder(&type_info)
+            .build()
+            .expect("all types");
+        assert!(all.num_rows() > 0);
+        let type_names = all
+            .column_by_name("type_name")
+            .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+            .expect("type_name column");
+        let type_names: Vec<_> = (0..type_names.len())
+            .map(|row| type_names.value(row))
+            .collect();
+        let mut sorted = type_names.clone();
+        sorted.sort_unstable();
+        assert_eq!(type_names, sorted);
+
+        let integer = sql::CommandGetXdbcTypeInfo {
+            data_type: Some(XdbcDataType::XdbcInteger as i32),
+        }
+        .into_builder(&type_info)
+        .build()
+        .expect("integer type");
+        assert_eq!(integer.num_rows(), 1);
+    }
+}
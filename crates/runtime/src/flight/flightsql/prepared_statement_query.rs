@@ -14,60 +14,322 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use arrow::{
+    array::RecordBatch,
+    compute::cast,
+    datatypes::{DataType, Field, Schema},
+};
 use arrow_flight::{
     flight_service_server::FlightService,
     sql::{self, ProstMessageExt},
-    FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+    FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, PutResult, Ticket,
 };
+use datafusion::{
+    common::ParamValues, dataframe::DataFrame, logical_expr::LogicalPlan,
+    physical_plan::stream::RecordBatchStreamAdapter, scalar::ScalarValue,
+};
+use futures::{stream, StreamExt, TryStreamExt};
 use prost::Message;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
 
 use crate::{
-    flight::{to_tonic_err, Service},
+    auth::Principal,
+    flight::{
+        decode_put_stream, flightsql::statement_update, handle_datafusion_error, to_tonic_err,
+        Service,
+    },
     timing::{TimeMeasurement, TimedStream},
 };
 
+/// Prepared statements that haven't been used for this long are dropped, so clients that never close them don't hold
+/// server memory indefinitely.
+const PREPARED_STATEMENT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A prepared statement created through `CreatePreparedStatement`, held on the server until it is closed or expires.
+#[derive(Debug, Clone)]
+pub(crate) struct PreparedStatement {
+    pub(crate) query: String,
+    pub(crate) dataset_schema: Schema,
+    /// The inferred type of each `$n` placeholder, in order. `None` if the type could not be inferred.
+    pub(crate) parameter_types: Vec<Option<DataType>>,
+    /// Parameter rows bound via `DoPut`. The query is executed once per row.
+    pub(crate) parameters: Vec<Vec<ScalarValue>>,
+    /// The handle of the statement these parameters were bound to. Every bind creates a new handle, so clients
+    /// sharing a statement don't overwrite each other's parameters.
+    pub(crate) bound_from: Option<String>,
+    pub(crate) last_used: Instant,
+}
+
+impl PreparedStatement {
+    fn parameter_schema(&self) -> Schema {
+        Schema::new(
+            self.parameter_types
+                .iter()
+                .enumerate()
+                .map(|(i, data_type)| {
+                    Field::new(
+                        format!("${}", i + 1),
+                        data_type.clone().unwrap_or(DataType::Utf8),
+                        true,
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
 /// Create a prepared statement from given SQL statement.
 pub(crate) async fn do_action_create_prepared_statement(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     statement: sql::ActionCreatePreparedStatementRequest,
 ) -> Result<sql::ActionCreatePreparedStatementResult, Status> {
     tracing::trace!("do_action_create_prepared_statement: {statement:?}");
-    let arrow_schema =
-        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), statement.query.clone())
-            .await
-            .map_err(to_tonic_err)?;
+    let ctx = Arc::clone(&flight_svc.datafusion.read().await.ctx);
+    let plan = ctx
+        .state()
+        .create_logical_plan(&statement.query)
+        .await
+        .map_err(handle_datafusion_error)?;
+    flight_svc.authorize_plan(principal, &plan)?;
+
+    let parameter_types = positional_parameter_types(
+        plan.get_parameter_types()
+            .map_err(handle_datafusion_error)?,
+    )?;
+
+    let prepared_statement = PreparedStatement {
+        query: statement.query,
+        dataset_schema: plan.schema().as_ref().into(),
+        parameter_types,
+        parameters: vec![],
+        bound_from: None,
+        last_used: Instant::now(),
+    };
 
-    let schema_bytes = Service::serialize_schema(&arrow_schema)?;
+    let dataset_schema = Service::serialize_schema(&prepared_statement.dataset_schema)?;
+    let parameter_schema = Service::serialize_schema(&prepared_statement.parameter_schema())?;
+
+    let handle = Uuid::new_v4().to_string();
+    let mut prepared_statements = flight_svc.prepared_statements.write().await;
+    remove_expired(&mut prepared_statements);
+    prepared_statements.insert(handle.clone(), prepared_statement);
 
     Ok(sql::ActionCreatePreparedStatementResult {
-        prepared_statement_handle: statement.query.into(),
-        dataset_schema: schema_bytes,
-        ..Default::default()
+        prepared_statement_handle: handle.into(),
+        dataset_schema,
+        parameter_schema,
     })
 }
 
+/// The type of each `$n` placeholder, indexed by `n`. Placeholders must be numbered from `$1` without gaps, so
+/// the columns of the bound parameter batch line up with them.
+fn positional_parameter_types(
+    parameter_types: HashMap<String, Option<DataType>>,
+) -> Result<Vec<Option<DataType>>, Status> {
+    let mut positional = vec![None; parameter_types.len()];
+    let mut seen = vec![false; parameter_types.len()];
+    for (id, data_type) in parameter_types {
+        let position = id
+            .strip_prefix('$')
+            .and_then(|position| position.parse::<usize>().ok())
+            .filter(|position| (1..=positional.len()).contains(position))
+            .ok_or_else(|| {
+                Status::invalid_argument(format!(
+                    "Unsupported placeholder {id}, parameters must be numbered $1 to ${} without gaps",
+                    positional.len()
+                ))
+            })?;
+        positional[position - 1] = data_type;
+        seen[position - 1] = true;
+    }
+    if let Some(missing) = seen.iter().position(|seen| !seen) {
+        return Err(Status::invalid_argument(format!(
+            "Placeholder ${} is missing, parameters must be numbered $1 to ${} without gaps",
+            missing + 1,
+            positional.len()
+        )));
+    }
+    Ok(positional)
+}
+
+fn remove_expired(prepared_statements: &mut HashMap<String, PreparedStatement>) {
+    prepared_statements.retain(|_, prepared_statement| {
+        prepared_statement.last_used.elapsed() < PREPARED_STATEMENT_TTL
+    });
+}
+
+/// Release the server-side resources held by a prepared statement, including the handles its parameters were bound
+/// to.
+pub(crate) async fn do_action_close_prepared_statement(
+    flight_svc: &Service,
+    statement: sql::ActionClosePreparedStatementRequest,
+) -> Result<(), Status> {
+    tracing::trace!("do_action_close_prepared_statement: {statement:?}");
+    let handle = parse_handle(&statement.prepared_statement_handle)?;
+    flight_svc
+        .prepared_statements
+        .write()
+        .await
+        .retain(|held_handle, prepared_statement| {
+            *held_handle != handle && prepared_statement.bound_from.as_ref() != Some(&handle)
+        });
+    Ok(())
+}
+
+/// Bind the parameters sent by the client to the prepared statement. The first `FlightData` message
+/// (already read from the stream by the caller) carries the parameter schema.
+///
+/// The bound statement is held under a new handle, returned in a `DoPutPreparedStatementResult`, and the statement
+/// the client bound to is left unchanged.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    query: sql::CommandPreparedStatementQuery,
+    first_message: FlightData,
+    streaming_flight: Streaming<FlightData>,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put: {query:?}");
+    let handle = parse_handle(&query.prepared_statement_handle)?;
+
+    let batches = decode_put_stream(first_message, streaming_flight).await?;
+
+    let prepared_statement =
+        get_prepared_statement(flight_svc, &query.prepared_statement_handle).await?;
+    authorized_plan(flight_svc, principal, &prepared_statement).await?;
+    let parameters = bind_parameters(&batches, &prepared_statement.parameter_types)?;
+
+    let bound_handle = Uuid::new_v4().to_string();
+    let bound_from = prepared_statement.bound_from.clone().unwrap_or(handle);
+    flight_svc.prepared_statements.write().await.insert(
+        bound_handle.clone(),
+        PreparedStatement {
+            parameters,
+            bound_from: Some(bound_from),
+            last_used: Instant::now(),
+            ..prepared_statement
+        },
+    );
+
+    let result = sql::DoPutPreparedStatementResult {
+        prepared_statement_handle: Some(bound_handle.into()),
+    };
+    Ok(Response::new(
+        stream::iter(vec![Ok(PutResult {
+            app_metadata: result.encode_to_vec().into(),
+        })])
+        .boxed(),
+    ))
+}
+
+/// Execute a prepared `INSERT` or `DELETE` statement once for each parameter row sent with it, or with the
+/// parameters bound to its handle if none are sent, returning the number of affected rows.
+pub(crate) async fn do_put_update(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    command: sql::CommandPreparedStatementUpdate,
+    first_message: FlightData,
+    streaming_flight: Streaming<FlightData>,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put_update: {command:?}");
+    let prepared_statement =
+        get_prepared_statement(flight_svc, &command.prepared_statement_handle).await?;
+    let plan = authorized_plan(flight_svc, principal, &prepared_statement).await?;
+
+    // A statement without parameters is sent as a descriptor alone, without a schema.
+    let parameters = if first_message.data_header.is_empty() {
+        prepared_statement.parameters
+    } else {
+        let batches = decode_put_stream(first_message, streaming_flight).await?;
+        bind_parameters(&batches, &prepared_statement.parameter_types)?
+    };
+
+    let mut record_count = 0;
+    if parameters.is_empty() {
+        record_count = statement_update::execute(flight_svc, plan).await?;
+    } else {
+        for row in parameters {
+            let plan = plan
+                .clone()
+                .with_param_values(ParamValues::List(row))
+                .map_err(handle_datafusion_error)?;
+            record_count += statement_update::execute(flight_svc, plan).await?;
+        }
+    }
+
+    Ok(statement_update::update_result(record_count))
+}
+
+/// Convert the rows of every bound parameter batch into the values for the statement's placeholders.
+fn bind_parameters(
+    batches: &[RecordBatch],
+    parameter_types: &[Option<DataType>],
+) -> Result<Vec<Vec<ScalarValue>>, Status> {
+    let mut parameters = vec![];
+    for batch in batches {
+        parameters.extend(batch_to_parameter_rows(batch, parameter_types)?);
+    }
+    Ok(parameters)
+}
+
+/// Convert each row of the bound parameter batch into the values for the statement's placeholders.
+fn batch_to_parameter_rows(
+    batch: &RecordBatch,
+    parameter_types: &[Option<DataType>],
+) -> Result<Vec<Vec<ScalarValue>>, Status> {
+    if batch.num_columns() != parameter_types.len() {
+        return Err(Status::invalid_argument(format!(
+            "Expected {} parameters, received {}",
+            parameter_types.len(),
+            batch.num_columns()
+        )));
+    }
+
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(parameter_types)
+        .map(|(column, data_type)| match data_type {
+            Some(data_type) if column.data_type() != data_type => {
+                cast(column, data_type).map_err(|e| {
+                    Status::invalid_argument(format!(
+                        "Unable to cast parameter to {data_type}: {e}"
+                    ))
+                })
+            }
+            _ => Ok(Arc::clone(column)),
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+
+    (0..batch.num_rows())
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| ScalarValue::try_from_array(column, row).map_err(to_tonic_err))
+                .collect::<Result<Vec<_>, Status>>()
+        })
+        .collect()
+}
+
 pub(crate) async fn get_flight_info(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     handle: sql::CommandPreparedStatementQuery,
     request: Request<FlightDescriptor>,
 ) -> Result<Response<FlightInfo>, Status> {
     tracing::trace!("get_flight_info: {handle:?}");
 
-    let sql = match std::str::from_utf8(&handle.prepared_statement_handle) {
-        Ok(sql) => sql.to_string(),
-        Err(e) => {
-            return Err(Status::invalid_argument(format!(
-                "Invalid prepared statement handle: {e}"
-            )))
-        }
-    };
-
-    let arrow_schema = Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql)
-        .await
-        .map_err(to_tonic_err)?;
+    let prepared_statement =
+        get_prepared_statement(flight_svc, &handle.prepared_statement_handle).await?;
+    authorized_plan(flight_svc, principal, &prepared_statement).await?;
+    let arrow_schema = prepared_statement.dataset_schema;
 
     tracing::trace!("get_flight_info_prepared_statement: arrow_schema={arrow_schema:?}");
 
@@ -86,25 +348,317 @@ pub(crate) async fn get_flight_info(
     Ok(Response::new(info))
 }
 
+pub(crate) async fn get_schema(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    handle: &sql::CommandPreparedStatementQuery,
+) -> Result<Schema, Status> {
+    let prepared_statement =
+        get_prepared_statement(flight_svc, &handle.prepared_statement_handle).await?;
+    authorized_plan(flight_svc, principal, &prepared_statement).await?;
+    Ok(prepared_statement.dataset_schema)
+}
+
+/// Plans the prepared statement, checking that the caller may access every dataset it reads or writes. A handle can
+/// be shared, so the principal that created the statement isn't necessarily the one using it.
+async fn authorized_plan(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    prepared_statement: &PreparedStatement,
+) -> Result<LogicalPlan, Status> {
+    let ctx = Arc::clone(&flight_svc.datafusion.read().await.ctx);
+    let plan = ctx
+        .state()
+        .create_logical_plan(&prepared_statement.query)
+        .await
+        .map_err(handle_datafusion_error)?;
+    flight_svc.authorize_plan(principal, &plan)?;
+    Ok(plan)
+}
+
 pub(crate) async fn do_get(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     query: sql::CommandPreparedStatementQuery,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
-    let datafusion = Arc::clone(&flight_svc.datafusion);
     tracing::trace!("do_get: {query:?}");
-    match std::str::from_utf8(&query.prepared_statement_handle) {
-        Ok(sql) => {
-            let start =
-                TimeMeasurement::new("flight_do_get_prepared_statement_query_duration_ms", vec![]);
-            let output =
-                Box::pin(Service::sql_to_flight_stream(datafusion, sql.to_owned())).await?;
-            let timed_output = TimedStream::new(output, move || start);
-            Ok(Response::new(
-                Box::pin(timed_output) as <Service as FlightService>::DoGetStream
-            ))
+    let prepared_statement =
+        get_prepared_statement(flight_svc, &query.prepared_statement_handle).await?;
+
+    let start = TimeMeasurement::new("flight_do_get_prepared_statement_query_duration_ms", vec![]);
+    let plan = authorized_plan(flight_svc, principal, &prepared_statement).await?;
+    let ctx = Arc::clone(&flight_svc.datafusion.read().await.ctx);
+    let df = ctx
+        .execute_logical_plan(plan)
+        .await
+        .map_err(handle_datafusion_error)?;
+
+    let output = if prepared_statement.parameters.is_empty() {
+        Box::pin(Service::dataframe_to_flight_stream(df)).await?
+    } else {
+        // Each bound parameter row produces its own result set, returned one after the other in the order the rows
+        // were bound.
+        let schema = Arc::new(df.schema().into());
+        let row_dfs = prepared_statement
+            .parameters
+            .into_iter()
+            .map(|row| df.clone().with_param_values(ParamValues::List(row)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(handle_datafusion_error)?;
+        let batches = stream::iter(row_dfs)
+            .then(DataFrame::execute_stream)
+            .try_flatten();
+        Service::batch_stream_to_flight_stream(Box::pin(RecordBatchStreamAdapter::new(
+            schema, batches,
+        )))
+    };
+    let timed_output = TimedStream::new(output, move || start);
+    Ok(Response::new(
+        Box::pin(timed_output) as <Service as FlightService>::DoGetStream
+    ))
+}
+
+async fn get_prepared_statement(
+    flight_svc: &Service,
+    handle: &[u8],
+) -> Result<PreparedStatement, Status> {
+    let handle = parse_handle(handle)?;
+    let mut prepared_statements = flight_svc.prepared_statements.write().await;
+    remove_expired(&mut prepared_statements);
+    let prepared_statement = prepared_statements
+        .get_mut(&handle)
+        .ok_or_else(|| Status::not_found(format!("Prepared statement {handle} not found")))?;
+    prepared_statement.last_used = Instant::now();
+    Ok(prepared_statement.clone())
+}
+
+fn parse_handle(handle: &[u8]) -> Result<String, Status> {
+    std::str::from_utf8(handle)
+        .map(ToString::to_string)
+        .map_err(|e| Status::invalid_argument(format!("Invalid prepared statement handle: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::Authenticator, datafusion::DataFusion, flight::serve_for_tests};
+    use arrow::array::Int64Array;
+    use arrow_flight::{encode::FlightDataEncoderBuilder, error::FlightError, FlightClient};
+    use data_components::{arrow::write::MemTable, delete::DeletionTableProviderAdapter};
+    use spicepod::component::access::{Operation, Role};
+
+    fn service() -> Service {
+        let df = DataFusion::new();
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3]))],
+        )
+        .expect("valid batch");
+        df.ctx.register_batch("t", batch).expect("table registers");
+        Service::for_tests(df)
+    }
+
+    #[test]
+    fn test_positional_parameter_types() {
+        let parameter_types = HashMap::from([
+            ("$2".to_string(), Some(DataType::Utf8)),
+            ("$1".to_string(), Some(DataType::Int64)),
+        ]);
+        assert_eq!(
+            positional_parameter_types(parameter_types).expect("parameters are numbered"),
+            vec![Some(DataType::Int64), Some(DataType::Utf8)]
+        );
+
+        let gaps = HashMap::from([
+            ("$1".to_string(), Some(DataType::Int64)),
+            ("$3".to_string(), Some(DataType::Utf8)),
+        ]);
+        assert!(positional_parameter_types(gaps).is_err());
+    }
+
+    async fn prepare(service: &Service, query: &str) -> sql::CommandPreparedStatementQuery {
+        let created = do_action_create_prepared_statement(
+            service,
+            None,
+            sql::ActionCreatePreparedStatementRequest {
+                query: query.to_string(),
+                transaction_id: None,
+            },
+        )
+        .await
+        .expect("statement is prepared");
+        sql::CommandPreparedStatementQuery {
+            prepared_statement_handle: created.prepared_statement_handle,
         }
-        Err(e) => Err(Status::invalid_argument(format!(
-            "Invalid prepared statement handle: {e}"
-        ))),
+    }
+
+    /// Binds `values` to the statement's `$1` placeholder through `DoPut`, returning the handle of the bound statement.
+    async fn bind(
+        client: &mut FlightClient,
+        query: &sql::CommandPreparedStatementQuery,
+        values: Vec<i64>,
+    ) -> Result<sql::CommandPreparedStatementQuery, FlightError> {
+        let parameters = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("$1", DataType::Int64, true)])),
+            vec![Arc::new(Int64Array::from(values))],
+        )
+        .expect("valid batch");
+        let request = FlightDataEncoderBuilder::new()
+            .with_flight_descriptor(Some(FlightDescriptor::new_cmd(
+                query.as_any().encode_to_vec(),
+            )))
+            .build(stream::iter(vec![Ok(parameters)]));
+        let results: Vec<PutResult> = client.do_put(request).await?.try_collect().await?;
+        let result = results.first().expect("a bind result");
+        let bound = sql::DoPutPreparedStatementResult::decode(result.app_metadata.clone())
+            .expect("valid bind result");
+        Ok(sql::CommandPreparedStatementQuery {
+            prepared_statement_handle: bound
+                .prepared_statement_handle
+                .expect("a bound statement handle"),
+        })
+    }
+
+    async fn execute(
+        client: &mut FlightClient,
+        query: &sql::CommandPreparedStatementQuery,
+    ) -> Vec<i64> {
+        let batches: Vec<RecordBatch> = client
+            .do_get(Ticket {
+                ticket: query.as_any().encode_to_vec().into(),
+            })
+            .await
+            .expect("statement executes")
+            .try_collect()
+            .await
+            .expect("results stream");
+        batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .expect("Int64 column")
+                    .values()
+                    .to_vec()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_bind_execute_and_close() {
+        let service = service();
+        let mut client = serve_for_tests(&service, None).await;
+        let query = prepare(&service, "SELECT a FROM t WHERE a = $1").await;
+
+        // Each bind gets its own handle, and rows come back in the order their parameters were bound.
+        let first = bind(&mut client, &query, vec![3, 1])
+            .await
+            .expect("parameters bind");
+        let second = bind(&mut client, &query, vec![2])
+            .await
+            .expect("parameters bind");
+        assert_eq!(execute(&mut client, &first).await, vec![3, 1]);
+        assert_eq!(execute(&mut client, &second).await, vec![2]);
+
+        do_action_close_prepared_statement(
+            &service,
+            sql::ActionClosePreparedStatementRequest {
+                prepared_statement_handle: query.prepared_statement_handle.clone(),
+            },
+        )
+        .await
+        .expect("statement closes");
+        for query in [query, first, second] {
+            let Err(status) = do_get(&service, None, query).await else {
+                panic!("a closed statement should not execute");
+            };
+            assert_eq!(status.code(), tonic::Code::NotFound);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_requires_read_access() {
+        let mut service = service();
+        service.auth = Some(Arc::new(Authenticator::with_roles(vec![Role {
+            name: "analysts".to_string(),
+            principals: vec!["alice".to_string()],
+            datasets: vec!["t".to_string()],
+            operations: vec![Operation::Read],
+        }])));
+        let query = prepare(&service, "SELECT a FROM t WHERE a = $1").await;
+
+        let principal = |name: &str| {
+            Some(Principal {
+                name: name.to_string(),
+            })
+        };
+        let mut bob = serve_for_tests(&service, principal("bob")).await;
+        let Err(FlightError::Tonic(status)) = bind(&mut bob, &query, vec![1]).await else {
+            panic!("bob may not read t");
+        };
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let mut alice = serve_for_tests(&service, principal("alice")).await;
+        let bound = bind(&mut alice, &query, vec![1])
+            .await
+            .expect("alice may read t");
+        assert_eq!(execute(&mut alice, &bound).await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_prepared_update() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let table = MemTable::try_new(Arc::clone(&schema), vec![vec![]]).expect("valid table");
+        let mut df = DataFusion::new();
+        df.register_writable_table(
+            "t",
+            Arc::new(DeletionTableProviderAdapter::new(Arc::new(table))),
+        )
+        .expect("table registers");
+        let service = Service::for_tests(df);
+        let mut client = serve_for_tests(&service, None).await;
+        let query = prepare(&service, "INSERT INTO t VALUES ($1)").await;
+
+        let parameters = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("$1", DataType::Int64, true)])),
+            vec![Arc::new(Int64Array::from(vec![4, 5]))],
+        )
+        .expect("valid batch");
+        let command = sql::CommandPreparedStatementUpdate {
+            prepared_statement_handle: query.prepared_statement_handle,
+        };
+        let request = FlightDataEncoderBuilder::new()
+            .with_flight_descriptor(Some(FlightDescriptor::new_cmd(
+                command.as_any().encode_to_vec(),
+            )))
+            .build(stream::iter(vec![Ok(parameters)]));
+        let results: Vec<PutResult> = client
+            .do_put(request)
+            .await
+            .expect("update runs")
+            .try_collect()
+            .await
+            .expect("update results");
+        let result = sql::DoPutUpdateResult::decode(
+            results
+                .first()
+                .expect("an update result")
+                .app_metadata
+                .clone(),
+        )
+        .expect("valid update result");
+        assert_eq!(result.record_count, 2);
+
+        let ctx = Arc::clone(&service.datafusion.read().await.ctx);
+        let rows = ctx
+            .sql("SELECT a FROM t")
+            .await
+            .expect("query plans")
+            .count()
+            .await
+            .expect("query runs");
+        assert_eq!(rows, 2);
     }
 }
@@ -0,0 +1,285 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use arrow::{array::RecordBatch, compute::concat_batches};
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{
+        self,
+        command_statement_ingest::{
+            table_definition_options::{TableExistsOption, TableNotExistOption},
+            TableDefinitionOptions,
+        },
+        DoPutUpdateResult,
+    },
+    FlightData, PutResult,
+};
+use futures::{stream, StreamExt};
+use prost::Message;
+use spicepod::component::access::Operation;
+use tonic::{Response, Status, Streaming};
+
+use crate::{
+    auth::Principal,
+    datafusion::DataFusion,
+    dataupdate::{DataUpdate, UpdateType},
+    flight::{decode_put_stream, handle_write_error, Service},
+    timing::TimeMeasurement,
+};
+
+/// Bulk-ingest the record batches of a `DoPut` stream into a writable dataset.
+///
+/// Only existing datasets can be ingested into. A `REPLACE` table exists option overwrites the
+/// dataset, `FAIL` rejects the ingest and any other option appends to it.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    command: sql::CommandStatementIngest,
+    first_message: FlightData,
+    streaming_flight: Streaming<FlightData>,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put_statement_ingest: {command:?}");
+    let _start = TimeMeasurement::new(
+        "flight_do_put_statement_ingest_duration_ms",
+        vec![("table", command.table.clone())],
+    );
+
+    let table_name = match &command.schema {
+        Some(schema) if !schema.is_empty() => format!("{schema}.{}", command.table),
+        _ => command.table.clone(),
+    };
+    flight_svc.authorize(principal, &table_name, Operation::Write)?;
+
+    let batches = decode_put_stream(first_message, streaming_flight).await?;
+
+    let df = flight_svc.datafusion.read().await;
+    let update_type = update_type(&df, &table_name, command.table_definition_options.as_ref())?;
+    let rows = ingest(&df, &table_name, update_type, batches).await?;
+
+    let result = DoPutUpdateResult {
+        record_count: i64::try_from(rows).unwrap_or(i64::MAX),
+    };
+
+    Ok(Response::new(
+        stream::iter(vec![Ok(PutResult {
+            app_metadata: result.encode_to_vec().into(),
+        })])
+        .boxed(),
+    ))
+}
+
+/// How the ingest writes to `table_name`, following the command's table definition options.
+fn update_type(
+    df: &DataFusion,
+    table_name: &str,
+    options: Option<&TableDefinitionOptions>,
+) -> Result<UpdateType, Status> {
+    if !df.table_exists(table_name) {
+        if options.is_some_and(|options| options.if_not_exist == TableNotExistOption::Create as i32)
+        {
+            return Err(Status::unimplemented(format!(
+                "Table {table_name} doesn't exist, creating tables by ingesting is not supported",
+            )));
+        }
+        return Err(Status::not_found(format!(
+            "Table {table_name} doesn't exist"
+        )));
+    }
+    if !df.is_writable(table_name) {
+        return Err(Status::invalid_argument(format!(
+            "Table {table_name} is not writable",
+        )));
+    }
+
+    match options.map(|options| options.if_exists) {
+        Some(if_exists) if if_exists == TableExistsOption::Fail as i32 => Err(
+            Status::already_exists(format!("Table {table_name} already exists")),
+        ),
+        Some(if_exists) if if_exists == TableExistsOption::Replace as i32 => {
+            Ok(UpdateType::Overwrite)
+        }
+        _ => Ok(UpdateType::Append),
+    }
+}
+
+/// Writes the ingested `batches` to `table_name` in a single write, returning the number of rows written.
+async fn ingest(
+    df: &DataFusion,
+    table_name: &str,
+    update_type: UpdateType,
+    batches: Vec<RecordBatch>,
+) -> Result<usize, Status> {
+    let Some(first_batch) = batches.first() else {
+        return Err(Status::invalid_argument("No data provided to ingest"));
+    };
+    let schema = first_batch.schema();
+    let batch = concat_batches(&schema, &batches)
+        .map_err(|e| Status::invalid_argument(format!("Unable to combine batches: {e}")))?;
+
+    let written = df
+        .write_data(
+            table_name,
+            DataUpdate {
+                schema: Arc::clone(&schema),
+                data: vec![batch],
+                update_type,
+            },
+            None,
+        )
+        .await
+        .map_err(handle_write_error)?;
+
+    Ok(written.rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::{
+        array::Int64Array,
+        datatypes::{DataType, Field, Schema},
+    };
+    use data_components::{arrow::write::MemTable, delete::DeletionTableProviderAdapter};
+
+    fn batch(values: Vec<i64>) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(values))],
+        )
+        .expect("valid batch")
+    }
+
+    async fn values(df: &DataFusion) -> Vec<i64> {
+        let batches = df
+            .ctx
+            .sql("SELECT a FROM t ORDER BY a")
+            .await
+            .expect("query plans")
+            .collect()
+            .await
+            .expect("query runs");
+        batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .expect("Int64 column")
+                    .values()
+                    .to_vec()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_ingest() {
+        let initial = batch(vec![1]);
+        let table = MemTable::try_new(initial.schema(), vec![vec![initial]]).expect("valid table");
+        let mut df = DataFusion::new();
+        df.register_writable_table(
+            "t",
+            Arc::new(DeletionTableProviderAdapter::new(Arc::new(table))),
+        )
+        .expect("table registers");
+
+        let rows = ingest(
+            &df,
+            "t",
+            UpdateType::Append,
+            vec![batch(vec![2, 3]), batch(vec![4])],
+        )
+        .await
+        .expect("batches are ingested");
+        assert_eq!(rows, 3);
+        assert_eq!(values(&df).await, vec![1, 2, 3, 4]);
+
+        let rows = ingest(&df, "t", UpdateType::Overwrite, vec![batch(vec![5])])
+            .await
+            .expect("batches are ingested");
+        assert_eq!(rows, 1);
+        assert_eq!(values(&df).await, vec![5]);
+
+        assert!(
+            ingest(&df, "missing", UpdateType::Append, vec![batch(vec![6])])
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_table_definition_options() {
+        let initial = batch(vec![1]);
+        let table = MemTable::try_new(initial.schema(), vec![vec![initial]]).expect("valid table");
+        let mut df = DataFusion::new();
+        df.register_writable_table(
+            "t",
+            Arc::new(DeletionTableProviderAdapter::new(Arc::new(table))),
+        )
+        .expect("table registers");
+
+        let options = |if_exists: TableExistsOption, if_not_exist: TableNotExistOption| {
+            TableDefinitionOptions {
+                if_exists: if_exists as i32,
+                if_not_exist: if_not_exist as i32,
+            }
+        };
+
+        assert!(matches!(
+            update_type(&df, "t", None),
+            Ok(UpdateType::Append)
+        ));
+        assert!(matches!(
+            update_type(
+                &df,
+                "t",
+                Some(&options(
+                    TableExistsOption::Replace,
+                    TableNotExistOption::Fail
+                ))
+            ),
+            Ok(UpdateType::Overwrite)
+        ));
+
+        let Err(status) = update_type(
+            &df,
+            "t",
+            Some(&options(TableExistsOption::Fail, TableNotExistOption::Fail)),
+        ) else {
+            panic!("FAIL should reject an existing table");
+        };
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let Err(status) = update_type(&df, "missing", None) else {
+            panic!("a missing table can't be ingested into");
+        };
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let Err(status) = update_type(
+            &df,
+            "missing",
+            Some(&options(
+                TableExistsOption::Append,
+                TableNotExistOption::Create,
+            )),
+        ) else {
+            panic!("tables can't be created by ingesting");
+        };
+        assert_eq!(status.code(), tonic::Code::Unimplemented);
+    }
+}
@@ -14,8 +14,6 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::sync::Arc;
-
 use arrow_flight::{
     flight_service_server::FlightService,
     sql::{self, ProstMessageExt},
@@ -25,6 +23,7 @@ use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::{
+    auth::Principal,
     flight::{to_tonic_err, Service},
     timing::{TimeMeasurement, TimedStream},
 };
@@ -32,6 +31,7 @@ use crate::{
 /// Get a `FlightInfo` for executing a SQL query.
 pub(crate) async fn get_flight_info(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     query: sql::CommandStatementQuery,
     request: Request<FlightDescriptor>,
 ) -> Result<Response<FlightInfo>, Status> {
@@ -39,10 +39,9 @@ pub(crate) async fn get_flight_info(
 
     let sql = query.query.as_str();
 
-    let arrow_schema =
-        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql.to_string())
-            .await
-            .map_err(to_tonic_err)?;
+    let arrow_schema = flight_svc
+        .get_arrow_schema(principal, sql.to_string())
+        .await?;
 
     let fd = request.into_inner();
 
@@ -61,12 +60,12 @@ pub(crate) async fn get_flight_info(
 
 pub(crate) async fn do_get(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     cmd: sql::CommandStatementQuery,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
-    let datafusion = Arc::clone(&flight_svc.datafusion);
     tracing::trace!("do_get_statement: {cmd:?}");
     let start = TimeMeasurement::new("flight_do_get_statement_query_duration_ms", vec![]);
-    let output = Box::pin(Service::sql_to_flight_stream(datafusion, cmd.query)).await?;
+    let output = Box::pin(flight_svc.sql_to_flight_stream(principal, cmd.query)).await?;
     let timed_output = TimedStream::new(output, move || start);
     Ok(Response::new(
         Box::pin(timed_output) as <Service as FlightService>::DoGetStream
@@ -0,0 +1,300 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{self, DoPutUpdateResult},
+    PutResult,
+};
+use datafusion::logical_expr::{
+    expr_rewriter::unnormalize_col, DmlStatement, Expr, LogicalPlan, WriteOp,
+};
+use futures::{stream, StreamExt};
+use prost::Message;
+use tonic::{Response, Status};
+
+use crate::{
+    auth::Principal,
+    dataupdate::{DataUpdate, UpdateType},
+    flight::{handle_datafusion_error, handle_write_error, Service},
+    timing::TimeMeasurement,
+};
+
+/// Execute an `INSERT` or `DELETE` statement against a writable dataset, returning the number of affected rows.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    command: sql::CommandStatementUpdate,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put_statement_update: {command:?}");
+    let _start = TimeMeasurement::new("flight_do_put_statement_update_duration_ms", vec![]);
+
+    let ctx = Arc::clone(&flight_svc.datafusion.read().await.ctx);
+    let plan = ctx
+        .state()
+        .create_logical_plan(&command.query)
+        .await
+        .map_err(handle_datafusion_error)?;
+    flight_svc.authorize_plan(principal, &plan)?;
+
+    let record_count = execute(flight_svc, plan).await?;
+    Ok(update_result(record_count))
+}
+
+/// Runs an `INSERT` or `DELETE` plan that the caller has already been authorized for, returning the number of
+/// affected rows.
+///
+/// `UPDATE` isn't supported: datasets are written by appending, overwriting or deleting, so the updated rows can't be
+/// rewritten in a single write.
+pub(crate) async fn execute(flight_svc: &Service, plan: LogicalPlan) -> Result<u64, Status> {
+    let LogicalPlan::Dml(DmlStatement {
+        table_name,
+        op,
+        input,
+        ..
+    }) = plan
+    else {
+        return Err(Status::invalid_argument(
+            "Only INSERT and DELETE statements can be executed as updates",
+        ));
+    };
+    let table_name = table_name.to_string();
+
+    let df = flight_svc.datafusion.read().await;
+    let ctx = Arc::clone(&df.ctx);
+    if !df.is_writable(&table_name) {
+        return Err(Status::invalid_argument(format!(
+            "Path doesn't exist or is not writable: {table_name}",
+        )));
+    }
+
+    let record_count = match op {
+        WriteOp::InsertInto | WriteOp::InsertOverwrite => {
+            let update_type = if op == WriteOp::InsertOverwrite {
+                UpdateType::Overwrite
+            } else {
+                UpdateType::Append
+            };
+
+            // The planner has already projected and cast the inserted values to the table schema.
+            let schema = Arc::new(input.schema().as_ref().into());
+            let data = ctx
+                .execute_logical_plan(input.as_ref().clone())
+                .await
+                .map_err(handle_datafusion_error)?
+                .collect()
+                .await
+                .map_err(handle_datafusion_error)?;
+
+            let written = df
+                .write_data(
+                    &table_name,
+                    DataUpdate {
+                        schema,
+                        data,
+                        update_type,
+                    },
+                    None,
+                )
+                .await
+                .map_err(handle_write_error)?;
+
+            written.rows as u64
+        }
+        WriteOp::Delete => {
+            let filters = delete_filters(&input)?;
+            df.delete_data(&table_name, &filters)
+                .await
+                .map_err(handle_write_error)?
+        }
+        WriteOp::Update => {
+            return Err(Status::unimplemented(
+                "UPDATE statements are not supported, use DELETE and INSERT instead",
+            ))
+        }
+        _ => {
+            return Err(Status::unimplemented(format!(
+                "{op} statements are not supported"
+            )))
+        }
+    };
+
+    Ok(record_count)
+}
+
+/// The `DoPut` response reporting the number of rows an update affected.
+pub(crate) fn update_result(
+    record_count: u64,
+) -> Response<<Service as FlightService>::DoPutStream> {
+    let result = DoPutUpdateResult {
+        record_count: i64::try_from(record_count).unwrap_or(i64::MAX),
+    };
+
+    Response::new(
+        stream::iter(vec![Ok(PutResult {
+            app_metadata: result.encode_to_vec().into(),
+        })])
+        .boxed(),
+    )
+}
+
+/// The filters selecting the rows a `DELETE` removes: none for a scan of the whole table, or the predicate of its
+/// `WHERE` clause. Any other input is rejected, rather than risk deleting rows it didn't select.
+fn delete_filters(input: &LogicalPlan) -> Result<Vec<Expr>, Status> {
+    match input {
+        LogicalPlan::TableScan(_) => Ok(vec![]),
+        LogicalPlan::Filter(filter) if matches!(filter.input.as_ref(), LogicalPlan::TableScan(_)) => {
+            Ok(vec![unnormalize_col(filter.predicate.clone())])
+        }
+        _ => Err(Status::invalid_argument(
+            "Unsupported DELETE statement, only DELETE FROM <table> [WHERE <predicate>] is supported",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datafusion::DataFusion;
+    use arrow::{
+        array::{Int64Array, RecordBatch},
+        datatypes::{DataType, Field, Schema},
+    };
+    use data_components::{arrow::write::MemTable, delete::DeletionTableProviderAdapter};
+    use datafusion::logical_expr::LogicalPlanBuilder;
+
+    fn service() -> Service {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3]))],
+        )
+        .expect("valid batch");
+        let table = MemTable::try_new(schema, vec![vec![batch]]).expect("valid table");
+
+        let mut df = DataFusion::new();
+        df.register_writable_table(
+            "t",
+            Arc::new(DeletionTableProviderAdapter::new(Arc::new(table))),
+        )
+        .expect("table registers");
+        Service::for_tests(df)
+    }
+
+    async fn update(service: &Service, query: &str) -> i64 {
+        let mut results = do_put(
+            service,
+            None,
+            sql::CommandStatementUpdate {
+                query: query.to_string(),
+                transaction_id: None,
+            },
+        )
+        .await
+        .expect("statement runs")
+        .into_inner();
+        let result = results
+            .next()
+            .await
+            .expect("a result")
+            .expect("a successful result");
+        DoPutUpdateResult::decode(result.app_metadata)
+            .expect("valid result")
+            .record_count
+    }
+
+    async fn values(service: &Service) -> Vec<i64> {
+        let ctx = Arc::clone(&service.datafusion.read().await.ctx);
+        let batches = ctx
+            .sql("SELECT a FROM t ORDER BY a")
+            .await
+            .expect("query plans")
+            .collect()
+            .await
+            .expect("query runs");
+        batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .expect("Int64 column")
+                    .values()
+                    .to_vec()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_delete_with_where() {
+        let service = service();
+        assert_eq!(update(&service, "DELETE FROM t WHERE a > 1").await, 2);
+        assert_eq!(values(&service).await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_without_where() {
+        let service = service();
+        assert_eq!(update(&service, "DELETE FROM t").await, 3);
+        assert_eq!(values(&service).await, Vec::<i64>::new());
+    }
+
+    #[tokio::test]
+    async fn test_insert() {
+        let service = service();
+        assert_eq!(update(&service, "INSERT INTO t VALUES (4), (5)").await, 2);
+        assert_eq!(values(&service).await, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_update_is_unsupported() {
+        let service = service();
+        let Err(status) = do_put(
+            &service,
+            None,
+            sql::CommandStatementUpdate {
+                query: "UPDATE t SET a = 4 WHERE a = 1".to_string(),
+                transaction_id: None,
+            },
+        )
+        .await
+        else {
+            panic!("UPDATE should be rejected");
+        };
+        assert_eq!(status.code(), tonic::Code::Unimplemented);
+        assert_eq!(values(&service).await, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_rejects_other_inputs() {
+        let service = service();
+        let ctx = Arc::clone(&service.datafusion.read().await.ctx);
+        let scan = ctx
+            .table("t")
+            .await
+            .expect("table exists")
+            .into_unoptimized_plan();
+        let limited = LogicalPlanBuilder::from(scan)
+            .limit(0, Some(1))
+            .expect("limit plans")
+            .build()
+            .expect("plan builds");
+        assert!(delete_filters(&limited).is_err());
+    }
+}
@@ -16,27 +16,42 @@ limitations under the License.
 
 use arrow_flight::{
     sql::{Any, Command},
-    FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+    FlightDescriptor, FlightEndpoint, FlightInfo, PollInfo, Ticket,
 };
 use prost::Message;
 use tonic::{Request, Response, Status};
 
+use crate::auth::Principal;
+
 use super::{flightsql, to_tonic_err, Service};
 
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<FlightDescriptor>,
 ) -> Result<Response<FlightInfo>, Status> {
+    let principal = request.extensions().get::<Principal>().cloned();
     let Ok(message) = Any::decode(&*request.get_ref().cmd) else {
         return Ok(get_flight_info_simple(request));
     };
 
     match Command::try_from(message).map_err(to_tonic_err)? {
         Command::CommandStatementQuery(token) => {
-            flightsql::statement_query::get_flight_info(flight_svc, token, request).await
+            flightsql::statement_query::get_flight_info(
+                flight_svc,
+                principal.as_ref(),
+                token,
+                request,
+            )
+            .await
         }
         Command::CommandPreparedStatementQuery(handle) => {
-            flightsql::prepared_statement_query::get_flight_info(flight_svc, handle, request).await
+            flightsql::prepared_statement_query::get_flight_info(
+                flight_svc,
+                principal.as_ref(),
+                handle,
+                request,
+            )
+            .await
         }
         Command::CommandGetCatalogs(token) => {
             Ok(flightsql::get_catalogs::get_flight_info(&token, request))
@@ -53,10 +68,40 @@ pub(crate) async fn handle(
         Command::CommandGetTableTypes(token) => {
             Ok(flightsql::get_table_types::get_flight_info(&token, request))
         }
+        Command::CommandGetPrimaryKeys(token) => Ok(flightsql::get_primary_keys::get_flight_info(
+            &token, request,
+        )),
+        Command::CommandGetImportedKeys(token) => Ok(flightsql::get_foreign_keys::get_flight_info(
+            &token, request,
+        )),
+        Command::CommandGetExportedKeys(token) => Ok(flightsql::get_foreign_keys::get_flight_info(
+            &token, request,
+        )),
+        Command::CommandGetCrossReference(token) => Ok(
+            flightsql::get_foreign_keys::get_flight_info(&token, request),
+        ),
+        Command::CommandGetXdbcTypeInfo(token) => Ok(
+            flightsql::get_xdbc_type_info::get_flight_info(&token, request),
+        ),
         _ => Err(Status::unimplemented("Not yet implemented")),
     }
 }
 
+/// Queries are only executed once their ticket is redeemed through `DoGet`, so the `FlightInfo` is
+/// complete as soon as the query has been planned and the client doesn't need to poll again.
+pub(crate) async fn poll(
+    flight_svc: &Service,
+    request: Request<FlightDescriptor>,
+) -> Result<Response<PollInfo>, Status> {
+    let info = handle(flight_svc, request).await?.into_inner();
+    Ok(Response::new(PollInfo {
+        info: Some(info),
+        flight_descriptor: None,
+        progress: Some(1.0),
+        expiration_time: None,
+    }))
+}
+
 fn get_flight_info_simple(request: Request<FlightDescriptor>) -> Response<FlightInfo> {
     tracing::trace!("get_flight_info_simple: {request:?}");
     let fd = request.into_inner();
@@ -69,3 +114,34 @@ fn get_flight_info_simple(request: Request<FlightDescriptor>) -> Response<Flight
         ..Default::default()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datafusion::DataFusion;
+    use arrow_flight::sql::{self, ProstMessageExt};
+
+    #[tokio::test]
+    async fn test_poll_returns_completed_info() {
+        let service = Service::for_tests(DataFusion::new());
+        let query = sql::CommandStatementQuery {
+            query: "SELECT 1 AS a".to_string(),
+            transaction_id: None,
+        };
+        let descriptor = FlightDescriptor::new_cmd(query.as_any().encode_to_vec());
+
+        let poll_info = poll(&service, Request::new(descriptor.clone()))
+            .await
+            .expect("query plans")
+            .into_inner();
+
+        // A completed poll has no descriptor to poll again with.
+        assert_eq!(poll_info.flight_descriptor, None);
+        assert_eq!(poll_info.progress, Some(1.0));
+        let info = poll_info.info.expect("the flight info");
+        assert_eq!(info.flight_descriptor, Some(descriptor));
+        assert_eq!(info.endpoint.len(), 1);
+        let schema = info.try_decode_schema().expect("valid schema");
+        assert_eq!(schema.field(0).name(), "a");
+    }
+}
@@ -0,0 +1,158 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use arrow::datatypes::Schema;
+use arrow_flight::{
+    sql::{Any, Command},
+    FlightDescriptor, SchemaAsIpc, SchemaResult,
+};
+use arrow_ipc::writer::IpcWriteOptions;
+use prost::Message;
+use spicepod::component::access::Operation;
+use tonic::{Request, Response, Status};
+
+use crate::auth::Principal;
+
+use super::{flightsql, to_tonic_err, Service};
+
+/// Resolves the Arrow schema for a dataset path or a SQL command.
+pub(crate) async fn handle(
+    flight_svc: &Service,
+    request: Request<FlightDescriptor>,
+) -> Result<Response<SchemaResult>, Status> {
+    let principal = request.extensions().get::<Principal>().cloned();
+    let fd = request.into_inner();
+    tracing::trace!("get_schema: {fd:?}");
+
+    let schema = if fd.path.is_empty() {
+        get_command_schema(flight_svc, principal.as_ref(), &fd.cmd).await?
+    } else {
+        let path = fd.path.join(".");
+        flight_svc.authorize(principal.as_ref(), &path, Operation::Read)?;
+        flight_svc
+            .datafusion
+            .read()
+            .await
+            .get_arrow_schema(&path)
+            .await
+            .map_err(|e| Status::not_found(format!("Unable to get schema for {path}: {e}")))?
+    };
+
+    let schema_result: SchemaResult = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+        .try_into()
+        .map_err(to_tonic_err)?;
+
+    Ok(Response::new(schema_result))
+}
+
+async fn get_command_schema(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    cmd: &[u8],
+) -> Result<Schema, Status> {
+    let Ok(message) = Any::decode(cmd) else {
+        let sql = std::str::from_utf8(cmd)
+            .map_err(|e| Status::invalid_argument(format!("Invalid command: {e}")))?;
+        return flight_svc
+            .get_arrow_schema(principal, sql.to_string())
+            .await;
+    };
+
+    match Command::try_from(message).map_err(to_tonic_err)? {
+        Command::CommandStatementQuery(command) => {
+            flight_svc.get_arrow_schema(principal, command.query).await
+        }
+        Command::CommandPreparedStatementQuery(command) => {
+            flightsql::prepared_statement_query::get_schema(flight_svc, principal, &command).await
+        }
+        _ => Err(Status::unimplemented("Not yet implemented")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::Authenticator, datafusion::DataFusion};
+    use arrow::{
+        array::{Int64Array, RecordBatch},
+        datatypes::{DataType, Field},
+    };
+    use arrow_flight::sql::{self, ProstMessageExt};
+    use spicepod::component::access::Role;
+    use std::sync::Arc;
+
+    fn service() -> Service {
+        let df = DataFusion::new();
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3]))],
+        )
+        .expect("valid batch");
+        df.ctx
+            .register_batch("events", batch)
+            .expect("table registers");
+        let mut service = Service::for_tests(df);
+        service.auth = Some(Arc::new(Authenticator::with_roles(vec![Role {
+            name: "analysts".to_string(),
+            principals: vec!["alice".to_string()],
+            datasets: vec!["events".to_string()],
+            operations: vec![Operation::Read],
+        }])));
+        service
+    }
+
+    async fn get_schema(
+        service: &Service,
+        principal: &str,
+        descriptor: FlightDescriptor,
+    ) -> Result<Schema, Status> {
+        let mut request = Request::new(descriptor);
+        request.extensions_mut().insert(Principal {
+            name: principal.to_string(),
+        });
+        let schema_result = handle(service, request).await?.into_inner();
+        Ok(Schema::try_from(&schema_result).expect("valid schema"))
+    }
+
+    #[tokio::test]
+    async fn test_get_schema_requires_read_access() {
+        let service = service();
+        let descriptors = [
+            FlightDescriptor::new_path(vec!["events".to_string()]),
+            FlightDescriptor::new_cmd(
+                sql::CommandStatementQuery {
+                    query: "SELECT a FROM events".to_string(),
+                    transaction_id: None,
+                }
+                .as_any()
+                .encode_to_vec(),
+            ),
+        ];
+
+        for descriptor in descriptors {
+            let schema = get_schema(&service, "alice", descriptor.clone())
+                .await
+                .expect("alice may read events");
+            assert_eq!(schema.fields().len(), 1);
+            assert_eq!(schema.field(0).name(), "a");
+
+            let Err(status) = get_schema(&service, "bob", descriptor).await else {
+                panic!("bob may not read events");
+            };
+            assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        }
+    }
+}
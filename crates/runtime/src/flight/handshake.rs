@@ -16,20 +16,37 @@ limitations under the License.
 
 use std::pin::Pin;
 
-use arrow_flight::HandshakeResponse;
+use arrow_flight::{HandshakeRequest, HandshakeResponse};
 use futures::Stream;
-use tonic::{metadata::MetadataValue, Response, Status};
+use tonic::{metadata::MetadataValue, Request, Response, Status, Streaming};
 use uuid::Uuid;
 
-use crate::timing::{TimeMeasurement, TimedStream};
+use crate::{
+    auth::{Authenticator, Principal},
+    timing::{TimeMeasurement, TimedStream},
+};
 
 type HandshakeResponseStream =
     Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>;
 
-pub(crate) fn handle() -> Result<Response<HandshakeResponseStream>, Status> {
-    // THIS IS PLACEHOLDER NO-OP AUTH THAT DOES NOT CHECK THE PROVIDED TOKEN AND SIMPLY RETURNS A UUID.
-    // TODO: Implement proper auth.
-    let token = Uuid::new_v4().to_string();
+/// Issues the bearer token that clients send with subsequent Flight calls.
+///
+/// The request's credentials have already been verified by the Flight server's interceptor. When
+/// authentication is disabled, the token is a random UUID that is never checked.
+pub(crate) fn handle(
+    auth: Option<&Authenticator>,
+    request: &Request<Streaming<HandshakeRequest>>,
+) -> Result<Response<HandshakeResponseStream>, Status> {
+    let token = match auth {
+        Some(auth) => {
+            let principal = request
+                .extensions()
+                .get::<Principal>()
+                .ok_or_else(|| Status::unauthenticated("Invalid credentials"))?;
+            auth.issue_session_token(principal)
+        }
+        None => Uuid::new_v4().to_string(),
+    };
     let result = HandshakeResponse {
         protocol_version: 0,
         payload: token.as_bytes().to_vec().into(),
@@ -0,0 +1,246 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use arrow_flight::{
+    flight_service_server::FlightService, Criteria, FlightDescriptor, FlightEndpoint, FlightInfo,
+    Ticket,
+};
+use futures::stream;
+use spicepod::component::access::Operation;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    auth::Principal,
+    timing::{TimeMeasurement, TimedStream},
+};
+
+use super::{to_tonic_err, Service};
+
+const DEFAULT_CATALOG: &str = "datafusion";
+const DEFAULT_SCHEMA: &str = "public";
+const INFORMATION_SCHEMA: &str = "information_schema";
+
+/// Lists every registered dataset the caller may read as a `FlightInfo`. The `Criteria` expression, if
+/// provided, is treated as a prefix that dataset names must match.
+pub(crate) async fn handle(
+    flight_svc: &Service,
+    request: Request<Criteria>,
+) -> Result<Response<<Service as FlightService>::ListFlightsStream>, Status> {
+    let start = TimeMeasurement::new("flight_list_flights_duration_ms", vec![]);
+    let principal = request.extensions().get::<Principal>().cloned();
+    let prefix = String::from_utf8(request.into_inner().expression.to_vec())
+        .map_err(|e| Status::invalid_argument(format!("Invalid criteria: {e}")))?;
+    tracing::trace!("list_flights: prefix={prefix}");
+
+    let ctx = Arc::clone(&flight_svc.datafusion.read().await.ctx);
+
+    let mut flights = vec![];
+    for catalog_name in ctx.catalog_names() {
+        let Some(catalog) = ctx.catalog(&catalog_name) else {
+            continue;
+        };
+
+        for schema_name in catalog.schema_names() {
+            if schema_name == INFORMATION_SCHEMA {
+                continue;
+            }
+            let Some(schema) = catalog.schema(&schema_name) else {
+                continue;
+            };
+
+            for table_name in schema.table_names() {
+                if !table_name.starts_with(&prefix) {
+                    continue;
+                }
+                let path = if catalog_name == DEFAULT_CATALOG && schema_name == DEFAULT_SCHEMA {
+                    vec![table_name.clone()]
+                } else {
+                    vec![
+                        catalog_name.clone(),
+                        schema_name.clone(),
+                        table_name.clone(),
+                    ]
+                };
+                if flight_svc
+                    .authorize(principal.as_ref(), &path.join("."), Operation::Read)
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let Some(table) = schema.table(&table_name).await.map_err(to_tonic_err)? else {
+                    continue;
+                };
+
+                let total_records = table
+                    .statistics()
+                    .and_then(|statistics| statistics.num_rows.get_value().copied())
+                    .and_then(|num_rows| i64::try_from(num_rows).ok())
+                    .unwrap_or(-1);
+
+                let endpoint = FlightEndpoint::new().with_ticket(Ticket {
+                    ticket: select_all_sql(&path).into(),
+                });
+
+                let info = FlightInfo::new()
+                    .try_with_schema(table.schema().as_ref())
+                    .map_err(to_tonic_err)?
+                    .with_endpoint(endpoint)
+                    .with_descriptor(FlightDescriptor::new_path(path))
+                    .with_total_records(total_records);
+
+                flights.push(Ok(info));
+            }
+        }
+    }
+
+    Ok(Response::new(Box::pin(TimedStream::new(
+        stream::iter(flights),
+        move || start,
+    ))))
+}
+
+fn select_all_sql(path: &[String]) -> String {
+    let table_reference = path
+        .iter()
+        .map(|part| format!(r#""{}""#, part.replace('"', r#""""#)))
+        .collect::<Vec<_>>()
+        .join(".");
+    format!("SELECT * FROM {table_reference}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::Authenticator, datafusion::DataFusion};
+    use arrow::{
+        array::{Int64Array, RecordBatch},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+    };
+    use async_trait::async_trait;
+    use datafusion::{
+        common::{stats::Precision, Statistics},
+        datasource::{MemTable, TableProvider, TableType},
+        error::Result as DataFusionResult,
+        execution::context::SessionState,
+        logical_expr::Expr,
+        physical_plan::ExecutionPlan,
+    };
+    use futures::TryStreamExt;
+    use spicepod::component::access::Role;
+    use std::any::Any;
+
+    /// A table that reports its row count, if known, in its statistics.
+    struct CountedTable(MemTable, Option<usize>);
+
+    #[async_trait]
+    impl TableProvider for CountedTable {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn schema(&self) -> SchemaRef {
+            self.0.schema()
+        }
+
+        fn table_type(&self) -> TableType {
+            self.0.table_type()
+        }
+
+        async fn scan(
+            &self,
+            state: &SessionState,
+            projection: Option<&Vec<usize>>,
+            filters: &[Expr],
+            limit: Option<usize>,
+        ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+            self.0.scan(state, projection, filters, limit).await
+        }
+
+        fn statistics(&self) -> Option<Statistics> {
+            self.1.map(|num_rows| Statistics {
+                num_rows: Precision::Exact(num_rows),
+                ..Statistics::new_unknown(&self.schema())
+            })
+        }
+    }
+
+    fn table() -> MemTable {
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from(vec![1, 2, 3]))],
+        )
+        .expect("valid batch");
+        MemTable::try_new(batch.schema(), vec![vec![batch]]).expect("valid table")
+    }
+
+    fn service() -> Service {
+        let df = DataFusion::new();
+        df.ctx
+            .register_table("events", Arc::new(CountedTable(table(), Some(3))))
+            .expect("table registers");
+        df.ctx
+            .register_table("logs", Arc::new(CountedTable(table(), None)))
+            .expect("table registers");
+        let mut service = Service::for_tests(df);
+        service.auth = Some(Arc::new(Authenticator::with_roles(vec![Role {
+            name: "analysts".to_string(),
+            principals: vec!["alice".to_string()],
+            datasets: vec!["events".to_string()],
+            operations: vec![Operation::Read],
+        }])));
+        service
+    }
+
+    async fn list_flights(service: &Service, principal: &str) -> Vec<(String, i64)> {
+        let mut request = Request::new(Criteria::default());
+        request.extensions_mut().insert(Principal {
+            name: principal.to_string(),
+        });
+        let mut flights: Vec<(String, i64)> = handle(service, request)
+            .await
+            .expect("flights are listed")
+            .into_inner()
+            .map_ok(|info| {
+                let path = info.flight_descriptor.expect("a descriptor").path.join(".");
+                (path, info.total_records)
+            })
+            .try_collect()
+            .await
+            .expect("valid flights");
+        flights.sort();
+        flights
+    }
+
+    #[tokio::test]
+    async fn test_list_flights_only_lists_readable_datasets() {
+        let mut service = service();
+        assert_eq!(
+            list_flights(&service, "alice").await,
+            vec![("events".to_string(), 3)]
+        );
+        assert!(list_flights(&service, "bob").await.is_empty());
+
+        // Without statistics the row count is unknown.
+        service.auth = None;
+        assert_eq!(
+            list_flights(&service, "bob").await,
+            vec![("events".to_string(), 3), ("logs".to_string(), -1)]
+        );
+    }
+}
@@ -23,7 +23,15 @@ use tokio::{
     sync::RwLock,
 };
 
-use crate::{config, datafusion::DataFusion, model::Model};
+use axum::Router;
+use futures::StreamExt;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+
+use crate::{auth::Authenticator, config, datafusion::DataFusion, model::Model, tls::TlsAcceptor};
 
 mod routes;
 mod v1;
@@ -39,18 +47,21 @@ pub enum Error {
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn start<A>(
     bind_address: A,
     app: Arc<RwLock<Option<App>>>,
     df: Arc<RwLock<DataFusion>>,
     models: Arc<RwLock<HashMap<String, Model>>>,
     config: Arc<config::Config>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<Arc<TlsAcceptor>>,
     with_metrics: Option<SocketAddr>,
 ) -> Result<()>
 where
     A: ToSocketAddrs + Debug,
 {
-    let routes = routes::routes(app, df, models, config, with_metrics);
+    let routes = routes::routes(app, df, models, config, auth, with_metrics);
 
     let listener = TcpListener::bind(&bind_address)
         .await
@@ -59,8 +70,28 @@ where
 
     metrics::counter!("spiced_runtime_http_server_start").increment(1);
 
-    axum::serve(listener, routes)
-        .await
-        .context(UnableToStartHttpServerSnafu)?;
+    match tls {
+        Some(tls) => serve_tls(listener, routes, &tls).await,
+        None => axum::serve(listener, routes)
+            .await
+            .context(UnableToStartHttpServerSnafu)?,
+    };
     Ok(())
 }
+
+/// `axum::serve` only accepts plain TCP listeners, so TLS connections are served with hyper directly.
+async fn serve_tls(listener: TcpListener, routes: Router, tls: &Arc<TlsAcceptor>) {
+    let mut incoming = tls.incoming(listener);
+    while let Some(Ok(stream)) = incoming.next().await {
+        let remote_addr = stream.remote_addr();
+        let service = TowerToHyperService::new(routes.clone());
+        tokio::spawn(async move {
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Error serving HTTPS connection from {remote_addr}: {e}");
+            }
+        });
+    }
+}
@@ -14,7 +14,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::{config, datafusion::DataFusion, model::Model};
+use crate::{auth::Authenticator, config, datafusion::DataFusion, model::Model};
 use app::App;
 use std::net::SocketAddr;
 use std::{collections::HashMap, sync::Arc};
@@ -22,9 +22,9 @@ use std::{collections::HashMap, sync::Arc};
 use axum::{
     body::Body,
     extract::MatchedPath,
-    http::Request,
+    http::{header::AUTHORIZATION, Request, StatusCode},
     middleware::{self, Next},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::{get, post, Router},
     Extension,
 };
@@ -37,24 +37,60 @@ pub(crate) fn routes(
     df: Arc<RwLock<DataFusion>>,
     models: Arc<RwLock<HashMap<String, Model>>>,
     config: Arc<config::Config>,
+    auth: Option<Arc<Authenticator>>,
     with_metrics: Option<SocketAddr>,
 ) -> Router {
     Router::new()
-        .route("/health", get(|| async { "ok\n" }))
         .route("/v1/sql", post(v1::query::post))
+        .route("/v1/sql/explain", post(v1::query::explain))
         .route("/v1/status", get(v1::status::get))
         .route("/v1/datasets", get(v1::datasets::get))
         .route("/v1/datasets/:name/refresh", post(v1::datasets::refresh))
+        .route("/v1/datasets/:name/changes", get(v1::datasets::changes))
+        .route("/v1/datasets/:name/data", post(v1::datasets::write))
         .route("/v1/spicepods", get(v1::spicepods::get))
         .route("/v1/models", get(v1::models::get))
-        .route("/v1/models/:name/predict", get(v1::inference::get))
+        .route(
+            "/v1/models/:name/predict",
+            get(v1::inference::get).post(v1::inference::post_model),
+        )
         .route("/v1/predict", post(v1::inference::post))
+        .route_layer(middleware::from_fn(authenticate))
+        .route("/health", get(|| async { "ok\n" }))
         .route_layer(middleware::from_fn(track_metrics))
         .layer(Extension(app))
         .layer(Extension(df))
         .layer(Extension(with_metrics))
         .layer(Extension(models))
         .layer(Extension(config))
+        .layer(Extension(auth))
+}
+
+/// Rejects requests without valid credentials when authentication is enabled.
+///
+/// The authenticated [`Principal`](crate::auth::Principal) is added to the request extensions.
+async fn authenticate(
+    Extension(auth): Extension<Option<Arc<Authenticator>>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(auth) = auth else {
+        return next.run(req).await;
+    };
+
+    let principal = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| auth.authenticate(value));
+
+    match principal {
+        Some(principal) => {
+            req.extensions_mut().insert(principal);
+            next.run(req).await
+        }
+        None => (StatusCode::UNAUTHORIZED, "Unauthorized\n").into_response(),
+    }
 }
 
 async fn track_metrics(req: Request<Body>, next: Next) -> impl IntoResponse {
@@ -20,6 +20,8 @@ use spicepod::component::dataset::Dataset;
 
 use crate::{datafusion::DataFusion, status::ComponentStatus};
 
+mod ingest;
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Format {
@@ -49,17 +51,24 @@ pub(crate) mod query {
     use arrow::record_batch::RecordBatch;
     use axum::{
         body::Bytes,
+        extract::Query,
         http::StatusCode,
         response::{IntoResponse, Response},
-        Extension,
+        Extension, Json,
     };
+    use serde::{Deserialize, Serialize};
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
-    use crate::datafusion::DataFusion;
+    use crate::{
+        auth::{self, Authenticator, Principal},
+        datafusion::{explain, DataFusion},
+    };
 
     pub(crate) async fn post(
         Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
         body: Bytes,
     ) -> Response {
         let query = match String::from_utf8(body.to_vec()) {
@@ -70,7 +79,21 @@ pub(crate) mod query {
             }
         };
 
-        let data_frame = match df.read().await.ctx.sql(&query).await {
+        let ctx = Arc::clone(&df.read().await.ctx);
+        let plan = match ctx.state().create_logical_plan(&query).await {
+            Ok(plan) => plan,
+            Err(e) => {
+                tracing::debug!("Error running query: {e}");
+                return (StatusCode::BAD_REQUEST, query.to_string()).into_response();
+            }
+        };
+
+        let principal = principal.map(|Extension(principal)| principal);
+        if let Err(e) = auth::authorize_plan(auth.as_deref(), principal.as_ref(), &plan) {
+            return (StatusCode::FORBIDDEN, e.to_string()).into_response();
+        }
+
+        let data_frame = match ctx.execute_logical_plan(plan).await {
             Ok(data_frame) => data_frame,
             Err(e) => {
                 tracing::debug!("Error running query: {e}");
@@ -111,6 +134,240 @@ pub(crate) mod query {
 
         (StatusCode::OK, res).into_response()
     }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct ExplainQueryParams {
+        #[serde(default)]
+        analyze: bool,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct ExplainResponse {
+        pub logical_plan: String,
+        pub physical_plan: String,
+        pub tables: Vec<ExplainTable>,
+        pub sources: Vec<ExplainSource>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct ExplainTable {
+        pub name: String,
+        pub accelerated: bool,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct ExplainSource {
+        pub node: String,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub sql: Option<String>,
+
+        pub details: String,
+    }
+
+    pub(crate) async fn explain(
+        Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
+        Query(params): Query<ExplainQueryParams>,
+        body: Bytes,
+    ) -> Response {
+        let query = match String::from_utf8(body.to_vec()) {
+            Ok(query) => query,
+            Err(e) => {
+                tracing::debug!("Error reading query: {e}");
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        };
+
+        let ctx = Arc::clone(&df.read().await.ctx);
+        let plan = match ctx.state().create_logical_plan(&query).await {
+            Ok(plan) => plan,
+            Err(e) => {
+                tracing::debug!("Error explaining query: {e}");
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        };
+
+        // An analyze runs the query, so explaining needs the same access as running it.
+        let principal = principal.map(|Extension(principal)| principal);
+        if let Err(e) = auth::authorize_plan(auth.as_deref(), principal.as_ref(), &plan) {
+            return (StatusCode::FORBIDDEN, e.to_string()).into_response();
+        }
+
+        let explanation = match explain::explain(&ctx, plan, params.analyze).await {
+            Ok(explanation) => explanation,
+            Err(e @ explain::Error::UnableToExecuteQuery { .. }) => {
+                tracing::debug!("Error explaining query: {e}");
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+            Err(e) => {
+                tracing::debug!("Error explaining query: {e}");
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        };
+
+        (
+            StatusCode::OK,
+            Json(ExplainResponse {
+                logical_plan: explanation.logical_plan,
+                physical_plan: explanation.physical_plan,
+                tables: explanation
+                    .tables
+                    .into_iter()
+                    .map(|t| ExplainTable {
+                        name: t.name,
+                        accelerated: t.accelerated,
+                    })
+                    .collect(),
+                sources: explanation
+                    .source_nodes
+                    .into_iter()
+                    .map(|n| ExplainSource {
+                        node: n.node,
+                        sql: n.sql,
+                        details: n.details,
+                    })
+                    .collect(),
+            }),
+        )
+            .into_response()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use arrow::array::Int64Array;
+        use arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::datasource::MemTable;
+        use spicepod::component::access::{Operation, Role};
+
+        use super::*;
+
+        fn data_fusion() -> Arc<RwLock<DataFusion>> {
+            let df = DataFusion::new();
+            let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+            let batch = RecordBatch::try_new(
+                Arc::clone(&schema),
+                vec![Arc::new(Int64Array::from(vec![1, 2, 3]))],
+            )
+            .expect("valid batch");
+            let table = MemTable::try_new(schema, vec![vec![batch]]).expect("valid table");
+            df.ctx
+                .register_table("events", Arc::new(table))
+                .expect("table registers");
+            Arc::new(RwLock::new(df))
+        }
+
+        async fn explain_query(
+            df: &Arc<RwLock<DataFusion>>,
+            auth: Option<Arc<Authenticator>>,
+            principal: Option<&str>,
+            query: &str,
+            analyze: bool,
+        ) -> (StatusCode, String) {
+            let response = explain(
+                Extension(Arc::clone(df)),
+                Extension(auth),
+                principal.map(|name| {
+                    Extension(Principal {
+                        name: name.to_string(),
+                    })
+                }),
+                Query(ExplainQueryParams { analyze }),
+                Bytes::from(query.to_string()),
+            )
+            .await;
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("response body");
+            (status, String::from_utf8_lossy(&body).to_string())
+        }
+
+        #[tokio::test]
+        async fn test_explain() {
+            let df = data_fusion();
+
+            let (status, body) =
+                explain_query(&df, None, None, "SELECT id FROM events WHERE id > 1", false).await;
+            assert_eq!(status, StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_str(&body).expect("JSON body");
+            assert_eq!(body["tables"][0]["name"], "events");
+            assert_eq!(body["tables"][0]["accelerated"], false);
+            assert!(!body["physical_plan"]
+                .as_str()
+                .expect("physical plan")
+                .contains("metrics="));
+
+            let (status, body) =
+                explain_query(&df, None, None, "SELECT id FROM events", true).await;
+            assert_eq!(status, StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_str(&body).expect("JSON body");
+            assert!(body["physical_plan"]
+                .as_str()
+                .expect("physical plan")
+                .contains("metrics="));
+        }
+
+        #[tokio::test]
+        async fn test_explain_refuses_statements() {
+            let df = data_fusion();
+
+            for statement in [
+                "DROP TABLE events",
+                "CREATE TABLE copied AS SELECT * FROM events",
+                "INSERT INTO events VALUES (4)",
+                "SET datafusion.execution.batch_size = 1",
+            ] {
+                let (status, _) = explain_query(&df, None, None, statement, false).await;
+                assert_eq!(status, StatusCode::BAD_REQUEST, "{statement}");
+            }
+
+            let ctx = Arc::clone(&df.read().await.ctx);
+            let rows = ctx
+                .sql("SELECT * FROM events")
+                .await
+                .expect("events is still registered")
+                .count()
+                .await
+                .expect("events is readable");
+            assert_eq!(rows, 3);
+            assert!(ctx.table("copied").await.is_err());
+        }
+
+        #[tokio::test]
+        async fn test_explain_requires_read_access() {
+            let df = data_fusion();
+            let auth = Some(Arc::new(Authenticator::with_roles(vec![Role {
+                name: "analysts".to_string(),
+                principals: vec!["alice".to_string()],
+                datasets: vec!["events".to_string()],
+                operations: vec![Operation::Read],
+            }])));
+
+            for analyze in [false, true] {
+                let (status, _) = explain_query(
+                    &df,
+                    auth.clone(),
+                    Some("bob"),
+                    "SELECT * FROM events",
+                    analyze,
+                )
+                .await;
+                assert_eq!(status, StatusCode::FORBIDDEN);
+
+                let (status, _) = explain_query(
+                    &df,
+                    auth.clone(),
+                    Some("alice"),
+                    "SELECT * FROM events",
+                    analyze,
+                )
+                .await;
+                assert_eq!(status, StatusCode::OK);
+            }
+        }
+    }
 }
 
 pub(crate) mod status {
@@ -280,21 +537,38 @@ pub(crate) mod datasets {
     use std::sync::Arc;
 
     use app::App;
+    use arrow::error::ArrowError;
     use axum::{
+        body::Bytes,
         extract::Path,
         extract::Query,
-        http::status,
-        response::{IntoResponse, Response},
+        http::{header::CONTENT_TYPE, status, HeaderMap},
+        response::{
+            sse::{Event, KeepAlive, Sse},
+            IntoResponse, Response,
+        },
         Extension, Json,
     };
+    use futures::stream;
     use serde::{Deserialize, Serialize};
-    use spicepod::component::dataset::Dataset;
-    use tokio::sync::RwLock;
+    use spicepod::component::{access::Operation, dataset::Dataset};
+    use tokio::sync::{broadcast::error::RecvError, RwLock};
     use tract_core::tract_data::itertools::Itertools;
 
-    use crate::{datafusion::DataFusion, status::ComponentStatus};
+    use crate::{
+        auth::{self, Authenticator, Principal},
+        changes::{with_op_column, Change},
+        datafusion::{DataFusion, WriteFailure, WriteResult},
+        dataupdate::{DataUpdate, UpdateType},
+        deduplication::IDEMPOTENCY_KEY_HEADER,
+        status::ComponentStatus,
+    };
 
-    use super::{convert_entry_to_csv, dataset_status, Format};
+    use super::{
+        convert_entry_to_csv, dataset_status,
+        ingest::{self, BodyFormat, RowError},
+        Format,
+    };
 
     #[derive(Debug, Deserialize)]
     pub(crate) struct DatasetFilter {
@@ -398,6 +672,8 @@ pub(crate) mod datasets {
     pub(crate) async fn refresh(
         Extension(app): Extension<Arc<RwLock<Option<App>>>>,
         Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
         Path(dataset_name): Path<String>,
     ) -> Response {
         let app_lock = app.read().await;
@@ -419,6 +695,22 @@ pub(crate) mod datasets {
                 .into_response();
         };
 
+        let principal = principal.map(|Extension(principal)| principal);
+        if let Err(e) = auth::authorize(
+            auth.as_deref(),
+            principal.as_ref(),
+            &dataset.name,
+            Operation::Refresh,
+        ) {
+            return (
+                status::StatusCode::FORBIDDEN,
+                Json(DatasetRefreshResponse {
+                    message: e.to_string(),
+                }),
+            )
+                .into_response();
+        }
+
         let acceleration_enabled = dataset.acceleration.as_ref().is_some_and(|f| f.enabled);
 
         if !acceleration_enabled {
@@ -450,6 +742,292 @@ pub(crate) mod datasets {
                 .into_response(),
         }
     }
+
+    /// Streams the changes to a dataset as Server-Sent Events.
+    ///
+    /// Each event is named after its operation (`insert`, `overwrite` or `delete`) and carries the changed rows as a
+    /// JSON array, including the `_op` column. A `lagged` event with the number of missed changes is sent when the
+    /// client falls behind.
+    pub(crate) async fn changes(
+        Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
+        Path(dataset_name): Path<String>,
+    ) -> Response {
+        let principal = principal.map(|Extension(principal)| principal);
+        if let Err(e) = auth::authorize(
+            auth.as_deref(),
+            principal.as_ref(),
+            &dataset_name,
+            Operation::Read,
+        ) {
+            return (status::StatusCode::FORBIDDEN, e.to_string()).into_response();
+        }
+
+        let df_read = df.read().await;
+        if !df_read.table_exists(&dataset_name) {
+            return (
+                status::StatusCode::NOT_FOUND,
+                format!("Dataset {dataset_name} not found"),
+            )
+                .into_response();
+        }
+        let rx = df_read.change_feeds().subscribe(&dataset_name);
+        drop(df_read);
+
+        let events = stream::unfold(rx, |mut rx| async move {
+            let event = match rx.recv().await {
+                Ok(change) => change_event(&change),
+                Err(RecvError::Lagged(skipped)) => {
+                    Ok(Event::default().event("lagged").data(skipped.to_string()))
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            Some((event, rx))
+        });
+
+        Sse::new(events)
+            .keep_alive(KeepAlive::default())
+            .into_response()
+    }
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct DatasetWriteResponse {
+        pub rows: usize,
+        pub sequence: u64,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct DatasetWriteError {
+        pub message: String,
+
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub errors: Vec<RowError>,
+    }
+
+    impl DatasetWriteError {
+        fn response(status: status::StatusCode, message: impl Into<String>) -> Response {
+            (
+                status,
+                Json(Self {
+                    message: message.into(),
+                    errors: vec![],
+                }),
+            )
+                .into_response()
+        }
+    }
+
+    /// Writes the rows in the request body to a writable dataset, responding with the rows written and the write
+    /// sequence number of the dataset.
+    ///
+    /// The `Content-Type` selects the body format: a JSON array, NDJSON, CSV with a header row, or an Arrow IPC stream
+    /// or file. JSON and CSV are read against the dataset schema and every invalid row is reported. Arrow IPC carries
+    /// its own schema, which can evolve the dataset schema. An `Idempotency-Key` header deduplicates retries.
+    pub(crate) async fn write(
+        Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
+        Path(dataset_name): Path<String>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let principal = principal.map(|Extension(principal)| principal);
+        if let Err(e) = auth::authorize(
+            auth.as_deref(),
+            principal.as_ref(),
+            &dataset_name,
+            Operation::Write,
+        ) {
+            return DatasetWriteError::response(status::StatusCode::FORBIDDEN, e.to_string());
+        }
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let format = match BodyFormat::from_content_type(content_type) {
+            Ok(format) => format,
+            Err(e) => {
+                return DatasetWriteError::response(
+                    status::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    e.to_string(),
+                )
+            }
+        };
+        let idempotency_token = headers
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+
+        let df_read = df.read().await;
+        if !df_read.table_exists(&dataset_name) {
+            return DatasetWriteError::response(
+                status::StatusCode::NOT_FOUND,
+                format!("Dataset {dataset_name} not found"),
+            );
+        }
+        if !df_read.is_writable(&dataset_name) {
+            return DatasetWriteError::response(
+                status::StatusCode::BAD_REQUEST,
+                format!("Dataset {dataset_name} is not writable"),
+            );
+        }
+        let schema = match df_read.get_arrow_schema(&dataset_name).await {
+            Ok(schema) => Arc::new(schema),
+            Err(e) => {
+                return DatasetWriteError::response(
+                    status::StatusCode::INTERNAL_SERVER_ERROR,
+                    e.to_string(),
+                )
+            }
+        };
+        drop(df_read);
+
+        let (schema, data) = match ingest::decode(format, &body, schema) {
+            Ok(decoded) => decoded,
+            Err(ingest::Error::InvalidRows { errors }) => {
+                return (
+                    status::StatusCode::BAD_REQUEST,
+                    Json(DatasetWriteError {
+                        message: format!("{} rows don't match the dataset schema", errors.len()),
+                        errors,
+                    }),
+                )
+                    .into_response();
+            }
+            Err(e) => {
+                return DatasetWriteError::response(status::StatusCode::BAD_REQUEST, e.to_string())
+            }
+        };
+
+        let data_update = DataUpdate {
+            schema,
+            data,
+            update_type: UpdateType::Append,
+        };
+
+        let df_read = df.read().await;
+        match df_read
+            .write_data(&dataset_name, data_update, idempotency_token)
+            .await
+        {
+            Ok(WriteResult { rows, sequence }) => (
+                status::StatusCode::CREATED,
+                Json(DatasetWriteResponse { rows, sequence }),
+            )
+                .into_response(),
+            Err(e) => match e.write_failure() {
+                WriteFailure::InvalidData => {
+                    DatasetWriteError::response(status::StatusCode::BAD_REQUEST, e.to_string())
+                }
+                WriteFailure::Conflict | WriteFailure::InProgress => {
+                    DatasetWriteError::response(status::StatusCode::CONFLICT, e.to_string())
+                }
+                WriteFailure::Internal => DatasetWriteError::response(
+                    status::StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Error writing data: {e}"),
+                ),
+            },
+        }
+    }
+
+    fn change_event(change: &Change) -> Result<Event, ArrowError> {
+        let mut writer = arrow_json::ArrayWriter::new(Vec::new());
+        for batch in &change.data {
+            writer.write(&with_op_column(batch, change.op)?)?;
+        }
+        writer.finish()?;
+
+        let data = String::from_utf8(writer.into_inner())
+            .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+
+        Ok(Event::default().event(change.op.as_str()).data(data))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use arrow::{
+            array::{Int64Array, RecordBatch},
+            datatypes::{DataType, Field, Schema},
+        };
+        use axum::http::StatusCode;
+        use data_components::{arrow::write::MemTable, delete::DeletionTableProviderAdapter};
+        use futures::StreamExt;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_changes_streams_writes() {
+            let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+            let table = MemTable::try_new(Arc::clone(&schema), vec![vec![]]).expect("valid table");
+            let mut df = DataFusion::new();
+            df.register_writable_table(
+                "t",
+                Arc::new(DeletionTableProviderAdapter::new(Arc::new(table))),
+            )
+            .expect("table registers");
+            let df = Arc::new(RwLock::new(df));
+
+            let response = changes(
+                Extension(Arc::clone(&df)),
+                Extension(None),
+                None,
+                Path("t".to_string()),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let batch = RecordBatch::try_new(
+                Arc::clone(&schema),
+                vec![Arc::new(Int64Array::from(vec![1, 2]))],
+            )
+            .expect("valid batch");
+            df.read()
+                .await
+                .write_data(
+                    "t",
+                    DataUpdate {
+                        schema,
+                        data: vec![batch],
+                        update_type: UpdateType::Append,
+                    },
+                    None,
+                )
+                .await
+                .expect("data written");
+
+            let mut body = response.into_body().into_data_stream();
+            let mut events = String::new();
+            while !events.contains("\n\n") {
+                let chunk = tokio::time::timeout(Duration::from_secs(10), body.next())
+                    .await
+                    .expect("an event is sent")
+                    .expect("the stream is open")
+                    .expect("a body chunk");
+                events.push_str(&String::from_utf8_lossy(&chunk));
+            }
+
+            assert!(events.contains("event: insert\n"), "{events}");
+            assert!(
+                events.contains(r#"data: [{"id":1,"_op":"insert"},{"id":2,"_op":"insert"}]"#),
+                "{events}"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_changes_unknown_dataset() {
+            let response = changes(
+                Extension(Arc::new(RwLock::new(DataFusion::new()))),
+                Extension(None),
+                None,
+                Path("missing".to_string()),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
 }
 
 pub(crate) mod spicepods {
@@ -614,22 +1192,31 @@ pub(crate) mod models {
 }
 
 pub(crate) mod inference {
+    use crate::auth::{self, Authenticator, Principal};
     use crate::datafusion::DataFusion;
     use crate::model::version as model_version;
-    use crate::model::Model;
+    use crate::model::{dataset_query, Inference, Model, ModelInput};
     use app::App;
     use arrow::array::Float32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::error::ArrowError;
+    use arrow::ipc::reader::StreamReader;
+    use arrow::record_batch::RecordBatch;
     use axum::{
+        body::Bytes,
         extract::Path,
-        http::StatusCode,
+        http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
         response::{IntoResponse, Response},
         Extension, Json,
     };
+    use futures::future::{join_all, BoxFuture, FutureExt, Shared};
     use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use spicepod::component::access::Operation;
+    use std::io::Cursor;
     use std::time::Instant;
     use std::{collections::HashMap, sync::Arc};
     use tokio::sync::RwLock;
-    use tract_core::tract_data::itertools::Itertools;
 
     #[derive(Deserialize)]
     pub struct BatchPredictRequest {
@@ -640,6 +1227,26 @@ pub(crate) mod inference {
     #[derive(Deserialize)]
     pub struct PredictRequest {
         pub model_name: String,
+
+        #[serde(flatten)]
+        pub input: PredictInput,
+    }
+
+    /// The input of a prediction. The model runs on the dataset it is bound to when neither is set.
+    #[derive(Default, Deserialize)]
+    pub struct PredictInput {
+        /// Feature rows as JSON objects. JSON numbers are read as `Float64` features.
+        #[serde(default)]
+        pub rows: Option<Vec<Value>>,
+
+        /// The order of the fields of `rows`, which JSON objects don't keep. Defaults to the column order of the
+        /// dataset the model is bound to, and is required for rows with several fields if the model isn't bound to one.
+        #[serde(default)]
+        pub columns: Option<Vec<String>>,
+
+        /// A query whose result is the model input.
+        #[serde(default)]
+        pub sql: Option<String>,
     }
 
     #[derive(Serialize)]
@@ -660,9 +1267,18 @@ pub(crate) mod inference {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub model_version: Option<String>,
 
+        /// The `y` column of the inference result, for models that predict a single `Float32` column `y`.
         #[serde(skip_serializing_if = "Option::is_none")]
         pub prediction: Option<Vec<f32>>,
 
+        /// Every column of the inference result, as one JSON object per row.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub outputs: Option<Vec<serde_json::Map<String, Value>>>,
+
+        /// The identifier of the prediction in the model's inference log, when it logs its predictions.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub request_id: Option<String>,
+
         pub duration_ms: u128,
     }
 
@@ -678,9 +1294,87 @@ pub(crate) mod inference {
         Extension(df): Extension<Arc<RwLock<DataFusion>>>,
         Path(model_name): Path<String>,
         Extension(models): Extension<Arc<RwLock<HashMap<String, Model>>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
+    ) -> Response {
+        let principal = principal.map(|Extension(principal)| principal);
+        let model_predict_response = run_inference(
+            app,
+            df,
+            models,
+            auth.as_deref(),
+            principal.as_ref(),
+            model_name,
+            ModelInput::Dataset,
+        )
+        .await;
+
+        predict_response(model_predict_response)
+    }
+
+    /// Runs a model on the input in the request body: a JSON [`PredictInput`] or an Arrow IPC stream of feature rows,
+    /// selected by the `Content-Type`. An empty body runs the model on the dataset it is bound to.
+    pub(crate) async fn post_model(
+        Extension(app): Extension<Arc<RwLock<Option<App>>>>,
+        Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        Extension(models): Extension<Arc<RwLock<HashMap<String, Model>>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
+        Path(model_name): Path<String>,
+        headers: HeaderMap,
+        body: Bytes,
     ) -> Response {
-        let model_predict_response = run_inference(app, df, models, model_name).await;
+        let start_time = Instant::now();
+        let principal = principal.map(|Extension(principal)| principal);
+
+        let is_arrow = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/vnd.apache.arrow.stream"));
+
+        let input = if is_arrow {
+            read_arrow_stream(&body)
+                .map(ModelInput::Batches)
+                .map_err(|e| format!("Unable to read the Arrow IPC input: {e}"))
+        } else if body.is_empty() {
+            Ok(ModelInput::Dataset)
+        } else {
+            match serde_json::from_slice::<PredictInput>(&body) {
+                Ok(input) => {
+                    model_input(
+                        &df,
+                        &models,
+                        &model_name,
+                        auth.as_deref(),
+                        principal.as_ref(),
+                        input,
+                    )
+                    .await
+                }
+                Err(e) => Err(format!("Unable to parse the prediction input: {e}")),
+            }
+        };
+
+        let model_predict_response = match input {
+            Ok(input) => {
+                run_inference(
+                    app,
+                    df,
+                    models,
+                    auth.as_deref(),
+                    principal.as_ref(),
+                    model_name,
+                    input,
+                )
+                .await
+            }
+            Err(message) => bad_request(model_name, message, start_time),
+        };
+
+        predict_response(model_predict_response)
+    }
 
+    fn predict_response(model_predict_response: PredictResponse) -> Response {
         match model_predict_response.status {
             PredictStatus::Success => {
                 (StatusCode::OK, Json(model_predict_response)).into_response()
@@ -696,29 +1390,57 @@ pub(crate) mod inference {
         }
     }
 
+    /// The rows of the dataset a model is bound to, queried once for the predictions of a batch that run on it. `None` if
+    /// the model isn't bound to a dataset.
+    type SharedDatasetRows<'a> = Shared<BoxFuture<'a, Result<Option<Vec<RecordBatch>>, String>>>;
+
+    /// Runs a batch of predictions concurrently.
     pub(crate) async fn post(
         Extension(app): Extension<Arc<RwLock<Option<App>>>>,
         Extension(df): Extension<Arc<RwLock<DataFusion>>>,
         Extension(models): Extension<Arc<RwLock<HashMap<String, Model>>>>,
+        Extension(auth): Extension<Option<Arc<Authenticator>>>,
+        principal: Option<Extension<Principal>>,
         Json(payload): Json<BatchPredictRequest>,
     ) -> Response {
         let start_time = Instant::now();
-        let mut model_predictions = Vec::new();
-        let mut model_prediction_futures = Vec::new();
+        let principal = principal.map(|Extension(principal)| principal);
 
+        // Predictions of a model on the dataset it is bound to share the dataset query, but each is routed to a
+        // version and logged on its own.
+        let mut dataset_rows: HashMap<String, SharedDatasetRows<'_>> = HashMap::new();
+        let mut runs = Vec::with_capacity(payload.predictions.len());
         for model_predict_request in payload.predictions {
-            let prediction_future = run_inference(
-                Arc::clone(&app),
-                Arc::clone(&df),
-                Arc::clone(&models),
-                model_predict_request.model_name,
-            );
-            model_prediction_futures.push(prediction_future);
+            let input = &model_predict_request.input;
+            let rows = (input.rows.is_none() && input.sql.is_none()).then(|| {
+                dataset_rows
+                    .entry(model_predict_request.model_name.clone())
+                    .or_insert_with(|| {
+                        bound_dataset_rows(
+                            &df,
+                            &models,
+                            model_predict_request.model_name.clone(),
+                            auth.as_deref(),
+                            principal.as_ref(),
+                        )
+                        .boxed()
+                        .shared()
+                    })
+                    .clone()
+            });
+
+            runs.push(predict(
+                &app,
+                &df,
+                &models,
+                auth.as_deref(),
+                principal.as_ref(),
+                model_predict_request,
+                rows,
+            ));
         }
 
-        for prediction_future in model_prediction_futures {
-            model_predictions.push(prediction_future.await);
-        }
+        let model_predictions = join_all(runs).await;
 
         (
             StatusCode::OK,
@@ -730,11 +1452,219 @@ pub(crate) mod inference {
             .into_response()
     }
 
+    async fn predict(
+        app: &Arc<RwLock<Option<App>>>,
+        df: &Arc<RwLock<DataFusion>>,
+        models: &Arc<RwLock<HashMap<String, Model>>>,
+        auth: Option<&Authenticator>,
+        principal: Option<&Principal>,
+        request: PredictRequest,
+        dataset_rows: Option<SharedDatasetRows<'_>>,
+    ) -> PredictResponse {
+        let start_time = Instant::now();
+        let input = match dataset_rows {
+            Some(dataset_rows) => dataset_rows.await.map(|rows| match rows {
+                Some(rows) => ModelInput::Batches(rows),
+                None => ModelInput::Dataset,
+            }),
+            None => {
+                model_input(
+                    df,
+                    models,
+                    &request.model_name,
+                    auth,
+                    principal,
+                    request.input,
+                )
+                .await
+            }
+        };
+        match input {
+            Ok(input) => {
+                run_inference(
+                    Arc::clone(app),
+                    Arc::clone(df),
+                    Arc::clone(models),
+                    auth,
+                    principal,
+                    request.model_name,
+                    input,
+                )
+                .await
+            }
+            Err(message) => bad_request(request.model_name, message, start_time),
+        }
+    }
+
+    /// Resolves a [`PredictInput`] of the model `model_name`, planning and authorizing its query.
+    async fn model_input(
+        df: &RwLock<DataFusion>,
+        models: &RwLock<HashMap<String, Model>>,
+        model_name: &str,
+        auth: Option<&Authenticator>,
+        principal: Option<&Principal>,
+        input: PredictInput,
+    ) -> Result<ModelInput, String> {
+        match (input.rows, input.sql) {
+            (Some(_), Some(_)) => Err("Set either rows or sql as the input, not both".to_string()),
+            (Some(rows), None) => {
+                let columns = match input.columns {
+                    Some(columns) => Some(columns),
+                    None => bound_dataset_columns(df, models, model_name).await?,
+                };
+                rows_to_batches(&rows, columns.as_deref())
+                    .map(ModelInput::Batches)
+                    .map_err(|e| format!("Unable to read the input rows: {e}"))
+            }
+            (None, Some(sql)) => {
+                let ctx = Arc::clone(&df.read().await.ctx);
+                let plan = ctx
+                    .state()
+                    .create_logical_plan(&sql)
+                    .await
+                    .map_err(|e| format!("Unable to plan the input query: {e}"))?;
+                auth::authorize_plan(auth, principal, &plan).map_err(|e| e.to_string())?;
+                Ok(ModelInput::Plan(plan))
+            }
+            (None, None) => Ok(ModelInput::Dataset),
+        }
+    }
+
+    /// Queries the rows of the dataset the model `model_name` is bound to, if it is bound to one.
+    async fn bound_dataset_rows(
+        df: &RwLock<DataFusion>,
+        models: &RwLock<HashMap<String, Model>>,
+        model_name: String,
+        auth: Option<&Authenticator>,
+        principal: Option<&Principal>,
+    ) -> Result<Option<Vec<RecordBatch>>, String> {
+        let dataset = models
+            .read()
+            .await
+            .get(&model_name)
+            .and_then(|model| model.model.datasets.first().cloned());
+        let Some(dataset) = dataset else {
+            return Ok(None);
+        };
+        auth::authorize(auth, principal, &dataset, Operation::Read).map_err(|e| e.to_string())?;
+
+        let ctx = Arc::clone(&df.read().await.ctx);
+        let rows = ctx
+            .sql(&dataset_query(&dataset))
+            .await
+            .map_err(|e| format!("Unable to query {dataset}: {e}"))?
+            .collect()
+            .await
+            .map_err(|e| format!("Unable to query {dataset}: {e}"))?;
+        Ok(Some(rows))
+    }
+
+    /// The columns of the dataset the model `model_name` is bound to, if it is bound to one.
+    async fn bound_dataset_columns(
+        df: &RwLock<DataFusion>,
+        models: &RwLock<HashMap<String, Model>>,
+        model_name: &str,
+    ) -> Result<Option<Vec<String>>, String> {
+        let dataset = models
+            .read()
+            .await
+            .get(model_name)
+            .and_then(|model| model.model.datasets.first().cloned());
+        let Some(dataset) = dataset else {
+            return Ok(None);
+        };
+
+        let schema = df
+            .read()
+            .await
+            .get_arrow_schema(&dataset)
+            .await
+            .map_err(|e| format!("Unable to get the schema of {dataset}: {e}"))?;
+        Ok(Some(
+            schema
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect(),
+        ))
+    }
+
+    /// Reads JSON feature rows into batches whose columns follow the order of `columns`. JSON objects don't keep the
+    /// order of their fields, so rows with several fields need `columns`.
+    fn rows_to_batches(
+        rows: &[Value],
+        columns: Option<&[String]>,
+    ) -> Result<Vec<RecordBatch>, ArrowError> {
+        let inferred = arrow_json::reader::infer_json_schema_from_iterator(rows.iter().map(Ok))?;
+        let fields: Vec<&Field> = match columns {
+            Some(columns) => {
+                if let Some(field) = inferred
+                    .fields()
+                    .iter()
+                    .find(|field| !columns.contains(field.name()))
+                {
+                    return Err(ArrowError::InvalidArgumentError(format!(
+                        "The rows have the field {}, which isn't one of the columns",
+                        field.name()
+                    )));
+                }
+                columns
+                    .iter()
+                    .filter_map(|column| inferred.field_with_name(column).ok())
+                    .collect()
+            }
+            None if inferred.fields().len() <= 1 => {
+                inferred.fields().iter().map(AsRef::as_ref).collect()
+            }
+            None => {
+                return Err(ArrowError::InvalidArgumentError(
+                    "Set columns to the order of the row fields, since the model isn't bound to a dataset"
+                        .to_string(),
+                ))
+            }
+        };
+        let schema = Schema::new(
+            fields
+                .into_iter()
+                .map(|field| match field.data_type() {
+                    DataType::Int64 => Field::new(field.name(), DataType::Float64, true),
+                    _ => field.clone(),
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let mut decoder = arrow_json::ReaderBuilder::new(Arc::new(schema))
+            .with_batch_size(rows.len().max(1))
+            .build_decoder()?;
+        decoder.serialize(rows)?;
+        Ok(decoder.flush()?.into_iter().collect())
+    }
+
+    fn read_arrow_stream(body: &[u8]) -> Result<Vec<RecordBatch>, ArrowError> {
+        StreamReader::try_new(Cursor::new(body), None)?.collect()
+    }
+
+    fn bad_request(model_name: String, message: String, start_time: Instant) -> PredictResponse {
+        PredictResponse {
+            status: PredictStatus::BadRequest,
+            error_message: Some(message),
+            model_name,
+            model_version: None,
+            prediction: None,
+            outputs: None,
+            request_id: None,
+            duration_ms: start_time.elapsed().as_millis(),
+        }
+    }
+
     async fn run_inference(
         app: Arc<RwLock<Option<App>>>,
         df: Arc<RwLock<DataFusion>>,
         models: Arc<RwLock<HashMap<String, Model>>>,
+        auth: Option<&Authenticator>,
+        principal: Option<&Principal>,
         model_name: String,
+        input: ModelInput,
     ) -> PredictResponse {
         let start_time = Instant::now();
 
@@ -746,6 +1676,8 @@ pub(crate) mod inference {
                 model_name,
                 model_version: None,
                 prediction: None,
+                outputs: None,
+                request_id: None,
                 duration_ms: start_time.elapsed().as_millis(),
             };
         };
@@ -759,10 +1691,18 @@ pub(crate) mod inference {
                 model_name,
                 model_version: None,
                 prediction: None,
+                outputs: None,
+                request_id: None,
                 duration_ms: start_time.elapsed().as_millis(),
             };
         };
 
+        if let (ModelInput::Dataset, Some(dataset)) = (&input, model.datasets.first()) {
+            if let Err(e) = auth::authorize(auth, principal, dataset, Operation::Read) {
+                return bad_request(model_name, e.to_string(), start_time);
+            }
+        }
+
         let loaded_models = models.read().await;
         let Some(runnable) = loaded_models.get(&model.name) else {
             tracing::debug!("Model {model_name} not found");
@@ -772,64 +1712,131 @@ pub(crate) mod inference {
                 model_name,
                 model_version: Some(model_version(&model.from)),
                 prediction: None,
+                outputs: None,
+                request_id: None,
                 duration_ms: start_time.elapsed().as_millis(),
             };
         };
 
-        match runnable.run(Arc::clone(&df)).await {
-            Ok(inference_result) => {
-                if let Some(column_data) = inference_result.column_by_name("y") {
-                    if let Some(array) = column_data.as_any().downcast_ref::<Float32Array>() {
-                        let result = array.values().iter().copied().collect_vec();
-                        return PredictResponse {
-                            status: PredictStatus::Success,
-                            error_message: None,
-                            model_name,
-                            model_version: Some(model_version(&model.from)),
-                            prediction: Some(result),
-                            duration_ms: start_time.elapsed().as_millis(),
-                        };
-                    }
+        let version = runnable.route();
+        drop(loaded_models);
+
+        let Inference {
+            output: result,
+            request_id,
+        } = match version.input_data(Arc::clone(&df), input).await {
+            Ok(data) => version.infer(data).await,
+            Err(e) => Inference {
+                output: Err(e),
+                request_id: None,
+            },
+        };
+
+        match result {
+            Ok(output) => match output_rows(&output) {
+                Ok(rows) => PredictResponse {
+                    status: PredictStatus::Success,
+                    error_message: None,
+                    model_name,
+                    model_version: Some(version.name.clone()),
+                    prediction: output
+                        .column_by_name("y")
+                        .and_then(|y| y.as_any().downcast_ref::<Float32Array>())
+                        .map(|y| y.values().to_vec()),
+                    outputs: Some(rows),
+                    request_id,
+                    duration_ms: start_time.elapsed().as_millis(),
+                },
+                Err(e) => {
                     tracing::error!(
-                        "Failed to cast inference result for model {model_name} to Float32Array"
+                        "Unable to serialize the inference result of {model_name}: {e}"
                     );
-                    tracing::debug!("Failed to cast inference result for model {model_name} to Float32Array: {column_data:?}");
-                    return PredictResponse {
+                    PredictResponse {
                         status: PredictStatus::InternalError,
-                        error_message: Some(
-                            "Unable to cast inference result to Float32Array".to_string(),
-                        ),
+                        error_message: Some(format!(
+                            "Unable to serialize the inference result: {e}"
+                        )),
                         model_name,
-                        model_version: Some(model_version(&model.from)),
+                        model_version: Some(version.name.clone()),
                         prediction: None,
+                        outputs: None,
+                        request_id,
                         duration_ms: start_time.elapsed().as_millis(),
-                    };
-                }
-                tracing::error!(
-                    "Unable to find column 'y' in inference result for model {model_name}"
-                );
-                PredictResponse {
-                    status: PredictStatus::InternalError,
-                    error_message: Some(
-                        "Unable to find column 'y' in inference result".to_string(),
-                    ),
-                    model_name,
-                    model_version: Some(model_version(&model.from)),
-                    prediction: None,
-                    duration_ms: start_time.elapsed().as_millis(),
+                    }
                 }
-            }
+            },
             Err(e) => {
                 tracing::error!("Unable to run inference: {e}");
                 PredictResponse {
                     status: PredictStatus::InternalError,
                     error_message: Some(e.to_string()),
                     model_name,
-                    model_version: Some(model_version(&model.from)),
+                    model_version: Some(version.name.clone()),
                     prediction: None,
+                    outputs: None,
+                    request_id,
                     duration_ms: start_time.elapsed().as_millis(),
                 }
             }
         }
     }
+
+    /// The rows of an inference result as JSON objects.
+    fn output_rows(
+        output: &RecordBatch,
+    ) -> Result<Vec<serde_json::Map<String, Value>>, ArrowError> {
+        let mut writer = arrow_json::ArrayWriter::new(Vec::new());
+        writer.write(output)?;
+        writer.finish()?;
+        serde_json::from_slice(&writer.into_inner())
+            .map_err(|e| ArrowError::JsonError(e.to_string()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_output_rows() {
+            let output = RecordBatch::try_new(
+                Arc::new(Schema::new(vec![
+                    Field::new("label", DataType::Utf8, false),
+                    Field::new("score", DataType::Float32, false),
+                ])),
+                vec![
+                    Arc::new(arrow::array::StringArray::from(vec!["a", "b"])),
+                    Arc::new(Float32Array::from(vec![0.5, 0.25])),
+                ],
+            )
+            .expect("valid batch");
+
+            let rows = output_rows(&output).expect("rows serialized");
+            assert_eq!(
+                serde_json::Value::from(rows.into_iter().map(Value::Object).collect::<Vec<_>>()),
+                serde_json::json!([
+                    {"label": "a", "score": 0.5},
+                    {"label": "b", "score": 0.25},
+                ])
+            );
+        }
+
+        #[test]
+        fn test_rows_to_batches_follows_columns() {
+            let rows = vec![
+                serde_json::json!({"b": 1, "a": 2.5}),
+                serde_json::json!({"a": 3.5, "b": 2}),
+            ];
+
+            let columns = ["b".to_string(), "a".to_string(), "c".to_string()];
+            let batches = rows_to_batches(&rows, Some(&columns)).expect("rows read");
+            let schema = batches[0].schema();
+            let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+            assert_eq!(names, vec!["b", "a"]);
+            assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+
+            assert!(rows_to_batches(&rows, Some(&["a".to_string()])).is_err());
+            assert!(rows_to_batches(&rows, None).is_err());
+            assert!(rows_to_batches(&[serde_json::json!({"a": 1})], None).is_ok());
+        }
+    }
 }
@@ -0,0 +1,479 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Decodes the rows written to a dataset over HTTP into record batches.
+
+use std::{io::Cursor, sync::Arc};
+
+use arrow::{
+    array::new_null_array,
+    csv,
+    datatypes::{FieldRef, Schema, SchemaRef},
+    error::ArrowError,
+    ipc::reader::{FileReader, StreamReader},
+    record_batch::RecordBatch,
+};
+use serde::Serialize;
+use serde_json::Value;
+use snafu::prelude::*;
+
+const BATCH_SIZE: usize = 8192;
+
+/// A row that can't be written, and why.
+///
+/// Rows of a JSON array are located by their index in the array, and rows of NDJSON and CSV bodies by their line
+/// number in the body, starting at 1.
+#[derive(Debug, Serialize)]
+pub(crate) struct RowError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) row: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) line: Option<usize>,
+    pub(crate) message: String,
+}
+
+#[derive(Debug, Snafu)]
+pub(crate) enum Error {
+    #[snafu(display("Unsupported content type {content_type}. Use application/json, application/x-ndjson, text/csv, application/vnd.apache.arrow.stream or application/vnd.apache.arrow.file."))]
+    UnsupportedContentType { content_type: String },
+
+    #[snafu(display("Unable to parse the JSON body: {source}"))]
+    InvalidJson { source: serde_json::Error },
+
+    #[snafu(display("{} rows don't match the dataset schema", errors.len()))]
+    InvalidRows { errors: Vec<RowError> },
+
+    #[snafu(display("Unable to read the CSV body: {source}"))]
+    InvalidCsv { source: ArrowError },
+
+    #[snafu(display("Unable to read the CSV header: {source}"))]
+    InvalidCsvHeader { source: ::csv::Error },
+
+    #[snafu(display("The CSV header doesn't match the dataset schema: {message}"))]
+    CsvHeaderMismatch { message: String },
+
+    #[snafu(display("Unable to read the Arrow IPC body: {source}"))]
+    InvalidArrow { source: ArrowError },
+
+    #[snafu(display("Unable to read batch {batch} of the Arrow IPC body: {source}"))]
+    InvalidArrowBatch { batch: usize, source: ArrowError },
+}
+
+pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The formats accepted in the body of a write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BodyFormat {
+    /// A JSON array of objects, or a single object.
+    Json,
+    /// One JSON object per line.
+    NdJson,
+    /// CSV with a header row naming the columns, in any order.
+    Csv,
+    ArrowStream,
+    ArrowFile,
+}
+
+impl BodyFormat {
+    pub(crate) fn from_content_type(content_type: &str) -> Result<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        match mime.as_str() {
+            "application/json" => Ok(Self::Json),
+            "application/x-ndjson"
+            | "application/ndjson"
+            | "application/jsonl"
+            | "application/x-jsonlines" => Ok(Self::NdJson),
+            "text/csv" => Ok(Self::Csv),
+            "application/vnd.apache.arrow.stream" => Ok(Self::ArrowStream),
+            "application/vnd.apache.arrow.file" => Ok(Self::ArrowFile),
+            _ => UnsupportedContentTypeSnafu { content_type }.fail(),
+        }
+    }
+}
+
+/// Decodes `body` into record batches.
+///
+/// JSON and CSV bodies are read against `schema`, the schema of the dataset. CSV columns are matched to the dataset
+/// columns by their header, and the nullable columns left out are null. Arrow IPC bodies carry their own schema, which
+/// is returned with the batches.
+pub(crate) fn decode(
+    format: BodyFormat,
+    body: &[u8],
+    schema: SchemaRef,
+) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    match format {
+        BodyFormat::Json => {
+            let rows = match serde_json::from_slice(body).context(InvalidJsonSnafu)? {
+                Value::Array(rows) => rows,
+                row => vec![row],
+            };
+            let batches = decode_json_rows(&rows, None, &schema)?;
+            Ok((schema, batches))
+        }
+        BodyFormat::NdJson => {
+            let mut rows = vec![];
+            let mut lines = vec![];
+            let mut errors = vec![];
+            for (index, line) in body.split(|byte| *byte == b'\n').enumerate() {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                match serde_json::from_slice(line) {
+                    Ok(row) => {
+                        rows.push(row);
+                        lines.push(index + 1);
+                    }
+                    Err(e) => errors.push(RowError {
+                        row: None,
+                        line: Some(index + 1),
+                        message: e.to_string(),
+                    }),
+                }
+            }
+            ensure!(errors.is_empty(), InvalidRowsSnafu { errors });
+
+            let batches = decode_json_rows(&rows, Some(&lines), &schema)?;
+            Ok((schema, batches))
+        }
+        BodyFormat::Csv => {
+            let header_schema = csv_header_schema(body, &schema)?;
+            let batches = match decode_csv(body, &header_schema) {
+                Ok(batches) => batches,
+                Err(e) => {
+                    let errors = csv_row_errors(body, &header_schema);
+                    ensure!(errors.is_empty(), InvalidRowsSnafu { errors });
+                    return Err(e).context(InvalidCsvSnafu);
+                }
+            };
+            let batches = batches
+                .iter()
+                .map(|batch| project_csv_batch(batch, &schema))
+                .collect::<Result<_, _>>()
+                .context(InvalidCsvSnafu)?;
+            Ok((schema, batches))
+        }
+        BodyFormat::ArrowStream => {
+            let reader =
+                StreamReader::try_new(Cursor::new(body), None).context(InvalidArrowSnafu)?;
+            let schema = reader.schema();
+            Ok((schema, collect_arrow_batches(reader)?))
+        }
+        BodyFormat::ArrowFile => {
+            let reader = FileReader::try_new(Cursor::new(body), None).context(InvalidArrowSnafu)?;
+            let schema = reader.schema();
+            Ok((schema, collect_arrow_batches(reader)?))
+        }
+    }
+}
+
+/// Reads the batches of an Arrow IPC body, reporting the batch that can't be read.
+fn collect_arrow_batches(
+    reader: impl Iterator<Item = Result<RecordBatch, ArrowError>>,
+) -> Result<Vec<RecordBatch>> {
+    reader
+        .enumerate()
+        .map(|(batch, result)| result.context(InvalidArrowBatchSnafu { batch }))
+        .collect()
+}
+
+/// Decodes JSON objects against `schema`, reporting every row that doesn't match it if any doesn't.
+///
+/// The rows are reported by their index, or by their line in `lines` if the rows were read from lines.
+fn decode_json_rows(
+    rows: &[Value],
+    lines: Option<&[usize]>,
+    schema: &SchemaRef,
+) -> Result<Vec<RecordBatch>> {
+    let batch_error = match decode_json(rows, schema) {
+        Ok(batches) => return Ok(batches),
+        Err(e) => e,
+    };
+
+    // Decode each row on its own to find the rows that don't match the schema.
+    let mut errors: Vec<RowError> = rows
+        .iter()
+        .enumerate()
+        .filter_map(|(row, value)| {
+            decode_json(std::slice::from_ref(value), schema)
+                .err()
+                .map(|e| RowError {
+                    row: lines.is_none().then_some(row),
+                    line: lines.and_then(|lines| lines.get(row).copied()),
+                    message: e.to_string(),
+                })
+        })
+        .collect();
+
+    if errors.is_empty() {
+        errors.push(RowError {
+            row: None,
+            line: None,
+            message: batch_error.to_string(),
+        });
+    }
+
+    InvalidRowsSnafu { errors }.fail()
+}
+
+/// The columns of `schema` named by the header of a CSV body, in the order of the header.
+///
+/// The header must name each column at most once, only name columns of `schema`, and name every non-nullable column.
+fn csv_header_schema(body: &[u8], schema: &SchemaRef) -> Result<SchemaRef> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(body);
+    let header = reader.headers().context(InvalidCsvHeaderSnafu)?;
+
+    let mut fields: Vec<FieldRef> = vec![];
+    for name in header {
+        let Ok(field) = schema.field_with_name(name) else {
+            return CsvHeaderMismatchSnafu {
+                message: format!("unknown column {name}"),
+            }
+            .fail();
+        };
+        ensure!(
+            !fields.iter().any(|field| field.name() == name),
+            CsvHeaderMismatchSnafu {
+                message: format!("duplicate column {name}"),
+            }
+        );
+        fields.push(Arc::new(field.clone()));
+    }
+
+    let missing: Vec<&str> = schema
+        .fields()
+        .iter()
+        .filter(|field| !field.is_nullable() && !fields.contains(field))
+        .map(|field| field.name().as_str())
+        .collect();
+    ensure!(
+        missing.is_empty(),
+        CsvHeaderMismatchSnafu {
+            message: format!("missing columns {}", missing.join(", ")),
+        }
+    );
+
+    Ok(Arc::new(Schema::new_with_metadata(
+        fields,
+        schema.metadata().clone(),
+    )))
+}
+
+/// Orders the columns of a batch read with [`csv_header_schema`] like `schema`, with null columns for the columns the
+/// header left out.
+fn project_csv_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) => Arc::clone(column),
+            None => new_null_array(field.data_type(), batch.num_rows()),
+        })
+        .collect();
+    RecordBatch::try_new(Arc::clone(schema), columns)
+}
+
+fn decode_csv(body: &[u8], schema: &SchemaRef) -> Result<Vec<RecordBatch>, ArrowError> {
+    csv::ReaderBuilder::new(Arc::clone(schema))
+        .with_header(true)
+        .with_batch_size(BATCH_SIZE)
+        .build(Cursor::new(body))?
+        .collect()
+}
+
+/// Decodes each record of a CSV body on its own against `schema`, to find the records that don't match it.
+fn csv_row_errors(body: &[u8], schema: &SchemaRef) -> Vec<RowError> {
+    let line_of = |position: Option<&::csv::Position>| {
+        position.and_then(|position| usize::try_from(position.line()).ok())
+    };
+
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(body);
+    let header = match reader.byte_headers() {
+        Ok(header) => header.clone(),
+        Err(e) => {
+            return vec![RowError {
+                row: None,
+                line: Some(1),
+                message: e.to_string(),
+            }]
+        }
+    };
+
+    reader
+        .byte_records()
+        .filter_map(|record| {
+            let (line, result) = match record {
+                Ok(record) => (
+                    line_of(record.position()),
+                    decode_csv_record(&header, &record, schema),
+                ),
+                Err(e) => (line_of(e.position()), Err(e.to_string())),
+            };
+            result.err().map(|message| RowError {
+                row: None,
+                line,
+                message,
+            })
+        })
+        .collect()
+}
+
+fn decode_csv_record(
+    header: &::csv::ByteRecord,
+    record: &::csv::ByteRecord,
+    schema: &SchemaRef,
+) -> Result<(), String> {
+    let mut writer = ::csv::Writer::from_writer(vec![]);
+    writer
+        .write_byte_record(header)
+        .and_then(|()| writer.write_byte_record(record))
+        .map_err(|e| e.to_string())?;
+    let body = writer.into_inner().map_err(|e| e.to_string())?;
+    decode_csv(&body, schema)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn decode_json(rows: &[Value], schema: &SchemaRef) -> Result<Vec<RecordBatch>, ArrowError> {
+    let mut decoder = arrow_json::ReaderBuilder::new(Arc::clone(schema))
+        .with_batch_size(BATCH_SIZE)
+        .with_strict_mode(true)
+        .build_decoder()?;
+
+    let mut batches = vec![];
+    for chunk in rows.chunks(BATCH_SIZE) {
+        decoder.serialize(chunk)?;
+        if let Some(batch) = decoder.flush()? {
+            batches.push(batch);
+        }
+    }
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::{
+        array::{Array, Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+    };
+
+    #[test]
+    fn test_decode_ndjson_reports_invalid_rows() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+
+        let body = b"{\"id\": 1, \"name\": \"a\"}\n\n{\"id\": 2}\n";
+        let (_, batches) =
+            decode(BodyFormat::NdJson, body, Arc::clone(&schema)).expect("rows decoded");
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 2);
+
+        let body = b"{\"id\": 1}\n{\"id\": \"x\"}\n{\"id\": 3, \"email\": \"c\"}\n";
+        let Err(Error::InvalidRows { errors }) = decode(BodyFormat::NdJson, body, schema) else {
+            panic!("expected invalid rows");
+        };
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![Some(2), Some(3)]
+        );
+
+        // Lines are counted including the blank lines.
+        let body = b"{\"id\": 1}\n\n{\"id\": \"x\"}\n";
+        let Err(Error::InvalidRows { errors }) = decode(
+            BodyFormat::NdJson,
+            body,
+            Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)])),
+        ) else {
+            panic!("expected invalid rows");
+        };
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![Some(3)]
+        );
+    }
+
+    #[test]
+    fn test_decode_csv_reports_invalid_rows() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+
+        let body = b"id,name\n1,a\nx,b\n3,c\n4,d,e\n";
+        let Err(Error::InvalidRows { errors }) = decode(BodyFormat::Csv, body, schema) else {
+            panic!("expected invalid rows");
+        };
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![Some(3), Some(5)]
+        );
+    }
+
+    #[test]
+    fn test_decode_csv_maps_columns_by_header() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("email", DataType::Utf8, true),
+        ]));
+
+        let body = b"name,id\na,1\nb,2\n";
+        let (_, batches) =
+            decode(BodyFormat::Csv, body, Arc::clone(&schema)).expect("rows decoded");
+        assert_eq!(batches[0].schema(), schema);
+        let ids = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .expect("id column");
+        assert_eq!(ids.values().to_vec(), vec![1, 2]);
+        let names = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .expect("name column");
+        assert_eq!(names.value(0), "a");
+        assert_eq!(batches[0].column(2).null_count(), 2);
+
+        for body in [&b"id,phone\n1,2\n"[..], b"id,id\n1,2\n", b"name\na\n"] {
+            assert!(matches!(
+                decode(BodyFormat::Csv, body, Arc::clone(&schema)),
+                Err(Error::CsvHeaderMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_body_format_from_content_type() {
+        assert_eq!(
+            BodyFormat::from_content_type("text/csv; charset=utf-8").ok(),
+            Some(BodyFormat::Csv)
+        );
+        assert!(BodyFormat::from_content_type("text/plain").is_err());
+    }
+}
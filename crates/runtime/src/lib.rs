@@ -40,11 +40,14 @@ use tokio::{signal, sync::RwLock};
 
 use crate::{dataconnector::DataConnector, datafusion::DataFusion};
 mod accelerated_table;
+pub mod auth;
+pub mod changes;
 pub mod config;
 pub mod dataaccelerator;
 pub mod dataconnector;
 pub mod datafusion;
 pub mod dataupdate;
+pub mod deduplication;
 pub mod execution_plan;
 mod flight;
 mod http;
@@ -54,8 +57,10 @@ pub mod modelruntime;
 pub mod modelsource;
 mod opentelemetry;
 pub mod podswatcher;
+pub mod schema_evolution;
 pub mod status;
 pub mod timing;
+pub mod tls;
 pub(crate) mod tracers;
 
 #[derive(Debug, Snafu)]
@@ -137,6 +142,23 @@ pub enum Error {
 
     #[snafu(display("Unable to load dataset connector: {dataset}"))]
     UnableToLoadDatasetConnector { dataset: String },
+
+    #[snafu(display("Unable to load TLS certificate: {source}"))]
+    UnableToLoadTls { source: tls::Error },
+
+    #[snafu(display("Unable to get the TLS key secret {secret}: {source}"))]
+    UnableToGetTlsKeySecret {
+        source: Box<dyn std::error::Error + Send + Sync>,
+        secret: String,
+    },
+
+    #[snafu(display("The TLS key secret {secret} was not found or has no `key` field"))]
+    TlsKeySecretNotFound { secret: String },
+
+    #[snafu(display(
+        "A TLS key is required with the TLS certificate, set --tls-key or --tls-key-secret"
+    ))]
+    MissingTlsKey,
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -148,7 +170,10 @@ pub struct Runtime {
     pub models: Arc<RwLock<HashMap<String, Model>>>,
     pub pods_watcher: podswatcher::PodsWatcher,
     pub secrets_provider: Arc<RwLock<secrets::SecretsProvider>>,
+    pub auth: Option<Arc<auth::Authenticator>>,
+    pub tls: Option<Arc<tls::TlsAcceptor>>,
 
+    tls_watcher: Option<notify::RecommendedWatcher>,
     spaced_tracer: Arc<tracers::SpacedTracer>,
 }
 
@@ -162,13 +187,25 @@ impl Runtime {
     ) -> Self {
         dataconnector::register_all().await;
         dataaccelerator::register_all().await;
+
+        let models = Arc::new(RwLock::new(HashMap::new()));
+        df.read().await.ctx.register_udtf(
+            datafusion::predict::PREDICT_FUNCTION_NAME,
+            Arc::new(datafusion::predict::PredictFunction::new(Arc::clone(
+                &models,
+            ))),
+        );
+
         Runtime {
             app,
             config,
             df,
-            models: Arc::new(RwLock::new(HashMap::new())),
+            models,
             pods_watcher,
             secrets_provider: Arc::new(RwLock::new(secrets::SecretsProvider::new())),
+            auth: None,
+            tls: None,
+            tls_watcher: None,
             spaced_tracer: Arc::new(tracers::SpacedTracer::new(Duration::from_secs(15))),
         }
     }
@@ -191,6 +228,118 @@ impl Runtime {
         }
     }
 
+    /// Loads the credentials for the spicepod `auth` section. Must be called after `load_secrets`.
+    ///
+    /// Authentication stays disabled when the spicepod has no `auth` section. If the credentials
+    /// can't be loaded, every request is rejected rather than falling back to no authentication.
+    pub async fn load_auth(&mut self) {
+        let app_lock = self.app.read().await;
+        let Some(app) = app_lock.as_ref() else {
+            return;
+        };
+        let Some(auth) = app.auth.as_ref() else {
+            if !app.access.is_empty() {
+                tracing::warn!("The spicepod access section has no effect without an auth section");
+            }
+            return;
+        };
+
+        let secrets_provider = self.secrets_provider.read().await;
+        let authenticator =
+            match auth::Authenticator::load(auth, &app.access, &secrets_provider).await {
+                Ok(authenticator) => authenticator,
+                Err(e) => {
+                    tracing::error!(
+                        "Unable to load auth credentials, all requests will be rejected: {e}"
+                    );
+                    auth::Authenticator::default()
+                }
+            };
+        drop(secrets_provider);
+        drop(app_lock);
+
+        self.auth = Some(Arc::new(authenticator));
+    }
+
+    /// Reloads the credentials and roles after the spicepod `auth` or `access` section changes.
+    ///
+    /// The servers are started with or without authentication, so turning it on or off requires a restart.
+    async fn reload_auth(&self, app: &App) {
+        match (&self.auth, &app.auth) {
+            (Some(authenticator), Some(auth)) => {
+                let secrets_provider = self.secrets_provider.read().await;
+                match authenticator
+                    .reload(auth, &app.access, &secrets_provider)
+                    .await
+                {
+                    Ok(()) => tracing::info!("Reloaded auth credentials"),
+                    Err(e) => tracing::error!(
+                        "Unable to reload auth credentials, the previous credentials remain in use: {e}"
+                    ),
+                }
+            }
+            (None, None) => {
+                if !app.access.is_empty() {
+                    tracing::warn!(
+                        "The spicepod access section has no effect without an auth section"
+                    );
+                }
+            }
+            _ => tracing::warn!(
+                "The spicepod auth section changed, restart the runtime to turn authentication on or off"
+            ),
+        }
+    }
+
+    /// Loads the TLS certificate configured with `--tls-cert`. Must be called after `load_secrets`.
+    ///
+    /// The certificate is reloaded whenever its files change.
+    pub async fn load_tls(&mut self) -> Result<()> {
+        let Some(cert) = self.config.tls_cert.clone() else {
+            return Ok(());
+        };
+
+        let key = match (&self.config.tls_key, &self.config.tls_key_secret) {
+            (Some(key), _) => tls::TlsKey::File(key.clone()),
+            (None, Some(secret_name)) => {
+                let secret = self
+                    .secrets_provider
+                    .read()
+                    .await
+                    .get_secret(secret_name)
+                    .await
+                    .context(UnableToGetTlsKeySecretSnafu {
+                        secret: secret_name.clone(),
+                    })?;
+                let pem = secret
+                    .as_ref()
+                    .and_then(|secret| secret.get("key"))
+                    .context(TlsKeySecretNotFoundSnafu {
+                        secret: secret_name.clone(),
+                    })?;
+                tls::TlsKey::Pem(pem.to_string())
+            }
+            (None, None) => return MissingTlsKeySnafu.fail(),
+        };
+
+        let acceptor = Arc::new(
+            tls::TlsAcceptor::new(tls::TlsConfig {
+                cert,
+                key,
+                client_ca: self.config.tls_client_ca.clone(),
+            })
+            .context(UnableToLoadTlsSnafu)?,
+        );
+
+        match acceptor.watch() {
+            Ok(watcher) => self.tls_watcher = Some(watcher),
+            Err(e) => tracing::warn!("TLS certificate changes will not be reloaded: {e}"),
+        }
+        self.tls = Some(acceptor);
+
+        Ok(())
+    }
+
     pub async fn load_datasets(&self) {
         let app_lock = self.app.read().await;
         if let Some(app) = app_lock.as_ref() {
@@ -512,19 +661,23 @@ impl Runtime {
     pub async fn load_model(&self, m: &SpicepodModel) {
         measure_scope_ms!("load_model", "model" => m.name, "source" => model::source(&m.from));
         tracing::info!("Loading model [{}] from {}...", m.name, m.from);
-        let mut model_map = self.models.write().await;
 
         let model = m.clone();
-        let source = model::source(&model.from);
 
         let shared_secrets_provider = Arc::clone(&self.secrets_provider);
         let secrets_provider = shared_secrets_provider.read().await;
 
-        let secret = match secrets_provider.get_secret(source.as_str()).await {
-            Ok(s) => s,
+        let secrets = match model::load_secrets(&model, &secrets_provider).await {
+            Ok(secrets) => secrets,
             Err(e) => {
                 metrics::counter!("models_load_error").increment(1);
-                status::update_model(&model.name, status::ComponentStatus::Error);
+                // The previous versions of the model, if any, keep serving predictions.
+                let status = if self.models.read().await.contains_key(&m.name) {
+                    status::ComponentStatus::Ready
+                } else {
+                    status::ComponentStatus::Error
+                };
+                status::update_model(&model.name, status);
                 tracing::warn!(
                     "Unable to load runnable model from spicepod {}, error: {}",
                     m.name,
@@ -533,22 +686,52 @@ impl Runtime {
                 return;
             }
         };
-
-        match Model::load(m.clone(), secret).await {
+        drop(secrets_provider);
+
+        let loaded = self.models.read().await;
+        let serving = loaded.contains_key(&m.name);
+        let loaded_versions = loaded
+            .get(&m.name)
+            .map(|loaded| loaded.reusable_versions(m))
+            .unwrap_or_default();
+        drop(loaded);
+
+        // The new versions are swapped in once they have all loaded and warmed up, so the model keeps serving
+        // predictions while it is updated.
+        match Model::load(
+            m.clone(),
+            &secrets,
+            loaded_versions,
+            Arc::clone(&self.df),
+            serving,
+        )
+        .await
+        {
             Ok(in_m) => {
-                model_map.insert(m.name.clone(), in_m);
+                let replaced = self.models.write().await.insert(m.name.clone(), in_m);
                 tracing::info!("Model [{}] deployed, ready for inferencing", m.name);
-                metrics::gauge!("models_count", "model" => m.name.clone(), "source" => model::source(&m.from)).increment(1.0);
+                if replaced.is_none() {
+                    metrics::gauge!("models_count", "model" => m.name.clone(), "source" => model::source(&m.from)).increment(1.0);
+                }
                 status::update_model(&model.name, status::ComponentStatus::Ready);
             }
             Err(e) => {
                 metrics::counter!("models_load_error").increment(1);
-                status::update_model(&model.name, status::ComponentStatus::Error);
-                tracing::warn!(
-                    "Unable to load runnable model from spicepod {}, error: {}",
-                    m.name,
-                    e,
-                );
+                if self.models.read().await.contains_key(&m.name) {
+                    status::update_model(&model.name, status::ComponentStatus::Ready);
+                    tracing::warn!(
+                        "Unable to update model {}, the previous versions keep serving predictions: {}",
+                        m.name,
+                        e,
+                    );
+                } else {
+                    status::update_model(&model.name, status::ComponentStatus::Error);
+                    tracing::warn!(
+                        "Unable to load runnable model from spicepod {}, error: {}",
+                        m.name,
+                        e,
+                    );
+                }
             }
         }
     }
@@ -569,7 +752,6 @@ impl Runtime {
 
     pub async fn update_model(&self, m: &SpicepodModel) {
         status::update_model(&m.name, status::ComponentStatus::Refreshing);
-        self.remove_model(m).await;
         self.load_model(m).await;
     }
 
@@ -580,14 +762,22 @@ impl Runtime {
             Arc::clone(&self.df),
             Arc::clone(&self.models),
             self.config.clone().into(),
+            self.auth.as_ref().map(Arc::clone),
+            self.tls.as_ref().map(Arc::clone),
             with_metrics,
         );
 
-        let flight_server_future =
-            flight::start(self.config.flight_bind_address, Arc::clone(&self.df));
+        let flight_server_future = flight::start(
+            self.config.flight_bind_address,
+            Arc::clone(&self.df),
+            self.auth.as_ref().map(Arc::clone),
+            self.tls.as_ref().map(Arc::clone),
+        );
         let open_telemetry_server_future = opentelemetry::start(
             self.config.open_telemetry_bind_address,
             Arc::clone(&self.df),
+            self.auth.as_ref().map(Arc::clone),
+            self.tls.as_ref().map(Arc::clone),
         );
         let pods_watcher_future = self.start_pods_watcher();
 
@@ -660,6 +850,10 @@ impl Runtime {
                     }
                 }
 
+                if current_app.auth != new_app.auth || current_app.access != new_app.access {
+                    self.reload_auth(&new_app).await;
+                }
+
                 *current_app = new_app;
             } else {
                 *app_lock = Some(new_app);
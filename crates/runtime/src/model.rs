@@ -14,21 +14,61 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::modelformat::ModelFormat;
+use crate::modelruntime::executor::{BatchOptions, Executor};
+use crate::modelruntime::tract::Tract;
+use crate::modelruntime::trees::{TreeEnsemble, TreeFormat};
 use crate::modelruntime::ModelRuntime;
-use crate::modelruntime::Runnable;
 use crate::modelsource::create_source_from;
 use crate::DataFusion;
+use arrow::datatypes::{Schema, SchemaRef};
 use arrow::record_batch::RecordBatch;
-use secrets::Secret;
+use datafusion::logical_expr::LogicalPlan;
+use secrets::{Secret, SecretsProvider};
 use snafu::prelude::*;
+use spicepod::component::params::Params;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::RwLock;
+use uuid::Uuid;
 
+use self::inference_log::{InferenceLogger, InferenceRecord};
+
+pub mod inference_log;
+
+/// How long an inference request waits for others to batch with, when the model doesn't set it.
+const DEFAULT_BATCHING_MAX_WAIT: Duration = Duration::from_millis(5);
+
+/// A loaded model: the versions of it that are loaded, with the share of predictions each serves.
 pub struct Model {
-    runnable: Box<dyn Runnable>,
+    versions: Vec<(Arc<ModelVersion>, u32)>,
+    predictions: AtomicU64,
     pub model: spicepod::component::model::Model,
 }
 
+/// A loaded version of a model.
+pub struct ModelVersion {
+    /// The name predictions report the version by.
+    pub name: String,
+    pub from: String,
+    model_name: String,
+    datasets: Vec<String>,
+    /// The schema of the bound dataset when the version was loaded.
+    dataset_schema: Option<SchemaRef>,
+    /// Where predictions are logged, set by the model currently serving the version.
+    inference_log: std::sync::RwLock<Option<InferenceLogger>>,
+    executor: Executor,
+}
+
+/// A prediction served by a model version.
+pub struct Inference {
+    pub output: Result<RecordBatch>,
+    /// The id the prediction was logged under, if the model logs its predictions.
+    pub request_id: Option<String>,
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -49,80 +89,487 @@ pub enum Error {
     #[snafu(display("Unable to run model: {source}"))]
     UnableToRunModel { source: crate::modelruntime::Error },
 
-    #[snafu(display("Unable to load required secrets"))]
-    UnableToLoadRequiredSecrets {},
+    #[snafu(display("Unable to get secret {name}: {source}"))]
+    UnableToGetSecret {
+        name: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Secret {name} not found"))]
+    SecretNotFound { name: String },
+
+    #[snafu(display("Model {name} is not bound to a dataset, so it needs input rows or a query"))]
+    NoBoundDataset { name: String },
+
+    #[snafu(display("The version weights of model {name} add up to more than 100"))]
+    InvalidVersionWeights { name: String },
+
+    #[snafu(display("Version {version} failed its warm-up inference: {source}"))]
+    WarmUpFailed { version: String, source: Box<Error> },
+}
+
+/// The input a model runs inference on.
+pub enum ModelInput {
+    /// All the rows of the first dataset the model is bound to, ordered by `ts`.
+    Dataset,
+
+    /// Feature rows supplied by the caller.
+    Batches(Vec<RecordBatch>),
+
+    /// The result of a query.
+    Plan(LogicalPlan),
 }
 
 impl Model {
+    /// Loads the versions of `model`, reusing the `loaded` versions that are unchanged. Each newly loaded version runs a
+    /// warm-up inference on the dataset the model is bound to, if it is bound to one.
+    ///
+    /// A version must pass its warm-up to replace the versions of a model that is already `serving` predictions. A model
+    /// that isn't serving yet is loaded even if its warm-up fails, since its dataset may still be loading.
     pub async fn load(
         model: spicepod::component::model::Model,
-        secret: Option<Secret>,
+        secrets: &HashMap<String, Option<Secret>>,
+        loaded: Vec<Arc<ModelVersion>>,
+        df: Arc<RwLock<DataFusion>>,
+        serving: bool,
     ) -> Result<Self> {
-        let source = source(&model.from);
-        let source = source.as_str();
+        let mut versions = Vec::new();
+        for (from, name, weight) in traffic(&model)? {
+            if let Some(version) = loaded
+                .iter()
+                .find(|version| version.from == from && version.name == name)
+            {
+                versions.push((Arc::clone(version), weight));
+                continue;
+            }
 
-        let Some(secret) = secret else {
-            tracing::warn!(
-                "Unable to load model {}: unable to get secret for source {}",
-                model.name,
-                source
-            );
-            return UnableToLoadRequiredSecretsSnafu {}.fail();
+            let secret = secrets.get(&from).cloned().flatten();
+            let mut version = ModelVersion::load(&model, from, name, secret).await?;
+            if let Err(e) = version.warm_up(Arc::clone(&df)).await {
+                if serving {
+                    return Err(e);
+                }
+                tracing::warn!("Loading model {} without a warm-up: {e}", model.name);
+            }
+            versions.push((Arc::new(version), weight));
+        }
+
+        let logger = model.inference_log.as_ref().map(|log| InferenceLogger {
+            df: Arc::downgrade(&df),
+            dataset: log.dataset.clone(),
+        });
+        for (version, _) in &versions {
+            version.set_inference_log(logger.clone());
+        }
+
+        Ok(Self {
+            versions,
+            predictions: AtomicU64::new(0),
+            model,
+        })
+    }
+
+    /// The loaded versions `model` can keep using, which are those of a model that only differs in its versions.
+    #[must_use]
+    pub fn reusable_versions(
+        &self,
+        model: &spicepod::component::model::Model,
+    ) -> Vec<Arc<ModelVersion>> {
+        if artifact_config(&self.model) != artifact_config(model) {
+            return Vec::new();
+        }
+
+        self.versions
+            .iter()
+            .map(|(version, _)| Arc::clone(version))
+            .collect()
+    }
+
+    /// A model serving a single version that runs `runnable`.
+    #[cfg(test)]
+    pub(crate) fn for_tests(
+        model: spicepod::component::model::Model,
+        runnable: Box<dyn crate::modelruntime::Runnable>,
+        dataset_schema: Option<SchemaRef>,
+    ) -> Self {
+        let version = ModelVersion {
+            name: model.name.clone(),
+            from: model.from.clone(),
+            model_name: model.name.clone(),
+            datasets: model.datasets.clone(),
+            dataset_schema,
+            inference_log: std::sync::RwLock::new(None),
+            executor: Executor::new(runnable, None),
         };
+        Self {
+            versions: vec![(Arc::new(version), 100)],
+            predictions: AtomicU64::new(0),
+            model,
+        }
+    }
+
+    /// The version to serve the next prediction. Predictions are split between the versions by their weights.
+    #[must_use]
+    pub fn route(&self) -> Arc<ModelVersion> {
+        let weights = self
+            .versions
+            .iter()
+            .map(|(_, weight)| *weight)
+            .collect::<Vec<_>>();
+        let prediction = self.predictions.fetch_add(1, Ordering::Relaxed);
+        Arc::clone(&self.versions[pick_version(&weights, prediction)].0)
+    }
+}
+
+impl ModelVersion {
+    async fn load(
+        model: &spicepod::component::model::Model,
+        from: String,
+        name: String,
+        secret: Option<Secret>,
+    ) -> Result<Self> {
+        let source = source(&from);
+        let source = source.as_str();
 
-        let mut params = std::collections::HashMap::new();
+        let mut params = model
+            .params
+            .as_ref()
+            .map(Params::as_string_map)
+            .unwrap_or_default();
         params.insert("name".to_string(), model.name.to_string());
-        params.insert("path".to_string(), path(&model.from));
-        params.insert("from".to_string(), path(&model.from));
+        params.insert("path".to_string(), path(&from));
+        params.insert("from".to_string(), path(&from));
         params.insert("files".to_string(), model.files.join(",").to_string());
+        params.insert("format".to_string(), model.format.to_string());
+
+        let path = create_source_from(source)
+            .context(UnknownModelSourceSnafu)?
+            .pull(secret, Arc::new(Option::from(params)))
+            .await
+            .context(UnableToLoadModelSnafu)?
+            .clone()
+            .to_string();
 
-        let tract = crate::modelruntime::tract::Tract {
-            path: create_source_from(source)
-                .context(UnknownModelSourceSnafu)?
-                .pull(secret, Arc::new(Option::from(params)))
-                .await
-                .context(UnableToLoadModelSnafu)?
-                .clone()
-                .to_string(),
+        let params = model
+            .params
+            .as_ref()
+            .map(Params::as_string_map)
+            .unwrap_or_default();
+        let runnable = match ModelFormat::from(model.format) {
+            ModelFormat::Onnx(_) => Tract {
+                path,
+                tensors: model.tensors.clone(),
+                params,
+            }
+            .load(),
+            ModelFormat::Xgboost(_) => TreeEnsemble {
+                path,
+                format: TreeFormat::Xgboost,
+                params,
+            }
+            .load(),
+            ModelFormat::Lightgbm(_) => TreeEnsemble {
+                path,
+                format: TreeFormat::Lightgbm,
+                params,
+            }
+            .load(),
         }
-        .load()
         .context(UnableToInitModelSnafu {})?;
 
+        let batching = model.batching.as_ref().map(|batching| BatchOptions {
+            max_rows: batching.max_rows,
+            max_wait: model
+                .batching_max_wait()
+                .unwrap_or(DEFAULT_BATCHING_MAX_WAIT),
+        });
+
         Ok(Self {
-            runnable: tract,
-            model: model.clone(),
+            name,
+            from,
+            model_name: model.name.clone(),
+            datasets: model.datasets.clone(),
+            dataset_schema: None,
+            inference_log: std::sync::RwLock::new(None),
+            executor: Executor::new(runnable, batching),
         })
     }
 
-    pub async fn run(&self, df: Arc<RwLock<DataFusion>>) -> Result<RecordBatch> {
-        let data = df
-            .read()
+    /// Runs inference on the bound dataset, so a version that can't serve predictions isn't swapped in. The inference is
+    /// skipped while the dataset is empty.
+    async fn warm_up(&mut self, df: Arc<RwLock<DataFusion>>) -> Result<()> {
+        let Some(dataset) = self.datasets.first() else {
+            return Ok(());
+        };
+
+        let ctx = Arc::clone(&df.read().await.ctx);
+        let table = ctx
+            .table(dataset.as_str())
             .await
-            .ctx
-            .sql(
-                &(format!(
-                    "select * from datafusion.public.{} order by ts asc",
-                    self.model.datasets[0]
-                )),
-            )
+            .context(UnableToQuerySnafu {})?;
+        self.dataset_schema = Some(Arc::new(Schema::from(table.schema())));
+
+        let warm_up_failed = |e| Error::WarmUpFailed {
+            version: self.name.clone(),
+            source: Box::new(e),
+        };
+        let data = self
+            .input_data(df, ModelInput::Dataset)
             .await
-            .context(UnableToQuerySnafu {})?
-            .collect()
+            .map_err(warm_up_failed)?;
+        if data.iter().all(|batch| batch.num_rows() == 0) {
+            tracing::debug!(
+                "Skipping the warm-up inference of version {} of model {}, {dataset} is empty",
+                self.name,
+                self.model_name
+            );
+            return Ok(());
+        }
+
+        self.infer(data).await.output.map_err(warm_up_failed)?;
+        tracing::debug!(
+            "Version {} of model {} passed its warm-up inference",
+            self.name,
+            self.model_name
+        );
+
+        Ok(())
+    }
+
+    /// The datasets the model is bound to.
+    #[must_use]
+    pub fn datasets(&self) -> &[String] {
+        &self.datasets
+    }
+
+    /// The schema of the dataset the model is bound to, as of when the version was loaded.
+    #[must_use]
+    pub fn dataset_schema(&self) -> Option<SchemaRef> {
+        self.dataset_schema.as_ref().map(Arc::clone)
+    }
+
+    /// Whether the model returns a prediction for each input row, in the order of the rows.
+    #[must_use]
+    pub fn is_row_wise(&self) -> bool {
+        self.executor.runnable().is_row_wise()
+    }
+
+    pub async fn run(&self, df: Arc<RwLock<DataFusion>>, input: ModelInput) -> Result<RecordBatch> {
+        let data = self.input_data(df, input).await?;
+        self.infer(data).await.output
+    }
+
+    /// The rows `input` refers to.
+    pub async fn input_data(
+        &self,
+        df: Arc<RwLock<DataFusion>>,
+        input: ModelInput,
+    ) -> Result<Vec<RecordBatch>> {
+        let data = match input {
+            ModelInput::Dataset => {
+                let Some(dataset) = self.datasets.first() else {
+                    return NoBoundDatasetSnafu {
+                        name: self.model_name.clone(),
+                    }
+                    .fail();
+                };
+                let ctx = Arc::clone(&df.read().await.ctx);
+                ctx.sql(&dataset_query(dataset))
+                    .await
+                    .context(UnableToQuerySnafu {})?
+                    .collect()
+                    .await
+                    .context(UnableToQuerySnafu {})?
+            }
+            ModelInput::Batches(batches) => batches,
+            ModelInput::Plan(plan) => {
+                let ctx = Arc::clone(&df.read().await.ctx);
+                ctx.execute_logical_plan(plan)
+                    .await
+                    .context(UnableToQuerySnafu {})?
+                    .collect()
+                    .await
+                    .context(UnableToQuerySnafu {})?
+            }
+        };
+
+        Ok(data)
+    }
+
+    /// Runs inference on `data` on the inference worker pool, logging the prediction if the model logs its
+    /// predictions.
+    pub async fn infer(&self, data: Vec<RecordBatch>) -> Inference {
+        let Some(logger) = self.inference_logger() else {
+            return Inference {
+                output: self.execute(data).await,
+                request_id: None,
+            };
+        };
+
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let output = self.execute(data.clone()).await;
+        let Some(df) = logger.df.upgrade() else {
+            return Inference {
+                output,
+                request_id: None,
+            };
+        };
+
+        let request_id = Uuid::new_v4().to_string();
+        inference_log::log_inference(
+            df,
+            logger.dataset,
+            InferenceRecord {
+                request_id: request_id.clone(),
+                model: self.model_name.clone(),
+                version: self.name.clone(),
+                started,
+                duration: start.elapsed(),
+                inputs: data,
+                output: output.as_ref().cloned().map_err(ToString::to_string),
+            },
+        );
+
+        Inference {
+            output,
+            request_id: Some(request_id),
+        }
+    }
+
+    async fn execute(&self, data: Vec<RecordBatch>) -> Result<RecordBatch> {
+        self.executor
+            .run(data)
             .await
-            .context(UnableToQuerySnafu {})?;
+            .context(UnableToRunModelSnafu {})
+    }
 
-        let result = self.runnable.run(data).context(UnableToRunModelSnafu {})?;
+    fn inference_logger(&self) -> Option<InferenceLogger> {
+        match self.inference_log.read() {
+            Ok(logger) => logger.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
 
-        Ok(result)
+    fn set_inference_log(&self, logger: Option<InferenceLogger>) {
+        match self.inference_log.write() {
+            Ok(mut log) => *log = logger,
+            Err(poisoned) => *poisoned.into_inner() = logger,
+        }
+    }
+
+    /// The schema of the inference results.
+    #[must_use]
+    pub fn output_schema(&self) -> SchemaRef {
+        self.executor.runnable().output_schema()
     }
 }
 
+/// The `from`, name and weight of each version of `model`, starting with the version in `from`.
+fn traffic(model: &spicepod::component::model::Model) -> Result<Vec<(String, String, u32)>> {
+    let weights = model
+        .versions
+        .iter()
+        .try_fold(0u32, |total, version| total.checked_add(version.weight))
+        .filter(|&weights| weights <= 100);
+    let Some(weights) = weights else {
+        return InvalidVersionWeightsSnafu {
+            name: model.name.clone(),
+        }
+        .fail();
+    };
+
+    let mut traffic = vec![(model.from.clone(), version(&model.from), 100 - weights)];
+    traffic.extend(model.versions.iter().map(|v| {
+        (
+            v.from.clone(),
+            v.name.clone().unwrap_or_else(|| version(&v.from)),
+            v.weight,
+        )
+    }));
+    Ok(traffic)
+}
+
+/// The parts of `model` its loaded versions depend on.
+fn artifact_config(model: &spicepod::component::model::Model) -> spicepod::component::model::Model {
+    let mut model = model.clone();
+    model.from = String::new();
+    model.versions = Vec::new();
+    model.inference_log = None;
+    model
+}
+
+/// The index of the version that serves the `prediction`th prediction, cycling through the versions in proportion to
+/// their `weights`.
+fn pick_version(weights: &[u32], prediction: u64) -> usize {
+    let total: u64 = weights.iter().map(|&weight| u64::from(weight)).sum();
+    if total == 0 {
+        return 0;
+    }
+
+    let mut slot = prediction % total;
+    for (index, &weight) in weights.iter().enumerate() {
+        let weight = u64::from(weight);
+        if slot < weight {
+            return index;
+        }
+        slot -= weight;
+    }
+    0
+}
+
+/// The secret each version of `model` authenticates with, by the `from` it is pulled from: the secret the version
+/// refers to, or else the secret named after its source, if there is one. The model's `secret` is only for the
+/// version in `from`, so credentials for one source aren't sent to another.
+pub async fn load_secrets(
+    model: &spicepod::component::model::Model,
+    secrets_provider: &SecretsProvider,
+) -> Result<HashMap<String, Option<Secret>>> {
+    let versions = std::iter::once((&model.from, &model.secret)).chain(
+        model
+            .versions
+            .iter()
+            .map(|version| (&version.from, &version.secret)),
+    );
+
+    let mut secrets = HashMap::new();
+    for (from, secret) in versions {
+        let secret = match secret {
+            Some(name) => Some(
+                secrets_provider
+                    .get_secret(name)
+                    .await
+                    .context(UnableToGetSecretSnafu { name })?
+                    .context(SecretNotFoundSnafu { name })?,
+            ),
+            None => {
+                let name = source(from);
+                secrets_provider
+                    .get_secret(&name)
+                    .await
+                    .context(UnableToGetSecretSnafu { name })?
+            }
+        };
+        secrets.insert(from.clone(), secret);
+    }
+
+    Ok(secrets)
+}
+
+/// The query for the input of a model bound to `dataset`.
+#[must_use]
+pub(crate) fn dataset_query(dataset: &str) -> String {
+    format!("select * from datafusion.public.{dataset} order by ts asc")
+}
+
 #[must_use]
 pub(crate) fn source(from: &str) -> String {
     match from {
         s if s.starts_with("spiceai:") => "spiceai".to_string(),
         s if s.starts_with("huggingface:") => "huggingface".to_string(),
         s if s.starts_with("file:/") => "localhost".to_string(),
+        s if s.starts_with("s3://") => "s3".to_string(),
+        s if s.starts_with("https://") || s.starts_with("http://") => "https".to_string(),
         _ => "spiceai".to_string(),
     }
 }
@@ -148,3 +595,57 @@ pub fn version(from: &str) -> String {
     let path = path(from);
     path.split(':').last().unwrap_or("").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_version() {
+        let picks = (0..10)
+            .map(|prediction| pick_version(&[7, 3], prediction))
+            .collect::<Vec<_>>();
+        assert_eq!(picks.iter().filter(|&&index| index == 1).count(), 3);
+
+        assert_eq!(pick_version(&[0, 100], 5), 1);
+        assert_eq!(pick_version(&[0, 0], 5), 0);
+    }
+
+    #[test]
+    fn test_traffic_weights() {
+        let model = |weights: &[u32]| -> spicepod::component::model::Model {
+            serde_json::from_value(serde_json::json!({
+                "from": "file:/models/v1.onnx",
+                "name": "model",
+                "versions": weights
+                    .iter()
+                    .enumerate()
+                    .map(|(index, weight)| serde_json::json!({
+                        "from": format!("file:/models/v{}.onnx", index + 2),
+                        "weight": weight,
+                    }))
+                    .collect::<Vec<_>>(),
+            }))
+            .expect("valid model")
+        };
+
+        let weights = |traffic: Vec<(String, String, u32)>| {
+            traffic
+                .into_iter()
+                .map(|(_, _, weight)| weight)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            weights(traffic(&model(&[30, 70])).expect("valid weights")),
+            vec![0, 30, 70]
+        );
+        assert!(matches!(
+            traffic(&model(&[60, 50])),
+            Err(Error::InvalidVersionWeights { .. })
+        ));
+        assert!(matches!(
+            traffic(&model(&[u32::MAX, 1])),
+            Err(Error::InvalidVersionWeights { .. })
+        ));
+    }
+}
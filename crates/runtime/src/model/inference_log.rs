@@ -0,0 +1,232 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Logs the predictions a model serves to a writable dataset, so they can be queried with SQL alongside the data
+//! they were made on.
+
+use std::{
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use arrow::{
+    array::{Float64Array, StringArray, TimestampMillisecondArray},
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use tokio::sync::RwLock;
+
+use crate::{
+    datafusion::DataFusion,
+    dataupdate::{DataUpdate, UpdateType},
+};
+
+/// The most rows of a prediction's inputs, and of its output, that are logged, so large predictions don't bloat the
+/// log.
+pub const MAX_LOGGED_ROWS: usize = 100;
+
+/// Where a model version logs its predictions.
+#[derive(Clone)]
+pub(crate) struct InferenceLogger {
+    /// Held weakly, since the runtime's `DataFusion` holds the loaded models for the `predict` function.
+    pub df: Weak<RwLock<DataFusion>>,
+    pub dataset: String,
+}
+
+/// A prediction served by a model.
+pub struct InferenceRecord {
+    pub request_id: String,
+    pub model: String,
+    pub version: String,
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub inputs: Vec<RecordBatch>,
+    pub output: Result<RecordBatch, String>,
+}
+
+/// The schema of the rows predictions are logged as.
+#[must_use]
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            false,
+        ),
+        Field::new("request_id", DataType::Utf8, false),
+        Field::new("model", DataType::Utf8, false),
+        Field::new("version", DataType::Utf8, false),
+        Field::new("duration_ms", DataType::Float64, false),
+        Field::new("inputs", DataType::Utf8, false),
+        Field::new("outputs", DataType::Utf8, true),
+        Field::new("error", DataType::Utf8, true),
+    ]))
+}
+
+/// Serializes `record` and writes it to `dataset` in the background, so logging doesn't delay the prediction.
+pub(crate) fn log_inference(df: Arc<RwLock<DataFusion>>, dataset: String, record: InferenceRecord) {
+    tokio::spawn(async move {
+        let request_id = record.request_id.clone();
+        let batch = match to_record_batch(&record) {
+            Ok(batch) => batch,
+            Err(e) => {
+                metrics::counter!("inference_log_errors", "dataset" => dataset).increment(1);
+                tracing::warn!("Unable to log prediction {request_id}: {e}");
+                return;
+            }
+        };
+
+        let data_update = DataUpdate {
+            schema: batch.schema(),
+            data: vec![batch],
+            update_type: UpdateType::Append,
+        };
+        if let Err(e) = df
+            .read()
+            .await
+            .write_data(&dataset, data_update, Some(&request_id))
+            .await
+        {
+            metrics::counter!("inference_log_errors", "dataset" => dataset.clone()).increment(1);
+            tracing::warn!("Unable to log prediction {request_id} to {dataset}: {e}");
+        }
+    });
+}
+
+/// The row `record` is logged as. Only the first [`MAX_LOGGED_ROWS`] rows of the inputs and output are logged.
+pub fn to_record_batch(record: &InferenceRecord) -> Result<RecordBatch, ArrowError> {
+    let ts = record
+        .started
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| i64::try_from(since_epoch.as_millis()).unwrap_or(i64::MAX))
+        .unwrap_or_default();
+    let inputs = to_json(&head(&record.inputs, MAX_LOGGED_ROWS))?;
+    let (outputs, error) = match &record.output {
+        Ok(output) => (
+            Some(to_json(&head(
+                std::slice::from_ref(output),
+                MAX_LOGGED_ROWS,
+            ))?),
+            None,
+        ),
+        Err(error) => (None, Some(error.clone())),
+    };
+
+    RecordBatch::try_new(
+        schema(),
+        vec![
+            Arc::new(TimestampMillisecondArray::from(vec![ts])),
+            Arc::new(StringArray::from(vec![record.request_id.clone()])),
+            Arc::new(StringArray::from(vec![record.model.clone()])),
+            Arc::new(StringArray::from(vec![record.version.clone()])),
+            Arc::new(Float64Array::from(vec![
+                record.duration.as_secs_f64() * 1000.0,
+            ])),
+            Arc::new(StringArray::from(vec![inputs])),
+            Arc::new(StringArray::from(vec![outputs])),
+            Arc::new(StringArray::from(vec![error])),
+        ],
+    )
+}
+
+/// The first `rows` rows of `batches`.
+fn head(batches: &[RecordBatch], rows: usize) -> Vec<RecordBatch> {
+    let mut head = Vec::new();
+    let mut remaining = rows;
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        let batch = batch.slice(0, remaining.min(batch.num_rows()));
+        remaining -= batch.num_rows();
+        head.push(batch);
+    }
+    head
+}
+
+/// The rows of `batches` as a JSON array.
+fn to_json(batches: &[RecordBatch]) -> Result<String, ArrowError> {
+    let mut writer = arrow_json::ArrayWriter::new(Vec::new());
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+
+    String::from_utf8(writer.into_inner()).map_err(|e| ArrowError::ExternalError(Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, AsArray, Float32Array};
+
+    use super::*;
+
+    #[test]
+    fn test_to_record_batch() {
+        let output = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("y", DataType::Float32, false)])),
+            vec![Arc::new(Float32Array::from(vec![0.5]))],
+        )
+        .expect("valid batch");
+        let mut record = InferenceRecord {
+            request_id: "r1".to_string(),
+            model: "churn".to_string(),
+            version: "v2".to_string(),
+            started: UNIX_EPOCH + Duration::from_secs(1),
+            duration: Duration::from_micros(1500),
+            inputs: vec![],
+            output: Ok(output),
+        };
+
+        let batch = to_record_batch(&record).expect("log row");
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.column(5).as_string::<i32>().value(0), "[]");
+        assert_eq!(
+            batch.column(6).as_string::<i32>().value(0),
+            r#"[{"y":0.5}]"#
+        );
+        assert!(batch.column(7).is_null(0));
+
+        record.output = Err("unable to run".to_string());
+        let batch = to_record_batch(&record).expect("log row");
+        assert!(batch.column(6).is_null(0));
+        assert_eq!(batch.column(7).as_string::<i32>().value(0), "unable to run");
+    }
+
+    #[test]
+    fn test_to_record_batch_caps_inputs() {
+        let inputs = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("x", DataType::Float32, false)])),
+            vec![Arc::new(Float32Array::from(vec![1.0; MAX_LOGGED_ROWS]))],
+        )
+        .expect("valid batch");
+        let record = InferenceRecord {
+            request_id: "r1".to_string(),
+            model: "churn".to_string(),
+            version: "v2".to_string(),
+            started: UNIX_EPOCH,
+            duration: Duration::ZERO,
+            inputs: vec![inputs.clone(), inputs],
+            output: Err("unable to run".to_string()),
+        };
+
+        let batch = to_record_batch(&record).expect("log row");
+        let logged: Vec<serde_json::Value> =
+            serde_json::from_str(batch.column(5).as_string::<i32>().value(0)).expect("JSON rows");
+        assert_eq!(logged.len(), MAX_LOGGED_ROWS);
+    }
+}
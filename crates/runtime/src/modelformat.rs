@@ -14,11 +14,27 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use spicepod::component::model::Format;
+
+pub mod lightgbm;
 pub mod onnx;
+pub mod xgboost;
 
 /// A `ModelFormat` specifies the supported format of a model artifacts.
 ///
-/// Currently, only `onnx` is supported.
+/// `onnx` models are loaded by `Tract`, and `xgboost` and `lightgbm` tree ensembles by `TreeEnsemble`.
 pub enum ModelFormat {
     Onnx(onnx::Onnx),
+    Xgboost(xgboost::Xgboost),
+    Lightgbm(lightgbm::Lightgbm),
+}
+
+impl From<Format> for ModelFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Onnx => ModelFormat::Onnx(onnx::Onnx {}),
+            Format::Xgboost => ModelFormat::Xgboost(xgboost::Xgboost {}),
+            Format::Lightgbm => ModelFormat::Lightgbm(lightgbm::Lightgbm {}),
+        }
+    }
 }
@@ -14,10 +14,14 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use arrow::record_batch::RecordBatch;
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use std::collections::HashMap;
 use std::result::Result;
 
+pub mod executor;
+pub mod tensors;
 pub mod tract;
+pub mod trees;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
@@ -27,14 +31,35 @@ pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub trait Runnable: Send + Sync {
     // Run inference with the input and loaded model
     fn run(&self, input: Vec<RecordBatch>) -> Result<RecordBatch, Error>;
+
+    // The schema of the `RecordBatch` returned by `run`
+    fn output_schema(&self) -> SchemaRef;
+
+    // Whether the model returns a row for each input row, so the inputs of several runs can be merged into one
+    fn is_row_wise(&self) -> bool {
+        false
+    }
 }
 
 /// A `ModelRuntime` loads a model into it supported `ModelFormat`.
-/// Currently only `Tract` + `Onnx` is supported
+/// `Tract` loads `Onnx` models, and `TreeEnsemble` loads `Xgboost` and `Lightgbm` tree ensembles.
 ///
 /// Implementing `load` is required, which returns a `Runnable` in a particular `ModelFormat`.
 pub trait ModelRuntime {
     // Load the model into the runtime and return a runnable
-    // TODO: add format parameter when more formats are supported
     fn load(&self) -> Result<Box<dyn Runnable>, Error>;
 }
+
+/// The value of the boolean model param `name`, or `default` when the model doesn't set it.
+pub(crate) fn bool_param(
+    params: &HashMap<String, String>,
+    name: &str,
+    default: bool,
+) -> Result<bool, Error> {
+    match params.get(name) {
+        Some(value) => value.parse().map_err(|_| {
+            format!("The model param {name} must be true or false, not {value}").into()
+        }),
+        None => Ok(default),
+    }
+}
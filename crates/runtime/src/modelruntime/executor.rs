@@ -0,0 +1,274 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Runs models on a bounded pool of blocking workers, merging the inputs of concurrent runs into one.
+
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+
+use arrow::record_batch::RecordBatch;
+use lazy_static::lazy_static;
+use snafu::prelude::*;
+use tokio::{
+    sync::{mpsc, oneshot, Semaphore},
+    time::{timeout_at, Instant},
+};
+
+use super::Runnable;
+
+/// The most runs that can wait for a model at once, before new runs wait to be queued.
+const QUEUE_SIZE: usize = 1024;
+
+lazy_static! {
+    /// Bounds the models running at once across all models, so a burst of predictions can't take every blocking
+    /// thread.
+    static ref WORKERS: Arc<Semaphore> = Arc::new(Semaphore::new(
+        std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    ));
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("The model is no longer running"))]
+    ExecutorStopped,
+}
+
+/// Limits on merging concurrent runs of a model.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions {
+    /// The most input rows in a merged run.
+    pub max_rows: usize,
+
+    /// How long a run waits for others to merge with.
+    pub max_wait: Duration,
+}
+
+struct Job {
+    input: Vec<RecordBatch>,
+    result: oneshot::Sender<Result<RecordBatch, super::Error>>,
+}
+
+/// Queues the runs of a model, and runs them on the worker pool.
+pub struct Executor {
+    runnable: Arc<dyn Runnable>,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Executor {
+    /// Starts running `runnable`. Runs are merged with `batching` when the model returns a row for each input row.
+    #[must_use]
+    pub fn new(runnable: Box<dyn Runnable>, batching: Option<BatchOptions>) -> Self {
+        let runnable: Arc<dyn Runnable> = Arc::from(runnable);
+        let batching = batching.filter(|_| runnable.is_row_wise());
+        let (jobs, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(dispatch(Arc::clone(&runnable), receiver, batching));
+
+        Self { runnable, jobs }
+    }
+
+    #[must_use]
+    pub fn runnable(&self) -> &dyn Runnable {
+        self.runnable.as_ref()
+    }
+
+    /// Runs the model on `input` once a worker is free.
+    pub async fn run(&self, input: Vec<RecordBatch>) -> Result<RecordBatch, super::Error> {
+        let (result, receiver) = oneshot::channel();
+        self.jobs
+            .send(Job { input, result })
+            .await
+            .map_err(|_| Error::ExecutorStopped)?;
+        receiver.await.map_err(|_| Error::ExecutorStopped)?
+    }
+}
+
+/// Groups the queued jobs into runs until the executor is dropped.
+async fn dispatch(
+    runnable: Arc<dyn Runnable>,
+    mut jobs: mpsc::Receiver<Job>,
+    batching: Option<BatchOptions>,
+) {
+    let mut next = None;
+    loop {
+        let job = match next.take() {
+            Some(job) => job,
+            None => match jobs.recv().await {
+                Some(job) => job,
+                None => break,
+            },
+        };
+
+        let mut rows = input_rows(&job);
+        let mut group = vec![job];
+        if let Some(batching) = batching {
+            let deadline = Instant::now() + batching.max_wait;
+            while rows < batching.max_rows {
+                match timeout_at(deadline, jobs.recv()).await {
+                    Ok(Some(job)) if rows + input_rows(&job) > batching.max_rows => {
+                        next = Some(job);
+                        break;
+                    }
+                    Ok(Some(job)) => {
+                        rows += input_rows(&job);
+                        group.push(job);
+                    }
+                    Ok(None) | Err(_) => break,
+                }
+            }
+        }
+
+        let Ok(permit) = Arc::clone(&WORKERS).acquire_owned().await else {
+            break;
+        };
+        let runnable = Arc::clone(&runnable);
+        tokio::task::spawn_blocking(move || {
+            run_group(runnable.as_ref(), group);
+            drop(permit);
+        });
+    }
+}
+
+fn input_rows(job: &Job) -> usize {
+    job.input.iter().map(RecordBatch::num_rows).sum()
+}
+
+fn run_group(runnable: &dyn Runnable, group: Vec<Job>) {
+    if group.len() > 1 {
+        if let Some(outputs) = run_merged(runnable, &group) {
+            tracing::debug!("Ran {} inference requests as one batch", group.len());
+            for (job, output) in group.into_iter().zip(outputs) {
+                let _ = job.result.send(Ok(output));
+            }
+            return;
+        }
+    }
+
+    for job in group {
+        let _ = job.result.send(runnable.run(job.input));
+    }
+}
+
+/// Runs the inputs of `group` as one input, and splits the output by the rows of each input.
+///
+/// Returns `None` when the inputs have different schemas, the run fails, or the output doesn't have a row for each
+/// input row, so the jobs can be run one by one instead.
+fn run_merged(runnable: &dyn Runnable, group: &[Job]) -> Option<Vec<RecordBatch>> {
+    let schema = group.first()?.input.first()?.schema();
+    let input = group
+        .iter()
+        .flat_map(|job| job.input.iter().cloned())
+        .collect::<Vec<_>>();
+    if input.iter().any(|batch| batch.schema() != schema) {
+        return None;
+    }
+
+    let output = match runnable.run(input) {
+        Ok(output) => output,
+        Err(e) => {
+            tracing::debug!("Unable to run inference requests as one batch: {e}");
+            return None;
+        }
+    };
+
+    let rows = group.iter().map(input_rows).collect::<Vec<_>>();
+    if output.num_rows() != rows.iter().sum::<usize>() {
+        return None;
+    }
+
+    let mut offset = 0;
+    Some(
+        rows.into_iter()
+            .map(|rows| {
+                let slice = output.slice(offset, rows);
+                offset += rows;
+                slice
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use arrow::{
+        array::{AsArray, Float64Array},
+        compute::kernels::numeric::mul,
+        datatypes::{DataType, Field, Float64Type, Schema, SchemaRef},
+    };
+
+    use super::*;
+
+    /// Doubles `x`, counting its runs.
+    struct Double {
+        runs: Arc<AtomicUsize>,
+    }
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![Field::new("x", DataType::Float64, false)]))
+    }
+
+    impl Runnable for Double {
+        fn run(&self, input: Vec<RecordBatch>) -> Result<RecordBatch, crate::modelruntime::Error> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            let input = arrow::compute::concat_batches(&schema(), &input)?;
+            let doubled = mul(input.column(0), &Float64Array::new_scalar(2.0))?;
+            Ok(RecordBatch::try_new(schema(), vec![doubled])?)
+        }
+
+        fn output_schema(&self) -> SchemaRef {
+            schema()
+        }
+
+        fn is_row_wise(&self) -> bool {
+            true
+        }
+    }
+
+    fn batch(values: Vec<f64>) -> RecordBatch {
+        RecordBatch::try_new(schema(), vec![Arc::new(Float64Array::from(values))])
+            .expect("valid batch")
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_runs_are_merged() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(
+            Box::new(Double {
+                runs: Arc::clone(&runs),
+            }),
+            Some(BatchOptions {
+                max_rows: 16,
+                max_wait: Duration::from_millis(100),
+            }),
+        );
+
+        let (a, b) = tokio::join!(
+            executor.run(vec![batch(vec![1.0, 2.0])]),
+            executor.run(vec![batch(vec![3.0])]),
+        );
+
+        let values = |output: RecordBatch| {
+            output
+                .column(0)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec()
+        };
+        assert_eq!(values(a.expect("result")), vec![2.0, 4.0]);
+        assert_eq!(values(b.expect("result")), vec![6.0]);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
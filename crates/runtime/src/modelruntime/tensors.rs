@@ -0,0 +1,342 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Maps the columns of record batches to the flat values of model tensors, and back.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, FixedSizeListArray, PrimitiveArray, UInt32Array},
+    compute::{cast, take},
+    datatypes::{ArrowPrimitiveType, DataType, Field},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Column \"{column}\" is not in the input."))]
+    MissingColumn { column: String },
+
+    #[snafu(display(
+        "Column \"{column}\" has type {data_type}. Tensor columns need a numeric or fixed size list type."
+    ))]
+    UnsupportedColumnType { column: String, data_type: DataType },
+
+    #[snafu(display("Column \"{column}\" has nulls, which can't be passed to a model."))]
+    NullValues { column: String },
+
+    #[snafu(display("Shape {shape:?} can't hold {values} values."))]
+    IncompatibleShape { shape: Vec<i64>, values: usize },
+
+    #[snafu(display("Shape {shape:?} needs {needed} rows, but the input has {rows}."))]
+    RowCountMismatch {
+        shape: Vec<i64>,
+        needed: usize,
+        rows: usize,
+    },
+
+    #[snafu(display(
+        "An output with {width} values per row can't be read into the columns {columns:?}."
+    ))]
+    IncompatibleOutputColumns { columns: Vec<String>, width: usize },
+
+    #[snafu(display("Unable to convert tensor values: {source}"))]
+    UnableToConvert { source: ArrowError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Collects the values of `columns` row by row, as the input tensor with `shape`.
+///
+/// Returns the values and the resolved shape. A `-1` dimension takes the size that fits all the rows, and a shape
+/// without one needs exactly the rows it holds.
+pub fn input_values<T: ArrowPrimitiveType>(
+    batches: &[RecordBatch],
+    columns: &[String],
+    shape: &[i64],
+) -> Result<(Vec<T::Native>, Vec<usize>)> {
+    let row_width = match batches.first() {
+        Some(batch) => row_width(batch, columns)?,
+        None => 0,
+    };
+    let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+    let fixed: usize = shape
+        .iter()
+        .filter(|&&dim| dim >= 0)
+        .map(|&dim| usize::try_from(dim).unwrap_or_default())
+        .product();
+    let dynamic = shape.iter().filter(|&&dim| dim < 0).count();
+
+    let incompatible = || IncompatibleShapeSnafu {
+        shape: shape.to_vec(),
+        values: rows * row_width,
+    };
+    match dynamic {
+        0 => {
+            ensure!(row_width > 0 && fixed % row_width == 0, incompatible());
+            let needed = fixed / row_width;
+            // Dropping or padding rows would misalign the predictions with the input rows.
+            ensure!(
+                needed == rows,
+                RowCountMismatchSnafu {
+                    shape: shape.to_vec(),
+                    needed,
+                    rows,
+                }
+            );
+        }
+        1 => {
+            ensure!(fixed > 0 && (rows * row_width) % fixed == 0, incompatible());
+        }
+        _ => return incompatible().fail(),
+    };
+
+    let mut values = Vec::with_capacity(rows * row_width);
+    for batch in batches {
+        let arrays = columns
+            .iter()
+            .map(|column| column_values::<T>(batch, column))
+            .collect::<Result<Vec<_>>>()?;
+        for row in 0..batch.num_rows() {
+            for (values_array, width) in &arrays {
+                let start = row * width;
+                values.extend_from_slice(&values_array.values()[start..start + width]);
+            }
+        }
+    }
+
+    let resolved = shape
+        .iter()
+        .map(|&dim| match usize::try_from(dim) {
+            Ok(dim) => dim,
+            Err(_) => values.len() / fixed,
+        })
+        .collect();
+
+    Ok((values, resolved))
+}
+
+/// The number of values each row contributes to an input tensor.
+fn row_width(batch: &RecordBatch, columns: &[String]) -> Result<usize> {
+    let schema = batch.schema();
+    columns
+        .iter()
+        .map(|column| {
+            let Ok(field) = schema.field_with_name(column) else {
+                return MissingColumnSnafu { column }.fail();
+            };
+            match field.data_type() {
+                DataType::FixedSizeList(_, size) => Ok(usize::try_from(*size).unwrap_or_default()),
+                data_type if data_type.is_numeric() => Ok(1),
+                data_type => UnsupportedColumnTypeSnafu {
+                    column,
+                    data_type: data_type.clone(),
+                }
+                .fail(),
+            }
+        })
+        .sum()
+}
+
+/// The values of `column` in `batch` as `T`, with the number of values in each row.
+fn column_values<T: ArrowPrimitiveType>(
+    batch: &RecordBatch,
+    column: &str,
+) -> Result<(PrimitiveArray<T>, usize)> {
+    let Some(array) = batch.column_by_name(column) else {
+        return MissingColumnSnafu { column }.fail();
+    };
+    ensure!(array.null_count() == 0, NullValuesSnafu { column });
+
+    let (values, width) = match array.data_type() {
+        DataType::FixedSizeList(_, size) => {
+            let list = array.as_fixed_size_list();
+            let width = usize::try_from(*size).unwrap_or_default();
+            let start = usize::try_from(list.value_offset(0)).unwrap_or_default();
+            (list.values().slice(start, list.len() * width), width)
+        }
+        data_type if data_type.is_numeric() => (Arc::clone(array), 1),
+        data_type => {
+            return UnsupportedColumnTypeSnafu {
+                column,
+                data_type: data_type.clone(),
+            }
+            .fail()
+        }
+    };
+    ensure!(values.null_count() == 0, NullValuesSnafu { column });
+
+    let values = cast(&values, &T::DATA_TYPE).context(UnableToConvertSnafu)?;
+    Ok((values.as_primitive::<T>().clone(), width))
+}
+
+/// The number of values in each row of an output tensor with `shape`, which is the size of its last dimension.
+#[must_use]
+pub fn output_width(shape: &[usize]) -> usize {
+    match shape {
+        [] | [_] => 1,
+        [.., last] => *last,
+    }
+}
+
+/// The fields `columns` are read into from an output tensor of `data_type`, with `width` values in each row.
+pub fn output_fields(columns: &[String], data_type: &DataType, width: usize) -> Result<Vec<Field>> {
+    match columns {
+        [column] if width != 1 => Ok(vec![Field::new(
+            column,
+            DataType::new_fixed_size_list(data_type.clone(), list_size(width), false),
+            false,
+        )]),
+        columns if columns.len() == width => Ok(columns
+            .iter()
+            .map(|column| Field::new(column, data_type.clone(), false))
+            .collect()),
+        columns => IncompatibleOutputColumnsSnafu {
+            columns: columns.to_vec(),
+            width,
+        }
+        .fail(),
+    }
+}
+
+/// Reads the flat `values` of an output tensor, with `width` values in each row, into `columns`.
+pub fn output_columns(
+    columns: &[String],
+    values: &ArrayRef,
+    width: usize,
+) -> Result<Vec<ArrayRef>> {
+    let fields = output_fields(columns, values.data_type(), width)?;
+
+    if let [field] = fields.as_slice() {
+        if let DataType::FixedSizeList(item, size) = field.data_type() {
+            let list =
+                FixedSizeListArray::try_new(Arc::clone(item), *size, Arc::clone(values), None)
+                    .context(UnableToConvertSnafu)?;
+            return Ok(vec![Arc::new(list)]);
+        }
+    }
+
+    let rows = values.len() / width.max(1);
+    (0..fields.len())
+        .map(|offset| {
+            let indices = (0..rows)
+                .map(|row| u32::try_from(row * width + offset).unwrap_or(u32::MAX))
+                .collect::<UInt32Array>();
+            take(values, &indices, None).context(UnableToConvertSnafu)
+        })
+        .collect()
+}
+
+fn list_size(width: usize) -> i32 {
+    i32::try_from(width).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{Float32Array, Float64Array, Int64Array},
+        datatypes::{Float32Type, Schema},
+    };
+
+    use super::*;
+
+    fn batch() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Float64, false),
+            Field::new("b", DataType::Int64, false),
+            Field::new(
+                "v",
+                DataType::new_fixed_size_list(DataType::Float32, 2, false),
+                false,
+            ),
+        ]);
+        let v = FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, false)),
+            2,
+            Arc::new(Float32Array::from(vec![10.0, 11.0, 20.0, 21.0, 30.0, 31.0])),
+            None,
+        )
+        .expect("valid list");
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0])),
+                Arc::new(Int64Array::from(vec![4, 5, 6])),
+                Arc::new(v),
+            ],
+        )
+        .expect("valid batch")
+    }
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_input_values_by_row() {
+        let (values, shape) =
+            input_values::<Float32Type>(&[batch()], &columns(&["a", "b"]), &[-1, 2])
+                .expect("values");
+        assert_eq!(values, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert_eq!(shape, vec![3, 2]);
+
+        let (values, shape) =
+            input_values::<Float32Type>(&[batch().slice(0, 2)], &columns(&["a", "v"]), &[1, 2, 3])
+                .expect("values");
+        assert_eq!(values, vec![1.0, 10.0, 11.0, 2.0, 20.0, 21.0]);
+        assert_eq!(shape, vec![1, 2, 3]);
+
+        // A fixed shape needs exactly as many rows as it holds.
+        assert!(matches!(
+            input_values::<Float32Type>(&[batch()], &columns(&["a", "v"]), &[1, 2, 3]),
+            Err(Error::RowCountMismatch {
+                needed: 2,
+                rows: 3,
+                ..
+            })
+        ));
+        assert!(matches!(
+            input_values::<Float32Type>(&[batch()], &columns(&["a"]), &[1, 4]),
+            Err(Error::RowCountMismatch { needed: 4, .. })
+        ));
+        assert!(matches!(
+            input_values::<Float32Type>(&[batch()], &columns(&["a", "b"]), &[-1, 4]),
+            Err(Error::IncompatibleShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_output_columns() {
+        let values: ArrayRef = Arc::new(Float32Array::from(vec![0.1, 0.9, 0.7, 0.3]));
+
+        let split = output_columns(&columns(&["p0", "p1"]), &values, 2).expect("columns");
+        assert_eq!(
+            split[1].as_primitive::<Float32Type>().values().to_vec(),
+            vec![0.9, 0.3]
+        );
+
+        let embedding = output_columns(&columns(&["embedding"]), &values, 2).expect("columns");
+        assert_eq!(embedding[0].len(), 2);
+        assert!(matches!(
+            embedding[0].data_type(),
+            DataType::FixedSizeList(_, 2)
+        ));
+
+        assert!(output_columns(&columns(&["a", "b", "c"]), &values, 2).is_err());
+    }
+}
@@ -14,16 +14,27 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use super::{ModelRuntime, Runnable};
+use super::tensors;
+use super::{bool_param, ModelRuntime, Runnable};
 use arrow::array::ArrayRef;
 use arrow::array::Float32Array;
 use arrow::array::Float64Array;
+use arrow::array::Int32Array;
+use arrow::array::Int64Array;
+use arrow::datatypes::ArrowPrimitiveType;
 use arrow::datatypes::DataType;
 use arrow::datatypes::Field;
+use arrow::datatypes::Float32Type;
+use arrow::datatypes::Float64Type;
+use arrow::datatypes::Int32Type;
+use arrow::datatypes::Int64Type;
 use arrow::datatypes::Schema;
+use arrow::datatypes::SchemaRef;
 use arrow::record_batch::RecordBatch;
 use snafu::prelude::*;
 use snafu::ResultExt;
+use spicepod::component::model::tensors::{DType, Input, Output, Tensors};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use tract_core::tract_data::itertools::Itertools;
@@ -31,6 +42,13 @@ use tract_onnx::prelude::*;
 
 pub struct Tract {
     pub path: String,
+
+    /// The mapping of the model's tensors to columns, if the model declares one.
+    pub tensors: Option<Tensors>,
+
+    /// The model's params. `optimize: false` runs the model as it is saved, for models tract's optimizer can't
+    /// handle.
+    pub params: HashMap<String, String>,
 }
 
 #[derive(Debug, Snafu)]
@@ -43,13 +61,41 @@ pub enum Error {
 
     #[snafu(display("{source}"))]
     ShapeError { source: ndarray::ShapeError },
+
+    #[snafu(display("{source}"))]
+    TensorMappingError { source: tensors::Error },
+
+    #[snafu(display("The model has no {kind} named \"{name}\""))]
+    UnknownTensor { kind: &'static str, name: String },
+
+    #[snafu(display("The model has {expected} inputs, but the tensors mapping has {actual}"))]
+    InputCountMismatch { expected: usize, actual: usize },
+
+    #[snafu(display("The tensors mapping lists the model {kind} {index} more than once"))]
+    DuplicateTensor { kind: &'static str, index: usize },
+
+    #[snafu(display("Tensors of type {datum_type:?} are not supported"))]
+    UnsupportedDatumType { datum_type: DatumType },
 }
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 type Plan = SimplePlan<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
 pub struct Model {
     model: Plan,
+    mapping: Option<Mapping>,
 }
+
+/// A declared tensors mapping, resolved against the inputs and outputs of the model.
+struct Mapping {
+    /// The inputs, in the order the model takes them.
+    inputs: Vec<Input>,
+
+    /// The index of each output in the model, with the number of values in each of its rows.
+    outputs: Vec<(usize, Output, usize)>,
+
+    schema: SchemaRef,
+}
+
 impl Model {
     // Attempts to get the shape of the input tensor expected by the Tract model. Parses the first
     // `input_fact`. Input shape of the form: [1, lookback_size, num_variates].
@@ -73,27 +119,239 @@ impl Model {
 
 impl ModelRuntime for Tract {
     fn load(&self) -> std::result::Result<Box<dyn Runnable>, super::Error> {
-        let model = load_tract_model(self.path.as_str()).context(TractSnafu)?;
-        Ok(Box::new(Model { model }))
+        let optimize = bool_param(&self.params, "optimize", true)?;
+        let Some(tensors) = &self.tensors else {
+            let model = load_tract_model(self.path.as_str(), optimize).context(TractSnafu)?;
+            return Ok(Box::new(Model {
+                model,
+                mapping: None,
+            }));
+        };
+
+        let mut model = tract_onnx::onnx()
+            .model_for_path(self.path.as_str())
+            .context(TractSnafu)?;
+
+        let input_names = model
+            .input_outlets()
+            .context(TractSnafu)?
+            .iter()
+            .map(|outlet| model.node(outlet.node).name.clone())
+            .collect_vec();
+        let inputs = order_by_model(&input_names, &tensors.inputs, "input", |input| {
+            input.name.as_deref()
+        })?;
+        ensure!(
+            inputs.len() == input_names.len() && inputs.iter().all(Option::is_some),
+            InputCountMismatchSnafu {
+                expected: input_names.len(),
+                actual: tensors.inputs.len(),
+            }
+        );
+        let inputs = inputs.into_iter().flatten().collect_vec();
+
+        // Fixed input shapes let the model be optimized for them.
+        for (index, input) in inputs.iter().enumerate() {
+            if let Ok(shape) = input
+                .shape
+                .iter()
+                .map(|&dim| usize::try_from(dim))
+                .collect::<std::result::Result<Vec<_>, _>>()
+            {
+                model
+                    .set_input_fact(
+                        index,
+                        InferenceFact::dt_shape(datum_type(input.dtype), shape),
+                    )
+                    .context(TractSnafu)?;
+            }
+        }
+
+        let output_names = model
+            .output_outlets()
+            .context(TractSnafu)?
+            .iter()
+            .map(|outlet| {
+                model
+                    .outlet_label(*outlet)
+                    .map_or_else(|| model.node(outlet.node).name.clone(), ToString::to_string)
+            })
+            .collect_vec();
+        let outputs = order_by_model(&output_names, &tensors.outputs, "output", |output| {
+            output.name.as_deref()
+        })?;
+
+        let model = into_plan(model, optimize).context(TractSnafu)?;
+
+        let mut fields = vec![];
+        let mut mapped_outputs = vec![];
+        for (index, output) in outputs.into_iter().enumerate() {
+            let Some(output) = output else {
+                continue;
+            };
+            let fact = model.model().output_fact(index).context(TractSnafu)?;
+            let shape = fact
+                .shape
+                .iter()
+                .map(|dim| dim.to_usize().unwrap_or(1))
+                .collect_vec();
+            let width = tensors::output_width(&shape);
+            fields.extend(
+                tensors::output_fields(&output.columns, &arrow_type(fact.datum_type)?, width)
+                    .context(TensorMappingSnafu)?,
+            );
+            mapped_outputs.push((index, output, width));
+        }
+
+        Ok(Box::new(Model {
+            model,
+            mapping: Some(Mapping {
+                inputs,
+                outputs: mapped_outputs,
+                schema: Arc::new(Schema::new(fields)),
+            }),
+        }))
+    }
+}
+
+fn load_tract_model(path: &str, optimize: bool) -> TractResult<Plan> {
+    into_plan(tract_onnx::onnx().model_for_path(path)?, optimize)
+}
+
+fn into_plan(model: InferenceModel, optimize: bool) -> TractResult<Plan> {
+    let model = if optimize {
+        model.into_optimized()?
+    } else {
+        model.into_typed()?
+    };
+    model.into_runnable()
+}
+
+/// Places each of `tensors` at the index of the model tensor it is for, which is the tensor with its name or,
+/// without a name, the tensor at its position in the list.
+fn order_by_model<T: Clone>(
+    names: &[String],
+    tensors: &[T],
+    kind: &'static str,
+    name: impl Fn(&T) -> Option<&str>,
+) -> Result<Vec<Option<T>>> {
+    let mut ordered = vec![None; names.len().max(tensors.len())];
+    for (position, tensor) in tensors.iter().enumerate() {
+        let index =
+            match name(tensor) {
+                Some(tensor_name) => names.iter().position(|name| name == tensor_name).context(
+                    UnknownTensorSnafu {
+                        kind,
+                        name: tensor_name,
+                    },
+                )?,
+                None => position,
+            };
+        ensure!(
+            ordered[index].is_none(),
+            DuplicateTensorSnafu { kind, index }
+        );
+        ordered[index] = Some(tensor.clone());
     }
+    Ok(ordered)
 }
 
-fn load_tract_model(path: &str) -> TractResult<Plan> {
-    tract_onnx::onnx()
-        .model_for_path(path)?
-        .into_optimized()?
-        .into_runnable()
+fn datum_type(dtype: DType) -> DatumType {
+    match dtype {
+        DType::Float32 => DatumType::F32,
+        DType::Float64 => DatumType::F64,
+        DType::Int32 => DatumType::I32,
+        DType::Int64 => DatumType::I64,
+    }
+}
+
+fn arrow_type(datum_type: DatumType) -> Result<DataType> {
+    match datum_type {
+        DatumType::F32 => Ok(DataType::Float32),
+        DatumType::F64 => Ok(DataType::Float64),
+        DatumType::I32 => Ok(DataType::Int32),
+        DatumType::I64 => Ok(DataType::Int64),
+        datum_type => UnsupportedDatumTypeSnafu { datum_type }.fail(),
+    }
+}
+
+fn input_tensor(batches: &[RecordBatch], input: &Input) -> Result<TValue> {
+    let tensor = match input.dtype {
+        DType::Float32 => tensor_from::<Float32Type>(batches, input),
+        DType::Float64 => tensor_from::<Float64Type>(batches, input),
+        DType::Int32 => tensor_from::<Int32Type>(batches, input),
+        DType::Int64 => tensor_from::<Int64Type>(batches, input),
+    }?;
+    Ok(tensor.into())
+}
+
+fn tensor_from<T: ArrowPrimitiveType>(batches: &[RecordBatch], input: &Input) -> Result<Tensor>
+where
+    T::Native: Datum,
+{
+    let (values, shape) = tensors::input_values::<T>(batches, &input.columns, &input.shape)
+        .context(TensorMappingSnafu)?;
+    Tensor::from_shape(&shape, &values).context(TractSnafu)
+}
+
+fn tensor_values(tensor: &Tensor) -> Result<ArrayRef> {
+    let values: ArrayRef = match tensor.datum_type() {
+        DatumType::F32 => Arc::new(Float32Array::from(
+            tensor.as_slice::<f32>().context(TractSnafu)?.to_vec(),
+        )),
+        DatumType::F64 => Arc::new(Float64Array::from(
+            tensor.as_slice::<f64>().context(TractSnafu)?.to_vec(),
+        )),
+        DatumType::I32 => Arc::new(Int32Array::from(
+            tensor.as_slice::<i32>().context(TractSnafu)?.to_vec(),
+        )),
+        DatumType::I64 => Arc::new(Int64Array::from(
+            tensor.as_slice::<i64>().context(TractSnafu)?.to_vec(),
+        )),
+        datum_type => return UnsupportedDatumTypeSnafu { datum_type }.fail(),
+    };
+    Ok(values)
+}
+
+impl Model {
+    fn run_mapped(&self, mapping: &Mapping, input: &[RecordBatch]) -> Result<RecordBatch> {
+        if input.is_empty() {
+            return Ok(RecordBatch::new_empty(Arc::clone(&mapping.schema)));
+        }
+
+        let inputs = mapping
+            .inputs
+            .iter()
+            .map(|tensor| input_tensor(input, tensor))
+            .collect::<Result<TVec<_>>>()?;
+        let outputs = self.model.run(inputs).context(TractSnafu)?;
+
+        let mut columns = vec![];
+        for (index, output, width) in &mapping.outputs {
+            let values = tensor_values(&outputs[*index])?;
+            columns.extend(
+                tensors::output_columns(&output.columns, &values, *width)
+                    .context(TensorMappingSnafu)?,
+            );
+        }
+
+        RecordBatch::try_new(Arc::clone(&mapping.schema), columns).context(ArrowSnafu)
+    }
 }
 
 impl Runnable for Model {
     fn run(&self, input: Vec<RecordBatch>) -> std::result::Result<RecordBatch, super::Error> {
+        if let Some(mapping) = &self.mapping {
+            return self.run_mapped(mapping, &input).map_err(Into::into);
+        }
+
         {
             let this = &self;
             let reader: &[RecordBatch] = &input;
-            let return_schema = Schema::new(vec![Field::new("y", DataType::Float32, false)]);
+            let return_schema = this.output_schema();
 
             let Some(first_record) = reader.first() else {
-                return Ok(RecordBatch::new_empty(Arc::new(return_schema)));
+                return Ok(RecordBatch::new_empty(return_schema));
             };
 
             let schema = first_record.schema();
@@ -164,13 +422,28 @@ impl Runnable for Model {
                 .copied()
                 .collect_vec();
 
-            let record_batch = RecordBatch::try_new(
-                Arc::new(return_schema),
-                vec![Arc::new(Float32Array::from(result))],
-            )
-            .context(ArrowSnafu)?;
+            let record_batch =
+                RecordBatch::try_new(return_schema, vec![Arc::new(Float32Array::from(result))])
+                    .context(ArrowSnafu)?;
 
             Ok(record_batch)
         }
     }
+
+    fn is_row_wise(&self) -> bool {
+        self.mapping.as_ref().is_some_and(|mapping| {
+            mapping
+                .inputs
+                .iter()
+                .all(|input| input.shape.first() == Some(&-1) && !input.shape[1..].contains(&-1))
+        })
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        if let Some(mapping) = &self.mapping {
+            return Arc::clone(&mapping.schema);
+        }
+
+        Arc::new(Schema::new(vec![Field::new("y", DataType::Float32, false)]))
+    }
 }
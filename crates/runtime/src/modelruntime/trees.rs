@@ -0,0 +1,393 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! A pure-Rust runtime for gradient-boosted tree ensembles saved as XGBoost or LightGBM JSON.
+//!
+//! The trees are evaluated on the input's Arrow columns directly. The features of a model that names them are read
+//! from the columns with their names, and the features of other models from the columns in their positions. Null
+//! values are missing values.
+
+use super::{bool_param, ModelRuntime, Runnable};
+use arrow::array::{Array, ArrayRef, FixedSizeListArray, Float32Array, Float64Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use snafu::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub mod lightgbm;
+pub mod xgboost;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read the model file {path}: {source}"))]
+    UnableToReadModel {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Unable to parse the model: {source}"))]
+    UnableToParseModel { source: serde_json::Error },
+
+    #[snafu(display("The model is not supported: {reason}"))]
+    UnsupportedModel { reason: String },
+
+    #[snafu(display("The input has no column for feature \"{feature}\""))]
+    MissingFeature { feature: String },
+
+    #[snafu(display("{source}"))]
+    ArrowError { source: arrow::error::ArrowError },
+}
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The library a tree ensemble was saved by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeFormat {
+    Xgboost,
+    Lightgbm,
+}
+
+pub struct TreeEnsemble {
+    pub path: String,
+    pub format: TreeFormat,
+
+    /// The model's params. `raw_score: true` returns the summed tree outputs without the objective's transform,
+    /// i.e. margins rather than probabilities.
+    pub params: HashMap<String, String>,
+}
+
+impl ModelRuntime for TreeEnsemble {
+    fn load(&self) -> std::result::Result<Box<dyn Runnable>, super::Error> {
+        let json = std::fs::read_to_string(&self.path).context(UnableToReadModelSnafu {
+            path: self.path.clone(),
+        })?;
+        let mut ensemble = match self.format {
+            TreeFormat::Xgboost => xgboost::parse(&json)?,
+            TreeFormat::Lightgbm => lightgbm::parse(&json)?,
+        };
+        if bool_param(&self.params, "raw_score", false)? {
+            ensemble.transform = Transform::Identity;
+        }
+        Ok(Box::new(ensemble))
+    }
+}
+
+/// The magnitude below which LightGBM treats a value as zero.
+const ZERO_THRESHOLD: f64 = 1e-35;
+
+/// Which feature values take the default branch of a split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Missing {
+    /// Only `NaN`s.
+    NaN,
+
+    /// `NaN`s and zeros.
+    Zero,
+
+    /// None. `NaN`s are compared as zeros.
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    Leaf(f64),
+    Split {
+        feature: usize,
+        threshold: f64,
+
+        /// Whether values equal to the threshold go left, i.e. LightGBM's `<=`, rather than XGBoost's `<`.
+        inclusive: bool,
+        default_left: bool,
+        missing: Missing,
+        left: usize,
+        right: usize,
+    },
+}
+
+/// A tree stored as a list of nodes, starting from the root.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Tree {
+    pub(crate) nodes: Vec<Node>,
+}
+
+impl Tree {
+    /// Checks that following the children from the root never reaches a node twice, so predictions always end at a
+    /// leaf.
+    pub(crate) fn check_acyclic(&self) -> Result<()> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut pending = vec![0];
+        while let Some(index) = pending.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            ensure!(
+                !visited[index],
+                UnsupportedModelSnafu {
+                    reason: format!("a tree reaches node {index} more than once"),
+                }
+            );
+            visited[index] = true;
+            if let Node::Split { left, right, .. } = node {
+                pending.extend([*left, *right]);
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, row: &[f64]) -> f64 {
+        let mut index = 0;
+        loop {
+            match self.nodes.get(index) {
+                Some(Node::Leaf(value)) => return *value,
+                Some(Node::Split {
+                    feature,
+                    threshold,
+                    inclusive,
+                    default_left,
+                    missing,
+                    left,
+                    right,
+                }) => {
+                    let value = row.get(*feature).copied().unwrap_or(f64::NAN);
+                    let is_missing = match missing {
+                        Missing::NaN => value.is_nan(),
+                        Missing::Zero => value.is_nan() || value.abs() <= ZERO_THRESHOLD,
+                        Missing::None => false,
+                    };
+                    let go_left = if is_missing {
+                        *default_left
+                    } else {
+                        let value = if value.is_nan() { 0.0 } else { value };
+                        if *inclusive {
+                            value <= *threshold
+                        } else {
+                            value < *threshold
+                        }
+                    };
+                    index = if go_left { *left } else { *right };
+                }
+                // The parsers only build acyclic trees with valid child indices.
+                None => return 0.0,
+            }
+        }
+    }
+}
+
+/// How the summed tree outputs are turned into predictions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Transform {
+    Identity,
+
+    /// The logistic function of the output multiplied by a factor.
+    Sigmoid(f64),
+    Exp,
+
+    /// Class probabilities across the outputs.
+    Softmax,
+}
+
+impl Transform {
+    fn apply(self, margins: &mut [f64]) {
+        match self {
+            Transform::Identity => {}
+            Transform::Sigmoid(factor) => {
+                for margin in margins.iter_mut() {
+                    *margin = 1.0 / (1.0 + (-factor * *margin).exp());
+                }
+            }
+            Transform::Exp => {
+                for margin in margins.iter_mut() {
+                    *margin = margin.exp();
+                }
+            }
+            Transform::Softmax => {
+                let max = margins.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                let mut total = 0.0;
+                for margin in margins.iter_mut() {
+                    *margin = (*margin - max).exp();
+                    total += *margin;
+                }
+                for margin in margins.iter_mut() {
+                    *margin /= total;
+                }
+            }
+        }
+    }
+}
+
+/// A loaded tree ensemble.
+///
+/// Models with one output return a `y` column. Classifiers with several classes return the most likely class in `y`
+/// and the score of each class in `scores`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Ensemble {
+    pub(crate) feature_names: Vec<String>,
+    pub(crate) num_features: usize,
+
+    /// The trees with the output they add to.
+    pub(crate) trees: Vec<(Tree, usize)>,
+    pub(crate) num_outputs: usize,
+
+    /// The value each output starts from before the trees are added.
+    pub(crate) base_margin: f64,
+
+    /// Whether the outputs are the mean of the trees, i.e. a random forest, rather than their sum.
+    pub(crate) average: bool,
+    pub(crate) transform: Transform,
+}
+
+impl Ensemble {
+    fn predict(&self, row: &[f64], outputs: &mut [f64]) {
+        outputs.fill(self.base_margin);
+        for (tree, output) in &self.trees {
+            if let Some(value) = outputs.get_mut(*output) {
+                *value += tree.predict(row);
+            }
+        }
+        if self.average && !self.trees.is_empty() {
+            #[allow(clippy::cast_precision_loss)]
+            let trees_per_output = (self.trees.len() / self.num_outputs.max(1)).max(1) as f64;
+            for value in outputs.iter_mut() {
+                *value = (*value - self.base_margin) / trees_per_output + self.base_margin;
+            }
+        }
+        self.transform.apply(outputs);
+    }
+
+    /// The index of the column each feature is read from.
+    fn feature_columns(&self, schema: &Schema) -> Result<Vec<usize>> {
+        if self.feature_names.is_empty() {
+            ensure!(
+                schema.fields().len() >= self.num_features,
+                MissingFeatureSnafu {
+                    feature: format!("f{}", schema.fields().len()),
+                }
+            );
+            return Ok((0..self.num_features).collect());
+        }
+
+        self.feature_names
+            .iter()
+            .map(|name| {
+                schema
+                    .index_of(name)
+                    .ok()
+                    .context(MissingFeatureSnafu { feature: name })
+            })
+            .collect()
+    }
+
+    /// The predictions for each row of `batch`, one after the other.
+    fn predict_batch(&self, batch: &RecordBatch, predictions: &mut Vec<f64>) -> Result<()> {
+        let columns = self
+            .feature_columns(&batch.schema())?
+            .into_iter()
+            .map(|index| {
+                let column = cast(batch.column(index), &DataType::Float64).context(ArrowSnafu)?;
+                Ok(column
+                    .as_any()
+                    .downcast_ref::<Float64Array>()
+                    .cloned()
+                    .unwrap_or_else(|| Float64Array::from(vec![f64::NAN; batch.num_rows()])))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut row = vec![f64::NAN; self.num_features];
+        let mut outputs = vec![0.0; self.num_outputs];
+        for index in 0..batch.num_rows() {
+            for (value, column) in row.iter_mut().zip(&columns) {
+                *value = if column.is_null(index) {
+                    f64::NAN
+                } else {
+                    column.value(index)
+                };
+            }
+            self.predict(&row, &mut outputs);
+            predictions.extend_from_slice(&outputs);
+        }
+        Ok(())
+    }
+
+    fn output(&self, predictions: &[f64]) -> Result<RecordBatch> {
+        #[allow(clippy::cast_possible_truncation)]
+        let scores: Vec<f32> = predictions.iter().map(|&value| value as f32).collect();
+        if self.num_outputs == 1 {
+            return RecordBatch::try_new(
+                self.output_schema(),
+                vec![Arc::new(Float32Array::from(scores))],
+            )
+            .context(ArrowSnafu);
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let classes: Vec<f32> = scores
+            .chunks(self.num_outputs)
+            .map(|scores| {
+                scores
+                    .iter()
+                    .enumerate()
+                    .fold((0, f32::NEG_INFINITY), |best, (class, &score)| {
+                        if score > best.1 {
+                            (class, score)
+                        } else {
+                            best
+                        }
+                    })
+                    .0 as f32
+            })
+            .collect();
+
+        let scores = FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, false)),
+            i32::try_from(self.num_outputs).unwrap_or(i32::MAX),
+            Arc::new(Float32Array::from(scores)),
+            None,
+        )
+        .context(ArrowSnafu)?;
+        let columns: Vec<ArrayRef> = vec![Arc::new(Float32Array::from(classes)), Arc::new(scores)];
+        RecordBatch::try_new(self.output_schema(), columns).context(ArrowSnafu)
+    }
+}
+
+impl Runnable for Ensemble {
+    fn run(&self, input: Vec<RecordBatch>) -> std::result::Result<RecordBatch, super::Error> {
+        let mut predictions = Vec::new();
+        for batch in &input {
+            self.predict_batch(batch, &mut predictions)?;
+        }
+        Ok(self.output(&predictions)?)
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        let mut fields = vec![Field::new("y", DataType::Float32, false)];
+        if self.num_outputs > 1 {
+            fields.push(Field::new(
+                "scores",
+                DataType::FixedSizeList(
+                    Arc::new(Field::new("item", DataType::Float32, false)),
+                    i32::try_from(self.num_outputs).unwrap_or(i32::MAX),
+                ),
+                false,
+            ));
+        }
+        Arc::new(Schema::new(fields))
+    }
+
+    fn is_row_wise(&self) -> bool {
+        true
+    }
+}
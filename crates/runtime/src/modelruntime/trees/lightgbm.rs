@@ -0,0 +1,225 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Reads the JSON models returned by LightGBM's `dump_model`.
+
+use super::{
+    Ensemble, Missing, Node, Result, Transform, Tree, UnableToParseModelSnafu,
+    UnsupportedModelSnafu,
+};
+use serde::Deserialize;
+use snafu::prelude::*;
+
+#[derive(Deserialize)]
+struct Model {
+    num_tree_per_iteration: usize,
+    max_feature_idx: usize,
+    #[serde(default)]
+    feature_names: Vec<String>,
+    #[serde(default)]
+    objective: String,
+    #[serde(default)]
+    average_output: bool,
+    tree_info: Vec<TreeInfo>,
+}
+
+#[derive(Deserialize)]
+struct TreeInfo {
+    tree_structure: LgbNode,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LgbNode {
+    Split(Split),
+    Leaf { leaf_value: f64 },
+}
+
+#[derive(Deserialize)]
+struct Split {
+    split_feature: usize,
+
+    /// A number, or the `||`-separated categories of a categorical split.
+    threshold: serde_json::Value,
+    decision_type: String,
+    default_left: bool,
+    missing_type: String,
+    left_child: Box<LgbNode>,
+    right_child: Box<LgbNode>,
+}
+
+pub(crate) fn parse(json: &str) -> Result<Ensemble> {
+    let model: Model = serde_json::from_str(json).context(UnableToParseModelSnafu)?;
+    let num_outputs = model.num_tree_per_iteration.max(1);
+
+    let trees = model
+        .tree_info
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            let mut nodes = Vec::new();
+            add_node(&info.tree_structure, &mut nodes)?;
+            Ok((Tree { nodes }, index % num_outputs))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Models trained without feature names name their features `Column_<index>`, which the input isn't expected to
+    // have, so their features are read by position.
+    let unnamed = model
+        .feature_names
+        .iter()
+        .enumerate()
+        .all(|(index, name)| *name == format!("Column_{index}"));
+    let feature_names = if unnamed {
+        Vec::new()
+    } else {
+        model.feature_names
+    };
+
+    Ok(Ensemble {
+        feature_names,
+        num_features: model.max_feature_idx + 1,
+        trees,
+        num_outputs,
+        base_margin: 0.0,
+        average: model.average_output,
+        transform: transform(&model.objective),
+    })
+}
+
+/// The transform of an objective such as `binary sigmoid:1` or `multiclass num_class:3`.
+fn transform(objective: &str) -> Transform {
+    let mut parts = objective.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    let sigmoid = parts
+        .find_map(|part| part.strip_prefix("sigmoid:"))
+        .and_then(|factor| factor.parse().ok())
+        .unwrap_or(1.0);
+
+    match name {
+        "binary" | "multiclassova" | "multiclass_ova" | "ova" | "ovr" => {
+            Transform::Sigmoid(sigmoid)
+        }
+        "cross_entropy" | "xentropy" => Transform::Sigmoid(1.0),
+        "multiclass" | "softmax" => Transform::Softmax,
+        "poisson" | "gamma" | "tweedie" => Transform::Exp,
+        _ => Transform::Identity,
+    }
+}
+
+/// Adds `node` and its children to `nodes`, returning its index.
+fn add_node(node: &LgbNode, nodes: &mut Vec<Node>) -> Result<usize> {
+    let index = nodes.len();
+    let split = match node {
+        LgbNode::Leaf { leaf_value } => {
+            nodes.push(Node::Leaf(*leaf_value));
+            return Ok(index);
+        }
+        LgbNode::Split(split) => split,
+    };
+
+    let inclusive = match split.decision_type.as_str() {
+        "<=" => true,
+        "<" => false,
+        decision_type => {
+            return UnsupportedModelSnafu {
+                reason: format!("{decision_type} splits are not supported"),
+            }
+            .fail()
+        }
+    };
+    let Some(threshold) = split.threshold.as_f64() else {
+        return UnsupportedModelSnafu {
+            reason: "categorical splits are not supported",
+        }
+        .fail();
+    };
+    let missing = match split.missing_type.as_str() {
+        "NaN" => Missing::NaN,
+        "Zero" => Missing::Zero,
+        _ => Missing::None,
+    };
+
+    // The children are added after their parent, whose indices are filled in once they are known.
+    nodes.push(Node::Leaf(0.0));
+    let left = add_node(&split.left_child, nodes)?;
+    let right = add_node(&split.right_child, nodes)?;
+    nodes[index] = Node::Split {
+        feature: split.split_feature,
+        threshold,
+        inclusive,
+        default_left: split.default_left,
+        missing,
+        left,
+        right,
+    };
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flattens_trees() {
+        let ensemble = parse(
+            r#"{
+                "num_class": 1,
+                "num_tree_per_iteration": 1,
+                "max_feature_idx": 1,
+                "objective": "regression",
+                "feature_names": ["a", "b"],
+                "tree_info": [{
+                    "tree_index": 0,
+                    "tree_structure": {
+                        "split_feature": 1,
+                        "threshold": 2.5,
+                        "decision_type": "<=",
+                        "default_left": false,
+                        "missing_type": "Zero",
+                        "left_child": {"leaf_index": 0, "leaf_value": 1.5},
+                        "right_child": {"leaf_index": 1, "leaf_value": -0.5}
+                    }
+                }]
+            }"#,
+        )
+        .expect("model parses");
+
+        assert_eq!(ensemble.transform, Transform::Identity);
+        assert_eq!(
+            ensemble.trees[0].0.nodes,
+            vec![
+                Node::Split {
+                    feature: 1,
+                    threshold: 2.5,
+                    inclusive: true,
+                    default_left: false,
+                    missing: Missing::Zero,
+                    left: 1,
+                    right: 2,
+                },
+                Node::Leaf(1.5),
+                Node::Leaf(-0.5),
+            ]
+        );
+
+        let mut output = [0.0];
+        ensemble.predict(&[0.0, 2.5], &mut output);
+        assert!((output[0] - 1.5).abs() < f64::EPSILON);
+        ensemble.predict(&[0.0, 0.0], &mut output);
+        assert!((output[0] + 0.5).abs() < f64::EPSILON);
+    }
+}
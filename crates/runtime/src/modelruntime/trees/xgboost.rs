@@ -0,0 +1,361 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Reads the JSON models saved by XGBoost's `save_model`.
+
+use super::{
+    Ensemble, Missing, Node, Result, Transform, Tree, UnableToParseModelSnafu,
+    UnsupportedModelSnafu,
+};
+use serde::Deserialize;
+use snafu::prelude::*;
+
+#[derive(Deserialize)]
+struct Model {
+    learner: Learner,
+}
+
+#[derive(Deserialize)]
+struct Learner {
+    #[serde(default)]
+    feature_names: Vec<String>,
+    gradient_booster: GradientBooster,
+    learner_model_param: LearnerModelParam,
+    objective: Objective,
+}
+
+#[derive(Deserialize)]
+struct GradientBooster {
+    name: String,
+    model: Option<Gbtree>,
+}
+
+#[derive(Deserialize)]
+struct Gbtree {
+    trees: Vec<XgbTree>,
+    tree_info: Vec<usize>,
+}
+
+/// A tree stored as parallel arrays indexed by node. The `split_conditions` of a leaf are its value.
+#[derive(Deserialize)]
+struct XgbTree {
+    left_children: Vec<i64>,
+    right_children: Vec<i64>,
+    split_indices: Vec<usize>,
+    split_conditions: Vec<f64>,
+    default_left: Vec<Flag>,
+    #[serde(default)]
+    split_type: Vec<u8>,
+}
+
+/// Older versions save flags as numbers rather than booleans.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Flag {
+    Bool(bool),
+    Int(u8),
+}
+
+impl Flag {
+    fn is_set(&self) -> bool {
+        match self {
+            Flag::Bool(flag) => *flag,
+            Flag::Int(flag) => *flag != 0,
+        }
+    }
+}
+
+/// The params are saved as strings, i.e. `"base_score": "5E-1"`.
+#[derive(Deserialize)]
+struct LearnerModelParam {
+    base_score: String,
+    #[serde(default)]
+    num_class: String,
+    #[serde(default)]
+    num_feature: String,
+}
+
+#[derive(Deserialize)]
+struct Objective {
+    name: String,
+}
+
+pub(crate) fn parse(json: &str) -> Result<Ensemble> {
+    let model: Model = serde_json::from_str(json).context(UnableToParseModelSnafu)?;
+    let learner = model.learner;
+
+    let Some(gbtree) = learner
+        .gradient_booster
+        .model
+        .filter(|_| learner.gradient_booster.name == "gbtree")
+    else {
+        return UnsupportedModelSnafu {
+            reason: format!(
+                "the {} booster is not supported, only gbtree",
+                learner.gradient_booster.name
+            ),
+        }
+        .fail();
+    };
+
+    let num_outputs = parse_param(&learner.learner_model_param.num_class)
+        .max(1.0)
+        .round();
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let num_outputs = num_outputs as usize;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let num_features = (parse_param(&learner.learner_model_param.num_feature) as usize)
+        .max(learner.feature_names.len());
+
+    // Newer versions save a base score per target, i.e. `"[5E-1]"`.
+    let base_score = parse_param(
+        learner
+            .learner_model_param
+            .base_score
+            .trim_matches(|c| c == '[' || c == ']')
+            .split(',')
+            .next()
+            .unwrap_or_default(),
+    );
+
+    // The base score is saved as a prediction, so it is turned back into a margin by the inverse of the transform.
+    let (transform, base_margin) = match learner.objective.name.as_str() {
+        "binary:logistic" | "reg:logistic" => (Transform::Sigmoid(1.0), logit(base_score)),
+        "binary:logitraw" => (Transform::Identity, logit(base_score)),
+        "count:poisson" | "reg:gamma" | "reg:tweedie" | "survival:cox" => {
+            (Transform::Exp, base_score.ln())
+        }
+        "multi:softprob" | "multi:softmax" => (Transform::Softmax, base_score),
+        "reg:squarederror"
+        | "reg:linear"
+        | "reg:squaredlogerror"
+        | "reg:pseudohubererror"
+        | "reg:absoluteerror"
+        | "reg:quantileerror"
+        | "rank:pairwise"
+        | "rank:ndcg"
+        | "rank:map" => (Transform::Identity, base_score),
+        // Other objectives, i.e. `binary:hinge`, which predicts classes rather than scores, and `survival:aft`.
+        name => {
+            return UnsupportedModelSnafu {
+                reason: format!("the {name} objective is not supported"),
+            }
+            .fail()
+        }
+    };
+
+    if gbtree.trees.len() != gbtree.tree_info.len() {
+        return UnsupportedModelSnafu {
+            reason: "the tree_info doesn't list every tree",
+        }
+        .fail();
+    }
+
+    let trees = gbtree
+        .trees
+        .iter()
+        .zip(gbtree.tree_info)
+        .map(|(tree, output)| Ok((tree_from(tree)?, output)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Ensemble {
+        feature_names: learner.feature_names,
+        num_features,
+        trees,
+        num_outputs,
+        base_margin,
+        average: false,
+        transform,
+    })
+}
+
+fn parse_param(value: &str) -> f64 {
+    value.trim().parse().unwrap_or_default()
+}
+
+fn logit(probability: f64) -> f64 {
+    -(1.0 / probability - 1.0).ln()
+}
+
+fn tree_from(tree: &XgbTree) -> Result<Tree> {
+    let len = tree.left_children.len();
+    if [
+        tree.right_children.len(),
+        tree.split_indices.len(),
+        tree.split_conditions.len(),
+        tree.default_left.len(),
+    ]
+    .iter()
+    .any(|&other| other != len)
+    {
+        return UnsupportedModelSnafu {
+            reason: "a tree's node arrays have different lengths",
+        }
+        .fail();
+    }
+    if tree.split_type.iter().any(|&split_type| split_type != 0) {
+        return UnsupportedModelSnafu {
+            reason: "categorical splits are not supported",
+        }
+        .fail();
+    }
+
+    let child = |index: i64| {
+        usize::try_from(index)
+            .ok()
+            .filter(|&index| index < len)
+            .context(UnsupportedModelSnafu {
+                reason: format!("a tree has a child node {index} out of range"),
+            })
+    };
+
+    let nodes = (0..len)
+        .map(|node| {
+            if tree.left_children[node] == -1 {
+                return Ok(Node::Leaf(tree.split_conditions[node]));
+            }
+            Ok(Node::Split {
+                feature: tree.split_indices[node],
+                threshold: tree.split_conditions[node],
+                inclusive: false,
+                default_left: tree.default_left[node].is_set(),
+                missing: Missing::NaN,
+                left: child(tree.left_children[node])?,
+                right: child(tree.right_children[node])?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let tree = Tree { nodes };
+    tree.check_acyclic()?;
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modelruntime::Runnable;
+    use arrow::array::{Float32Array, Float64Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use std::sync::Arc;
+
+    const MODEL: &str = r#"{
+        "learner": {
+            "feature_names": ["a", "b"],
+            "gradient_booster": {
+                "name": "gbtree",
+                "model": {
+                    "trees": [{
+                        "left_children": [1, -1, 3, -1, -1],
+                        "right_children": [2, -1, 4, -1, -1],
+                        "split_indices": [0, 0, 1, 0, 0],
+                        "split_conditions": [0.5, -1.0, 2.0, 0.5, 1.0],
+                        "default_left": [0, 0, 1, 0, 0]
+                    }],
+                    "tree_info": [0]
+                }
+            },
+            "learner_model_param": {"base_score": "5E-1", "num_class": "0", "num_feature": "2"},
+            "objective": {"name": "binary:logistic"}
+        }
+    }"#;
+
+    #[test]
+    fn test_predicts_on_named_columns() {
+        let ensemble = parse(MODEL).expect("model parses");
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("b", DataType::Float64, true),
+            Field::new("a", DataType::Float64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Float64Array::from(vec![
+                    Some(0.0),
+                    Some(1.0),
+                    Some(3.0),
+                    None,
+                ])),
+                Arc::new(Float64Array::from(vec![0.0, 1.0, 1.0, 1.0])),
+            ],
+        )
+        .expect("batch is valid");
+
+        let output = ensemble.run(vec![batch]).expect("model runs");
+        let y = output
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .expect("y is Float32");
+
+        #[allow(clippy::cast_possible_truncation)]
+        let expected: Vec<f32> = [-1.0_f64, 0.5, 1.0, 0.5]
+            .iter()
+            .map(|margin| (1.0 / (1.0 + (-margin).exp())) as f32)
+            .collect();
+        for (actual, expected) in y.values().iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_requires_named_columns() {
+        let ensemble = parse(MODEL).expect("model parses");
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("a", DataType::Float64, true),
+                Field::new("c", DataType::Float64, true),
+            ])),
+            vec![
+                Arc::new(Float64Array::from(vec![0.0])),
+                Arc::new(Float64Array::from(vec![0.0])),
+            ],
+        )
+        .expect("batch is valid");
+
+        let error = ensemble.run(vec![batch]).expect_err("feature b is missing");
+        assert_eq!(
+            error.to_string(),
+            r#"The input has no column for feature "b""#
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsupported_objectives() {
+        for objective in ["binary:hinge", "survival:aft", "custom"] {
+            let model = MODEL.replace("binary:logistic", objective);
+            assert!(
+                matches!(
+                    parse(&model),
+                    Err(crate::modelruntime::trees::Error::UnsupportedModel { .. })
+                ),
+                "{objective}"
+            );
+        }
+        assert!(parse(&MODEL.replace("binary:logistic", "reg:squarederror")).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_cyclic_trees() {
+        // The right child of the root leads back to the root.
+        let model = MODEL.replace("[1, -1, 3, -1, -1]", "[1, -1, 0, -1, -1]");
+        assert!(matches!(
+            parse(&model),
+            Err(crate::modelruntime::trees::Error::UnsupportedModel { .. })
+        ));
+    }
+}
@@ -20,8 +20,11 @@ use snafu::prelude::*;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+pub mod artifact;
+pub mod https;
 pub mod huggingface;
 pub mod local;
+pub mod s3;
 pub mod spiceai;
 
 #[derive(Debug, Snafu)]
@@ -50,12 +53,42 @@ pub enum Error {
 
     #[snafu(display("Unknown data source: {model_source}"))]
     UnknownModelSource { model_source: String },
+
+    #[snafu(display("Invalid model URL {url}: {source}"))]
+    InvalidModelUrl {
+        url: String,
+        source: url::ParseError,
+    },
+
+    #[snafu(display("Unable to download the model from {url}: {status}"))]
+    UnableToDownloadModel { url: String, status: String },
+
+    #[snafu(display("Unable to get the model object: {source}"))]
+    UnableToGetObject { source: object_store::Error },
+
+    #[snafu(display("Unable to write the model file: {source}"))]
+    UnableToWriteModelFile { source: std::io::Error },
+
+    #[snafu(display("Refusing to send the model source token to {url}, which doesn't use https"))]
+    InsecureTokenUrl { url: String },
+
+    #[snafu(display(
+        "The model downloaded from {url} has checksum {actual}, which doesn't match {expected}"
+    ))]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 /// A `ModelSource` pulls a model from a source into a local directory
 ///
+/// The `secret` holds the credentials for the source, if the model has any. Sources that need none, or can pull
+/// public models without them, must not require it.
+///
 /// Implementing `pull` is required, which will fetch the model from the source (either local or
 /// remote) and store it in the local directory. The local directory is returned for further
 /// processing by `ModelRuntime`.
@@ -63,7 +96,7 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 pub trait ModelSource {
     async fn pull(
         &self,
-        secret: Secret,
+        secret: Option<Secret>,
         params: Arc<Option<HashMap<String, String>>>,
     ) -> Result<String>;
 }
@@ -91,6 +124,8 @@ pub fn create_source_from(source: &str) -> Result<Box<dyn ModelSource>> {
         "localhost" => Ok(Box::new(local::Local {})),
         "spiceai" => Ok(Box::new(spiceai::SpiceAI {})),
         "huggingface" => Ok(Box::new(huggingface::Huggingface {})),
+        "s3" => Ok(Box::new(s3::S3 {})),
+        "https" => Ok(Box::new(https::Https {})),
         _ => UnknownModelSourceSnafu {
             model_source: source,
         }
@@ -0,0 +1,275 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Caches model artifacts downloaded from URLs under the model path, so they are only downloaded again when they
+//! change.
+//!
+//! An artifact URL can pin the artifact's SHA-256 checksum in its fragment, i.e.
+//! `https://example.com/models/churn.onnx#sha256=<hex>`. A cached artifact with the pinned checksum is used without
+//! asking the server, and a downloaded artifact with a different checksum is rejected. Without a checksum, the ETag
+//! the artifact was downloaded with decides whether the cached artifact is current.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use snafu::prelude::*;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+/// An artifact URL, without its checksum fragment.
+pub struct ArtifactUrl {
+    pub url: Url,
+    pub sha256: Option<String>,
+}
+
+impl ArtifactUrl {
+    pub fn parse(from: &str) -> super::Result<Self> {
+        let mut url = Url::parse(from).context(super::InvalidModelUrlSnafu { url: from })?;
+        let sha256 = url
+            .fragment()
+            .and_then(|fragment| fragment.strip_prefix("sha256="))
+            .map(str::to_ascii_lowercase);
+        url.set_fragment(None);
+
+        Ok(Self { url, sha256 })
+    }
+
+    /// The name of the artifact file, which is the last segment of its path.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        self.url
+            .path_segments()
+            .and_then(Iterator::last)
+            .filter(|segment| !segment.is_empty())
+            .unwrap_or("model.onnx")
+            .to_string()
+    }
+}
+
+/// Where an artifact is cached: a directory for its URL under the model path, as models with several versions cache
+/// artifacts with the same file name.
+pub async fn cache_path(model_name: &str, artifact: &ArtifactUrl) -> super::Result<PathBuf> {
+    let url_hash = hex(&Sha256::digest(artifact.url.as_str().as_bytes()));
+    let mut path = PathBuf::from(super::ensure_model_path(model_name)?);
+    path.push(&url_hash[..16]);
+    fs::create_dir_all(&path)
+        .await
+        .context(super::UnableToCreateModelPathSnafu)?;
+    path.push(artifact.file_name());
+    Ok(path)
+}
+
+/// Whether the artifact cached at `path` can be used without asking the server, which is when it has the checksum
+/// the URL pins.
+pub async fn is_pinned_and_cached(path: &Path, artifact: &ArtifactUrl) -> bool {
+    let Some(expected) = &artifact.sha256 else {
+        return false;
+    };
+    match file_sha256(path).await {
+        Ok(actual) => &actual == expected,
+        Err(_) => false,
+    }
+}
+
+/// The ETag the artifact cached at `path` was downloaded with.
+pub async fn cached_etag(path: &Path) -> Option<String> {
+    if !fs::try_exists(path).await.unwrap_or(false) {
+        return None;
+    }
+    fs::read_to_string(etag_path(path)).await.ok()
+}
+
+fn etag_path(path: &Path) -> PathBuf {
+    let mut etag_path = path.as_os_str().to_owned();
+    etag_path.push(".etag");
+    PathBuf::from(etag_path)
+}
+
+/// The SHA-256 checksum of the file at `path`, as lowercase hex.
+pub async fn file_sha256(path: &Path) -> io::Result<String> {
+    let (hasher, _) = hash_file(path).await?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Hashes the file at `path` on the blocking pool, as model files can be gigabytes. Returns the hasher with the
+/// number of bytes hashed.
+async fn hash_file(path: &Path) -> io::Result<(Sha256, u64)> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        let hashed = io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok((hasher, hashed))
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Writes a downloaded artifact to a partial file, which replaces the cached artifact once it is verified.
+pub struct ArtifactWriter {
+    path: PathBuf,
+    partial_path: PathBuf,
+    file: File,
+    hasher: Sha256,
+}
+
+impl ArtifactWriter {
+    /// Starts writing the artifact cached at `path` from the beginning.
+    pub async fn create(path: &Path) -> super::Result<Self> {
+        let partial_path = partial_path(path);
+        let file = File::create(&partial_path)
+            .await
+            .context(super::UnableToWriteModelFileSnafu)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            partial_path,
+            file,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// Continues writing the artifact cached at `path` after what an interrupted download of the same `etag` already
+    /// wrote. The partial file of a download of another ETag is discarded. Returns the writer with the number of bytes
+    /// already written.
+    pub async fn resume(path: &Path, etag: Option<&str>) -> super::Result<(Self, u64)> {
+        let partial_path = partial_path(path);
+        let partial_etag_path = etag_path(&partial_path);
+        let partial_etag = fs::read_to_string(&partial_etag_path).await.ok();
+        if partial_etag.as_deref() != etag {
+            let _ = fs::remove_file(&partial_path).await;
+            match etag {
+                Some(etag) => fs::write(&partial_etag_path, etag)
+                    .await
+                    .context(super::UnableToWriteModelFileSnafu)?,
+                None => {
+                    let _ = fs::remove_file(&partial_etag_path).await;
+                }
+            }
+        }
+
+        let (hasher, written) = match hash_file(&partial_path).await {
+            Ok(hashed) => hashed,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Sha256::new(), 0),
+            Err(e) => return Err(super::Error::UnableToWriteModelFile { source: e }),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial_path)
+            .await
+            .context(super::UnableToWriteModelFileSnafu)?;
+
+        Ok((
+            Self {
+                path: path.to_path_buf(),
+                partial_path,
+                file,
+                hasher,
+            },
+            written,
+        ))
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> super::Result<()> {
+        self.hasher.update(chunk);
+        self.file
+            .write_all(chunk)
+            .await
+            .context(super::UnableToWriteModelFileSnafu)
+    }
+
+    /// Verifies the artifact downloaded from `url` against the `sha256` checksum, if there is one, and replaces the
+    /// cached artifact with it.
+    pub async fn finish(
+        self,
+        url: &str,
+        sha256: Option<&str>,
+        etag: Option<&str>,
+    ) -> super::Result<()> {
+        let Self {
+            path,
+            partial_path,
+            mut file,
+            hasher,
+        } = self;
+        file.flush()
+            .await
+            .context(super::UnableToWriteModelFileSnafu)?;
+        drop(file);
+
+        let actual = hex(&hasher.finalize());
+        if let Some(expected) = sha256 {
+            if !actual.eq_ignore_ascii_case(expected) {
+                let _ = fs::remove_file(&partial_path).await;
+                return super::ChecksumMismatchSnafu {
+                    url,
+                    expected,
+                    actual,
+                }
+                .fail();
+            }
+        }
+
+        fs::rename(&partial_path, &path)
+            .await
+            .context(super::UnableToWriteModelFileSnafu)?;
+        let _ = fs::remove_file(etag_path(&partial_path)).await;
+        let etag_path = etag_path(&path);
+        match etag {
+            Some(etag) => fs::write(etag_path, etag)
+                .await
+                .context(super::UnableToWriteModelFileSnafu)?,
+            None => {
+                let _ = fs::remove_file(etag_path).await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".partial");
+    PathBuf::from(partial_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_url() {
+        let artifact =
+            ArtifactUrl::parse("https://example.com/models/churn.onnx#sha256=ABC123").expect("url");
+        assert_eq!(
+            artifact.url.as_str(),
+            "https://example.com/models/churn.onnx"
+        );
+        assert_eq!(artifact.sha256.as_deref(), Some("abc123"));
+        assert_eq!(artifact.file_name(), "churn.onnx");
+
+        let artifact = ArtifactUrl::parse("s3://bucket/churn.onnx").expect("url");
+        assert_eq!(artifact.sha256, None);
+        assert_eq!(artifact.file_name(), "churn.onnx");
+    }
+}
@@ -14,23 +14,50 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use super::artifact::{self, ArtifactWriter};
 use super::Error;
 use super::ModelSource;
 use async_trait::async_trait;
 use regex::Regex;
+use reqwest::{header, Client, RequestBuilder, StatusCode};
 use secrets::Secret;
+use serde::Deserialize;
 use snafu::prelude::*;
 use std::collections::HashMap;
-use std::io::Cursor;
+use std::path::{Component, Path};
 use std::sync::Arc;
 
+/// The Hugging Face Hub, unless the `endpoint` param or the `HF_ENDPOINT` environment variable points to a mirror.
+const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
 pub struct Huggingface {}
 
+/// A file in a Hugging Face repo, as listed by the Hub's tree API.
+#[derive(Debug, Deserialize)]
+struct RepoFile {
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+    #[serde(default)]
+    size: u64,
+    /// The git object id of the file, which changes whenever the file does.
+    #[serde(default)]
+    oid: String,
+    #[serde(default)]
+    lfs: Option<LfsPointer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LfsPointer {
+    /// The SHA-256 checksum of the file.
+    oid: String,
+}
+
 #[async_trait]
 impl ModelSource for Huggingface {
     async fn pull(
         &self,
-        secret: Secret,
+        secret: Option<Secret>,
         params: Arc<Option<HashMap<String, String>>>,
     ) -> super::Result<String> {
         let name = params
@@ -52,12 +79,15 @@ impl ModelSource for Huggingface {
             .and_then(|p| p.get("files"))
             .map(ToString::to_string);
 
-        let files = match files_param {
-            Some(files) => files.split(',').map(ToString::to_string).collect(),
+        let files: Vec<String> = match files_param {
+            Some(files) => files
+                .split(',')
+                .filter(|file| !file.is_empty())
+                .map(ToString::to_string)
+                .collect(),
             None => vec![],
         };
 
-        // it is not copying local model into .spice folder
         let local_path = super::ensure_model_path(name.as_str())?;
 
         let remote_path = params
@@ -88,65 +118,537 @@ impl ModelSource for Huggingface {
             .build());
         };
 
-        let revision = match caps["revision"].to_owned() {
-            s if s.is_empty() => "main".to_string(),
-            s if s == "latest" => "main".to_string(),
-            _ => caps["revision"].to_string(),
+        let revision = match caps.name("revision").map(|revision| revision.as_str()) {
+            None | Some("" | "latest") => "main".to_string(),
+            Some(revision) => revision.to_string(),
         };
+        ensure!(
+            is_relative_path(&revision),
+            super::UnableToLoadConfigSnafu {
+                reason: format!("revision is invalid for huggingface source: {revision}"),
+            }
+        );
 
-        let versioned_path = format!("{local_path}/{revision}");
+        let endpoint = params
+            .as_ref()
+            .as_ref()
+            .and_then(|p| p.get("endpoint"))
+            .cloned()
+            .or_else(|| std::env::var("HF_ENDPOINT").ok())
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+        let endpoint = endpoint.trim_end_matches('/');
+        let repo = format!("{}/{}", &caps["org"], &caps["model"]);
+        let client = Repo {
+            client: Client::new(),
+            token: secret
+                .as_ref()
+                .and_then(|secret| secret.get("token"))
+                .filter(|token| !token.is_empty())
+                .map(ToString::to_string),
+        };
 
-        let mut onnx_file_name = String::new();
+        // The file list has the checksums the downloads are verified with. A repo can only be downloaded without it
+        // when its files are listed.
+        let repo_files = match client.list_files(endpoint, &repo, &revision).await {
+            Ok(repo_files) => repo_files,
+            Err(e) if !files.is_empty() => {
+                tracing::warn!(
+                    "Unable to list the files of {repo}, downloading them without verifying them: {e}"
+                );
+                vec![]
+            }
+            Err(e) => return Err(e),
+        };
+        let files: Vec<String> = if files.is_empty() {
+            repo_files.iter().map(|file| file.path.clone()).collect()
+        } else {
+            files
+        };
+        // The files are downloaded under the model path, so a path that could escape it is refused.
+        if let Some(file) = files.iter().find(|file| !is_relative_path(file)) {
+            return Err(super::UnableToLoadConfigSnafu {
+                reason: format!("file path is invalid for huggingface source: {file}"),
+            }
+            .build());
+        }
 
-        std::fs::create_dir_all(versioned_path.clone())
-            .context(super::UnableToCreateModelPathSnafu {})?;
+        let versioned_path = format!("{local_path}/{revision}");
 
-        let p = versioned_path.clone();
+        // Tree ensembles are saved as JSON.
+        let extension = match params.as_ref().as_ref().and_then(|p| p.get("format")) {
+            Some(format) if format == "xgboost" || format == "lightgbm" => ".json",
+            _ => ".onnx",
+        };
+        let Some(model_file) = model_file(&files, extension) else {
+            return Err(super::UnableToLoadConfigSnafu {
+                reason: format!("no {extension} model file found in {repo} at {revision}"),
+            }
+            .build());
+        };
+        let model_file_name = format!("{versioned_path}/{model_file}");
 
         for file in files {
-            let file_name = format!("{p}/{file}");
+            let file_name = format!("{versioned_path}/{file}");
+            let file_path = Path::new(&file_name);
+            if let Some(parent) = file_path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .context(super::UnableToCreateModelPathSnafu {})?;
+            }
 
-            if std::fs::metadata(file_name.clone()).is_ok() {
+            let repo_file = repo_files.iter().find(|repo_file| repo_file.path == file);
+            if is_downloaded(file_path, repo_file).await {
                 tracing::info!("File already exists: {}, skipping download", file_name);
-
                 continue;
             }
 
-            let download_url = format!(
-                "https://huggingface.co/{}/{}/resolve/{}/{}",
-                caps["org"].to_owned(),
-                caps["model"].to_owned(),
-                revision,
-                file,
-            );
-
+            let download_url = format!("{endpoint}/{repo}/resolve/{revision}/{file}");
             tracing::info!("Downloading model: {}", download_url);
+            let sha256 = repo_file
+                .and_then(|repo_file| repo_file.lfs.as_ref())
+                .map(|lfs| lfs.oid.as_str());
+            let oid = repo_file
+                .map(|repo_file| repo_file.oid.as_str())
+                .filter(|oid| !oid.is_empty());
+            client
+                .download(&download_url, file_path, sha256, oid)
+                .await?;
 
-            if file.to_lowercase().ends_with(".onnx") {
-                onnx_file_name = file_name.clone();
+            tracing::info!("Downloaded: {}", file_name);
+        }
+
+        Ok(model_file_name)
+    }
+}
+
+/// The model file among `files`, which is the first file with the `extension` of the model format.
+fn model_file<'a>(files: &'a [String], extension: &str) -> Option<&'a String> {
+    files
+        .iter()
+        .find(|file| file.to_lowercase().ends_with(extension))
+}
+
+/// Whether `path` is a non-empty relative path that stays within the directory it is joined to.
+fn is_relative_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Whether the file at `path` is a current download of `repo_file`: it has the checksum of an LFS file, or the size
+/// and git object id of another file. Without the file list, an existing file is assumed to be current.
+async fn is_downloaded(path: &Path, repo_file: Option<&RepoFile>) -> bool {
+    let Ok(metadata) = tokio::fs::metadata(path).await else {
+        return false;
+    };
+    match repo_file {
+        Some(RepoFile { lfs: Some(lfs), .. }) => artifact::file_sha256(path)
+            .await
+            .is_ok_and(|sha256| sha256.eq_ignore_ascii_case(&lfs.oid)),
+        Some(repo_file) => {
+            metadata.len() == repo_file.size
+                && artifact::cached_etag(path).await.as_deref() == Some(repo_file.oid.as_str())
+        }
+        None => true,
+    }
+}
+
+/// Requests to a Hugging Face repo, authorized with the token for gated and private repos.
+struct Repo {
+    client: Client,
+    token: Option<String>,
+}
+
+impl Repo {
+    /// A request for `url`, which must use https if the request carries the token. This covers the endpoint and the
+    /// pages of a file listing, which the server links to.
+    fn get(&self, url: &str) -> super::Result<RequestBuilder> {
+        let request = self.client.get(url);
+        match &self.token {
+            Some(token) => {
+                ensure!(
+                    url.starts_with("https://"),
+                    super::InsecureTokenUrlSnafu { url }
+                );
+                Ok(request.bearer_auth(token))
             }
+            None => Ok(request),
+        }
+    }
 
-            let client = reqwest::Client::new();
-            let response = client
-                .get(download_url)
-                .bearer_auth(secret.get("token").unwrap_or_default())
+    /// Lists the files in the repo at `revision`, following the pages of the listing.
+    async fn list_files(
+        &self,
+        endpoint: &str,
+        repo: &str,
+        revision: &str,
+    ) -> super::Result<Vec<RepoFile>> {
+        let mut files = Vec::new();
+        let mut url = Some(format!(
+            "{endpoint}/api/models/{repo}/tree/{revision}?recursive=true"
+        ));
+        while let Some(page_url) = url.take() {
+            let response = self
+                .get(&page_url)?
                 .send()
                 .await
                 .context(super::UnableToFetchModelSnafu {})?;
+            ensure!(
+                response.status().is_success(),
+                super::UnableToDownloadModelSnafu {
+                    url: page_url,
+                    status: response.status().to_string(),
+                }
+            );
 
-            if !response.status().is_success() {
-                return Err(Error::UnableToDownloadModelFile {});
+            url = response
+                .headers()
+                .get(header::LINK)
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_page);
+            let page: Vec<RepoFile> = response
+                .json()
+                .await
+                .map_err(|_| Error::UnableToParseMetadata {})?;
+            files.extend(page.into_iter().filter(|file| file.kind == "file"));
+        }
+
+        Ok(files)
+    }
+
+    /// Downloads `url` to `path`, continuing an interrupted download where it stopped. The git object id `oid` is
+    /// kept with the file in place of an ETag, so a changed file is downloaded again.
+    async fn download(
+        &self,
+        url: &str,
+        path: &Path,
+        sha256: Option<&str>,
+        oid: Option<&str>,
+    ) -> super::Result<()> {
+        let (mut writer, written) = ArtifactWriter::resume(path, oid).await?;
+        let mut request = self.get(url)?;
+        if written > 0 {
+            tracing::info!("Resuming the download of {url} after {written} bytes");
+            request = request.header(header::RANGE, format!("bytes={written}-"));
+        }
+
+        let mut response = request
+            .send()
+            .await
+            .context(super::UnableToFetchModelSnafu {})?;
+        if written > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            // The server sent the whole file, or can't resume from the partial file, so the download starts over.
+            writer = ArtifactWriter::create(path).await?;
+            if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                response = self
+                    .get(url)?
+                    .send()
+                    .await
+                    .context(super::UnableToFetchModelSnafu {})?;
+            }
+        }
+        ensure!(
+            response.status().is_success(),
+            super::UnableToDownloadModelSnafu {
+                url,
+                status: response.status().to_string(),
             }
+        );
 
-            let mut file = std::fs::File::create(file_name.clone())
-                .context(super::UnableToCreateModelPathSnafu {})?;
-            let mut content = Cursor::new(response.bytes().await.unwrap_or_default());
-            std::io::copy(&mut content, &mut file)
-                .context(super::UnableToCreateModelPathSnafu {})?;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .context(super::UnableToFetchModelSnafu {})?
+        {
+            writer.write(&chunk).await?;
+        }
+        writer.finish(url, sha256, oid).await
+    }
+}
 
-            tracing::info!("Downloaded: {}", file_name);
+/// The URL of the next page in a `Link` header, i.e. `<https://huggingface.co/api/...>; rel="next"`.
+fn next_page(link: &str) -> Option<String> {
+    link.split(',').find_map(|link| {
+        let (url, rel) = link.split_once(';')?;
+        (rel.trim() == r#"rel="next""#).then(|| {
+            url.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use axum::{
+        extract::{RawQuery, State},
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+        response::IntoResponse,
+        routing::get,
+        Json, Router,
+    };
+    use sha2::{Digest, Sha256};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const CONTENT: &[u8] = b"weights of a model served by the test server";
+
+    /// The `Range` headers the test server was sent.
+    type Ranges = Arc<Mutex<Vec<String>>>;
+
+    /// Serves a two page listing of `org/model` and [`CONTENT`] at `/file`, honoring `Range` requests. Returns the
+    /// server's endpoint.
+    async fn serve(ranges: Ranges) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test server");
+        let endpoint = format!("http://{}", listener.local_addr().expect("local address"));
+
+        let next_page = format!(
+            r#"<{endpoint}/api/models/org/model/tree/main?recursive=true&cursor=2>; rel="next""#
+        );
+        let list = move |RawQuery(query): RawQuery| {
+            let next_page = next_page.clone();
+            async move {
+                if query.is_some_and(|query| query.contains("cursor=2")) {
+                    return Json(serde_json::json!([
+                        {"type": "directory", "path": "onnx", "oid": "1"},
+                        {"type": "file", "path": "onnx/config.json", "size": 2, "oid": "2"},
+                    ]))
+                    .into_response();
+                }
+                let mut response = Json(serde_json::json!([{
+                    "type": "file",
+                    "path": "model.onnx",
+                    "size": CONTENT.len(),
+                    "oid": "3",
+                    "lfs": {"oid": hex_sha256(CONTENT)},
+                }]))
+                .into_response();
+                response.headers_mut().insert(
+                    header::LINK,
+                    HeaderValue::from_str(&next_page).expect("valid header"),
+                );
+                response
+            }
+        };
+        let file = |State(ranges): State<Ranges>, headers: HeaderMap| async move {
+            let Some(range) = headers
+                .get(header::RANGE)
+                .and_then(|range| range.to_str().ok())
+            else {
+                return CONTENT.into_response();
+            };
+            ranges.lock().expect("ranges").push(range.to_string());
+            let start: usize = range
+                .trim_start_matches("bytes=")
+                .trim_end_matches('-')
+                .parse()
+                .expect("range start");
+            (StatusCode::PARTIAL_CONTENT, &CONTENT[start..]).into_response()
+        };
+
+        let app = Router::new()
+            .route("/api/models/org/model/tree/main", get(list))
+            .route("/file", get(file))
+            .with_state(ranges);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        endpoint
+    }
+
+    fn hex_sha256(bytes: &[u8]) -> String {
+        Sha256::digest(bytes)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn temp_path(file: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spice-hf-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("temp dir");
+        dir.join(file)
+    }
+
+    fn repo() -> Repo {
+        Repo {
+            client: Client::new(),
+            token: None,
         }
+    }
+
+    #[tokio::test]
+    async fn test_list_files_follows_pages() {
+        let endpoint = serve(Ranges::default()).await;
+
+        let files = repo()
+            .list_files(&endpoint, "org/model", "main")
+            .await
+            .expect("file list");
+
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["model.onnx", "onnx/config.json"]);
+        assert_eq!(
+            files[0].lfs.as_ref().map(|lfs| lfs.oid.clone()),
+            Some(hex_sha256(CONTENT))
+        );
+        assert_eq!(files[1].oid, "2");
+    }
+
+    #[tokio::test]
+    async fn test_download_verifies_sha256() {
+        let endpoint = serve(Ranges::default()).await;
+        let url = format!("{endpoint}/file");
+        let path = temp_path("model.onnx");
+
+        let result = repo()
+            .download(
+                &url,
+                &path,
+                Some(hex_sha256(b"other weights").as_str()),
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+        assert!(!path.exists());
+
+        repo()
+            .download(&url, &path, Some(hex_sha256(CONTENT).as_str()), Some("3"))
+            .await
+            .expect("download");
+        assert_eq!(std::fs::read(&path).expect("downloaded file"), CONTENT);
+        assert_eq!(artifact::cached_etag(&path).await.as_deref(), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_partial_file() {
+        let ranges = Ranges::default();
+        let endpoint = serve(Arc::clone(&ranges)).await;
+        let path = temp_path("model.onnx");
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".partial");
+        std::fs::write(&partial_path, &CONTENT[..10]).expect("partial file");
+
+        repo()
+            .download(
+                &format!("{endpoint}/file"),
+                &path,
+                Some(hex_sha256(CONTENT).as_str()),
+                None,
+            )
+            .await
+            .expect("download");
+
+        assert_eq!(std::fs::read(&path).expect("downloaded file"), CONTENT);
+        assert_eq!(*ranges.lock().expect("ranges"), vec!["bytes=10-"]);
+    }
+
+    #[tokio::test]
+    async fn test_is_downloaded_refreshes_changed_files() {
+        let path = temp_path("config.json");
+        std::fs::write(&path, "{}").expect("file");
+        let mut etag_path = path.as_os_str().to_owned();
+        etag_path.push(".etag");
+        std::fs::write(&etag_path, "2").expect("etag file");
+        let mut repo_file = RepoFile {
+            kind: "file".to_string(),
+            path: "config.json".to_string(),
+            size: 2,
+            oid: "2".to_string(),
+            lfs: None,
+        };
+
+        assert!(is_downloaded(&path, Some(&repo_file)).await);
+        repo_file.oid = "4".to_string();
+        assert!(!is_downloaded(&path, Some(&repo_file)).await);
+    }
+
+    #[tokio::test]
+    async fn test_download_discards_stale_partial_file() {
+        let ranges = Ranges::default();
+        let endpoint = serve(Arc::clone(&ranges)).await;
+        let path = temp_path("model.onnx");
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".partial");
+        std::fs::write(&partial_path, b"an older version of the weights").expect("partial file");
+        let mut partial_etag_path = partial_path.clone();
+        partial_etag_path.push(".etag");
+        std::fs::write(&partial_etag_path, "2").expect("partial etag file");
+
+        repo()
+            .download(
+                &format!("{endpoint}/file"),
+                &path,
+                Some(hex_sha256(CONTENT).as_str()),
+                Some("3"),
+            )
+            .await
+            .expect("download");
+
+        assert_eq!(std::fs::read(&path).expect("downloaded file"), CONTENT);
+        assert!(ranges.lock().expect("ranges").is_empty());
+        assert!(!PathBuf::from(partial_etag_path).exists());
+    }
+
+    #[test]
+    fn test_token_requires_https() {
+        let repo = Repo {
+            client: Client::new(),
+            token: Some("hf_token".to_string()),
+        };
+        assert!(repo.get("https://huggingface.co/api/models").is_ok());
+        assert!(matches!(
+            repo.get("http://mirror.example.com/api/models"),
+            Err(Error::InsecureTokenUrl { .. })
+        ));
+        assert!(self::repo().get("http://127.0.0.1/api/models").is_ok());
+    }
+
+    #[test]
+    fn test_model_file() {
+        let files = vec![
+            "config.json".to_string(),
+            "onnx/Model.ONNX".to_string(),
+            "model.onnx".to_string(),
+        ];
+        assert_eq!(
+            model_file(&files, ".onnx").map(String::as_str),
+            Some("onnx/Model.ONNX")
+        );
+        assert_eq!(
+            model_file(&files, ".json").map(String::as_str),
+            Some("config.json")
+        );
+        assert_eq!(model_file(&files[..1], ".onnx"), None);
+    }
+
+    #[test]
+    fn test_is_relative_path() {
+        assert!(is_relative_path("onnx/model.onnx"));
+        assert!(is_relative_path("main"));
+        assert!(!is_relative_path(""));
+        assert!(!is_relative_path(".."));
+        assert!(!is_relative_path("onnx/../../model.onnx"));
+        assert!(!is_relative_path("/etc/passwd"));
+        assert!(!is_relative_path("./model.onnx"));
+    }
 
-        Ok(onnx_file_name)
+    #[test]
+    fn test_next_page() {
+        assert_eq!(
+            next_page(
+                r#"<https://huggingface.co/api/models/org/model/tree/main?cursor=abc>; rel="next""#
+            ),
+            Some("https://huggingface.co/api/models/org/model/tree/main?cursor=abc".to_string())
+        );
+        assert_eq!(next_page(r#"<https://huggingface.co/a>; rel="prev""#), None);
     }
 }
@@ -27,7 +27,7 @@ pub struct Local {}
 impl ModelSource for Local {
     async fn pull(
         &self,
-        _: Secret,
+        _: Option<Secret>,
         params: Arc<Option<HashMap<String, String>>>,
     ) -> super::Result<String> {
         let name = params
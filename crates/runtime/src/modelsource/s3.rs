@@ -0,0 +1,122 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::artifact::{self, ArtifactUrl, ArtifactWriter};
+use super::ModelSource;
+use async_trait::async_trait;
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use secrets::Secret;
+use snafu::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Downloads a model artifact from an `s3://bucket/key` URL, with the same `key` and `secret` credentials as the S3
+/// data connector. Without credentials, the request is unsigned.
+pub struct S3 {}
+
+#[async_trait]
+impl ModelSource for S3 {
+    async fn pull(
+        &self,
+        secret: Option<Secret>,
+        params: Arc<Option<HashMap<String, String>>>,
+    ) -> super::Result<String> {
+        let params = params.as_ref().as_ref();
+        let Some(name) = params.and_then(|p| p.get("name")) else {
+            return Err(super::UnableToLoadConfigSnafu {
+                reason: "Name is required",
+            }
+            .build());
+        };
+        let Some(from) = params.and_then(|p| p.get("from")) else {
+            return Err(super::UnableToLoadConfigSnafu {
+                reason: "From is required",
+            }
+            .build());
+        };
+
+        let artifact = ArtifactUrl::parse(from)?;
+        let path = artifact::cache_path(name, &artifact).await?;
+        let path_str = path.to_string_lossy().to_string();
+        if artifact::is_pinned_and_cached(&path, &artifact).await {
+            tracing::info!("Model artifact {path_str} matches its checksum, skipping download");
+            return Ok(path_str);
+        }
+
+        let Some(bucket) = artifact.url.host_str() else {
+            return Err(super::UnableToLoadConfigSnafu {
+                reason: format!("from is invalid for s3 source, it has no bucket: {from}"),
+            }
+            .build());
+        };
+
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        if let Some(region) = params.and_then(|p| p.get("region")) {
+            builder = builder.with_region(region);
+        }
+        if let Some(endpoint) = params.and_then(|p| p.get("endpoint")) {
+            builder = builder.with_endpoint(endpoint).with_allow_http(true);
+        }
+        let credentials = secret
+            .as_ref()
+            .map(|secret| (secret.get("key"), secret.get("secret")));
+        match credentials.unwrap_or_default() {
+            (Some(key), Some(secret)) => {
+                builder = builder
+                    .with_access_key_id(key)
+                    .with_secret_access_key(secret);
+            }
+            _ => builder = builder.with_skip_signature(true),
+        }
+        let store = builder.build().context(super::UnableToGetObjectSnafu)?;
+
+        let location = Path::from(artifact.url.path().trim_start_matches('/'));
+        let meta = store
+            .head(&location)
+            .await
+            .context(super::UnableToGetObjectSnafu)?;
+        if meta.e_tag.is_some() && meta.e_tag == artifact::cached_etag(&path).await {
+            tracing::info!("Model artifact {path_str} is unchanged, skipping download");
+            return Ok(path_str);
+        }
+
+        tracing::info!("Downloading model: {}", artifact.url);
+        let mut stream = store
+            .get(&location)
+            .await
+            .context(super::UnableToGetObjectSnafu)?
+            .into_stream();
+        let mut writer = ArtifactWriter::create(&path).await?;
+        while let Some(chunk) = stream.next().await {
+            writer
+                .write(&chunk.context(super::UnableToGetObjectSnafu)?)
+                .await?;
+        }
+        writer
+            .finish(
+                artifact.url.as_str(),
+                artifact.sha256.as_deref(),
+                meta.e_tag.as_deref(),
+            )
+            .await?;
+        tracing::info!("Downloaded: {path_str}");
+
+        Ok(path_str)
+    }
+}
@@ -33,7 +33,7 @@ use secrets::Secret;
 impl ModelSource for SpiceAI {
     async fn pull(
         &self,
-        secret: Secret,
+        secret: Option<Secret>,
         params: Arc<Option<HashMap<String, String>>>,
     ) -> super::Result<String> {
         let name = params
@@ -107,9 +107,11 @@ impl ModelSource for SpiceAI {
         }
 
         let client = reqwest::Client::new();
-        let data: ModelRoot = client
-            .get(url)
-            .bearer_auth(secret.get("token").unwrap_or_default())
+        let mut request = client.get(url);
+        if let Some(token) = secret.as_ref().and_then(|secret| secret.get("token")) {
+            request = request.bearer_auth(token);
+        }
+        let data: ModelRoot = request
             .send()
             .await
             .context(super::UnableToFetchModelSnafu)?
@@ -43,9 +43,12 @@ use opentelemetry_proto::tonic::metrics::v1::number_data_point::Value;
 use opentelemetry_proto::tonic::metrics::v1::DataPointFlags;
 use opentelemetry_proto::tonic::metrics::v1::NumberDataPoint;
 use snafu::prelude::*;
+use spicepod::component::access::Operation;
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tonic_0_9_0::async_trait;
 use tonic_0_9_0::codec::CompressionEncoding;
+use tonic_0_9_0::service::interceptor::InterceptedService;
 use tonic_0_9_0::transport::Server;
 use tonic_0_9_0::Request;
 use tonic_0_9_0::Response;
@@ -53,9 +56,12 @@ use tonic_0_9_0::Status;
 use tonic_health::pb::health_server::Health;
 use tonic_health::pb::health_server::HealthServer;
 
+use crate::auth::{self, Authenticator, Principal};
 use crate::datafusion::DataFusion;
 use crate::dataupdate::DataUpdate;
 use crate::dataupdate::UpdateType;
+use crate::deduplication::IDEMPOTENCY_KEY_HEADER;
+use crate::tls::TlsAcceptor;
 use crate::{tracers::OnceTracer, warn_once};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -67,6 +73,9 @@ pub enum Error {
         source: tonic_0_9_0::transport::Error,
     },
 
+    #[snafu(display("Unable to bind to address: {source}"))]
+    UnableToBindServerToPort { source: std::io::Error },
+
     #[snafu(display("Failed to build record batch from OpenTelemetry metrics: {source}"))]
     FailedToBuildRecordBatch { source: arrow::error::ArrowError },
 
@@ -100,6 +109,7 @@ const START_TIME_UNIX_NANO_COLUMN_NAME: &str = "start_time_unix_nano";
 
 pub struct Service {
     data_fusion: Arc<RwLock<DataFusion>>,
+    auth: Option<Arc<Authenticator>>,
     once_tracer: OnceTracer,
 }
 
@@ -111,10 +121,23 @@ impl MetricsService for Service {
     ) -> std::result::Result<Response<ExportMetricsServiceResponse>, Status> {
         let mut rejected_data_points = 0;
         let mut total_data_points = 0;
+        let principal = request.extensions().get::<Principal>().cloned();
+        let idempotency_token = request
+            .metadata()
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|token| token.to_str().ok())
+            .map(ToString::to_string);
+        // Each metric is a separate write, so a retried export is deduplicated metric by metric.
+        let mut write_index = 0;
         for resource_metric in request.into_inner().resource_metrics {
             for scope_metric in resource_metric.scope_metrics {
                 for metric in scope_metric.metrics {
                     if let Some(data) = metric.data {
+                        let metric_token = idempotency_token
+                            .as_ref()
+                            .map(|token| format!("{token}:{write_index}"));
+                        write_index += 1;
+
                         let existing_schema = match self
                             .data_fusion
                             .read()
@@ -146,6 +169,21 @@ impl MetricsService for Service {
                                     continue;
                                 };
 
+                                if let Err(e) = auth::authorize(
+                                    self.auth.as_deref(),
+                                    principal.as_ref(),
+                                    metric.name.as_str(),
+                                    Operation::Write,
+                                ) {
+                                    warn_once!(
+                                        self.once_tracer,
+                                        "Skipping metric: {}",
+                                        e.to_string()
+                                    );
+                                    rejected_data_points += data_points_count;
+                                    continue;
+                                }
+
                                 let schema = record_batch.schema();
                                 let data_update = DataUpdate {
                                     data: vec![record_batch],
@@ -154,8 +192,13 @@ impl MetricsService for Service {
                                 };
 
                                 let mut write_failed = false;
-                                if let Err(e) =
-                                    df.write_data(metric.name.as_str(), data_update).await
+                                if let Err(e) = df
+                                    .write_data(
+                                        metric.name.as_str(),
+                                        data_update,
+                                        metric_token.as_deref(),
+                                    )
+                                    .await
                                 {
                                     write_failed = true;
                                     tracing::debug!("Failed to add OpenTelemetry data: {e}");
@@ -195,6 +238,27 @@ impl MetricsService for Service {
     }
 }
 
+/// Verifies the `authorization` header sent with every export, accepting the same API keys, users and session tokens
+/// as the HTTP and Flight APIs. The authenticated [`Principal`] is added to the request extensions.
+fn authenticate(
+    auth: &Authenticator,
+    mut request: Request<()>,
+) -> std::result::Result<Request<()>, Status> {
+    let authorization = request
+        .metadata()
+        .get("authorization")
+        .ok_or_else(|| Status::unauthenticated("Missing authorization header"))?
+        .to_str()
+        .map_err(|_| Status::unauthenticated("Invalid authorization header"))?;
+
+    let principal = auth
+        .authenticate(authorization)
+        .ok_or_else(|| Status::unauthenticated("Invalid credentials"))?;
+
+    request.extensions_mut().insert(principal);
+    Ok(request)
+}
+
 async fn create_health_service() -> HealthServer<impl Health> {
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     health_reporter
@@ -580,21 +644,41 @@ fn append_null(
     }
 }
 
-pub async fn start(bind_address: SocketAddr, data_fusion: Arc<RwLock<DataFusion>>) -> Result<()> {
+pub async fn start(
+    bind_address: SocketAddr,
+    data_fusion: Arc<RwLock<DataFusion>>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<Arc<TlsAcceptor>>,
+) -> Result<()> {
     let service = Service {
         data_fusion,
+        auth: auth.as_ref().map(Arc::clone),
         once_tracer: OnceTracer::new(),
     };
-    let svc = MetricsServiceServer::new(service).accept_compressed(CompressionEncoding::Gzip);
+    let svc = InterceptedService::new(
+        MetricsServiceServer::new(service).accept_compressed(CompressionEncoding::Gzip),
+        move |request: Request<()>| match &auth {
+            Some(auth) => authenticate(auth, request),
+            None => Ok(request),
+        },
+    );
 
     tracing::info!("Spice Runtime OpenTelemetry listening on {bind_address}");
 
-    Server::builder()
+    let router = Server::builder()
         .add_service(create_health_service().await)
-        .add_service(svc)
-        .serve(bind_address)
-        .await
-        .context(UnableToServeSnafu)?;
+        .add_service(svc);
+
+    match tls {
+        Some(tls) => {
+            let listener = TcpListener::bind(bind_address)
+                .await
+                .context(UnableToBindServerToPortSnafu)?;
+            router.serve_with_incoming(tls.incoming(listener)).await
+        }
+        None => router.serve(bind_address).await,
+    }
+    .context(UnableToServeSnafu)?;
 
     Ok(())
 }
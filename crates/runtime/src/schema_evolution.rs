@@ -0,0 +1,282 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Reconciles the schema of data written to a dataset with the dataset's schema.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::new_null_array,
+    compute::cast,
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use snafu::prelude::*;
+use spicepod::component::dataset::schema_evolution::SchemaEvolution;
+
+use crate::dataupdate::DataUpdate;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "Column \"{column}\" is not in the dataset. Enable schema_evolution.add_columns to add it."
+    ))]
+    UnknownColumn { column: String },
+
+    #[snafu(display("Column \"{column}\" is required by the dataset but missing from the data."))]
+    MissingRequiredColumn { column: String },
+
+    #[snafu(display(
+        "Column \"{column}\" has type {actual}, which can't be written to the dataset's {expected} column.{hint}"
+    ))]
+    IncompatibleType {
+        column: String,
+        expected: DataType,
+        actual: DataType,
+        hint: &'static str,
+    },
+
+    #[snafu(display("Unable to convert the data to the dataset schema: {source}"))]
+    UnableToConvertData { source: ArrowError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Returns the schema `table` must evolve to, to accept data with the `incoming` schema, or `None` if `table` can
+/// accept the data as it is.
+///
+/// New columns are appended as nullable columns, and widened columns keep their position.
+pub fn evolve_schema(
+    table: &Schema,
+    incoming: &Schema,
+    policy: &SchemaEvolution,
+) -> Result<Option<SchemaRef>> {
+    let mut fields: Vec<Field> = table
+        .fields()
+        .iter()
+        .map(|field| field.as_ref().clone())
+        .collect();
+    let mut evolved = false;
+
+    for field in &mut fields {
+        match incoming.field_with_name(field.name()) {
+            Ok(incoming_field) => {
+                let (table_type, incoming_type) = (field.data_type(), incoming_field.data_type());
+                if table_type == incoming_type || is_lossless_widening(incoming_type, table_type) {
+                    continue;
+                }
+
+                if is_lossless_widening(table_type, incoming_type) {
+                    ensure!(
+                        policy.widen_types,
+                        IncompatibleTypeSnafu {
+                            column: field.name().clone(),
+                            expected: table_type.clone(),
+                            actual: incoming_type.clone(),
+                            hint: " Enable schema_evolution.widen_types to widen the column.",
+                        }
+                    );
+                    *field = field.clone().with_data_type(incoming_type.clone());
+                    evolved = true;
+                } else {
+                    IncompatibleTypeSnafu {
+                        column: field.name().clone(),
+                        expected: table_type.clone(),
+                        actual: incoming_type.clone(),
+                        hint: "",
+                    }
+                    .fail()?;
+                }
+            }
+            Err(_) => ensure!(
+                field.is_nullable(),
+                MissingRequiredColumnSnafu {
+                    column: field.name().clone(),
+                }
+            ),
+        }
+    }
+
+    for incoming_field in incoming.fields() {
+        if table.field_with_name(incoming_field.name()).is_ok() {
+            continue;
+        }
+
+        ensure!(
+            policy.add_columns,
+            UnknownColumnSnafu {
+                column: incoming_field.name().clone(),
+            }
+        );
+        fields.push(incoming_field.as_ref().clone().with_nullable(true));
+        evolved = true;
+    }
+
+    Ok(evolved.then(|| Arc::new(Schema::new_with_metadata(fields, table.metadata().clone()))))
+}
+
+/// Converts `batch` to `schema`: reordering the columns, casting them to the column types and filling the missing
+/// nullable columns with nulls.
+pub fn conform_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    if batch.schema() == *schema {
+        return Ok(batch.clone());
+    }
+
+    let batch_schema = batch.schema();
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch_schema.index_of(field.name()) {
+            Ok(index) => cast(batch.column(index), field.data_type()),
+            Err(_) => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .context(UnableToConvertDataSnafu)?;
+
+    RecordBatch::try_new(Arc::clone(schema), columns).context(UnableToConvertDataSnafu)
+}
+
+/// Converts the batches of `data_update` to `schema` with [`conform_batch`].
+pub fn conform_data_update(data_update: DataUpdate, schema: SchemaRef) -> Result<DataUpdate> {
+    if data_update.schema == schema {
+        return Ok(data_update);
+    }
+
+    let data = data_update
+        .data
+        .iter()
+        .map(|batch| conform_batch(batch, &schema))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(DataUpdate {
+        schema,
+        data,
+        update_type: data_update.update_type,
+    })
+}
+
+/// Whether every value of type `from` can be represented as a value of type `to`.
+fn is_lossless_widening(from: &DataType, to: &DataType) -> bool {
+    use DataType::{
+        Float16, Float32, Float64, Int16, Int32, Int64, Int8, UInt16, UInt32, UInt64, UInt8,
+    };
+
+    matches!(
+        (from, to),
+        (Int8, Int16 | Int32 | Int64 | Float32 | Float64)
+            | (Int16, Int32 | Int64 | Float32 | Float64)
+            | (Int32, Int64 | Float64)
+            | (
+                UInt8,
+                UInt16 | UInt32 | UInt64 | Int16 | Int32 | Int64 | Float32 | Float64
+            )
+            | (UInt16, UInt32 | UInt64 | Int32 | Int64 | Float32 | Float64)
+            | (UInt32, UInt64 | Int64 | Float64)
+            | (Float16, Float32 | Float64)
+            | (Float32, Float64)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, Int32Array, Int64Array};
+
+    use super::*;
+
+    #[test]
+    fn test_evolve_schema() {
+        let table = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+        let strict = SchemaEvolution::default();
+        let permissive = SchemaEvolution {
+            add_columns: true,
+            widen_types: true,
+        };
+
+        // Narrower types and missing nullable columns don't change the table.
+        let incoming = Schema::new(vec![Field::new("id", DataType::Int16, false)]);
+        assert!(evolve_schema(&table, &incoming, &strict)
+            .expect("compatible")
+            .is_none());
+
+        // New columns are rejected unless enabled, and added as nullable columns.
+        let incoming = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("email", DataType::Utf8, false),
+        ]);
+        assert!(matches!(
+            evolve_schema(&table, &incoming, &strict),
+            Err(Error::UnknownColumn { .. })
+        ));
+        let evolved = evolve_schema(&table, &incoming, &permissive)
+            .expect("compatible")
+            .expect("evolved");
+        assert!(evolved
+            .field_with_name("email")
+            .expect("added")
+            .is_nullable());
+
+        // Wider types are rejected unless enabled.
+        let incoming = Schema::new(vec![Field::new("id", DataType::Int64, false)]);
+        assert!(matches!(
+            evolve_schema(&table, &incoming, &strict),
+            Err(Error::IncompatibleType { .. })
+        ));
+        let evolved = evolve_schema(&table, &incoming, &permissive)
+            .expect("compatible")
+            .expect("evolved");
+        assert_eq!(evolved.field(0).data_type(), &DataType::Int64);
+
+        // Incompatible types and missing required columns are always rejected.
+        let incoming = Schema::new(vec![Field::new("id", DataType::Utf8, false)]);
+        assert!(matches!(
+            evolve_schema(&table, &incoming, &permissive),
+            Err(Error::IncompatibleType { .. })
+        ));
+        let incoming = Schema::new(vec![Field::new("name", DataType::Utf8, true)]);
+        assert!(matches!(
+            evolve_schema(&table, &incoming, &permissive),
+            Err(Error::MissingRequiredColumn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_conform_batch() {
+        let target = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)])),
+            vec![Arc::new(Int32Array::from(vec![1, 2]))],
+        )
+        .expect("valid batch");
+
+        let conformed = conform_batch(&batch, &target).expect("conformed");
+
+        assert_eq!(conformed.schema(), target);
+        let ids = conformed
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .expect("widened");
+        assert_eq!(ids.value(1), 2);
+        assert_eq!(conformed.column(1).null_count(), 2);
+    }
+}
@@ -0,0 +1,521 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::{
+    fs,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use snafu::prelude::*;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::{VerifierBuilderError, WebPkiClientVerifier},
+    RootCertStore, ServerConfig,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Connections that don't complete the TLS handshake in time are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait before accepting again after an error that isn't specific to one connection, i.e. running out
+/// of file descriptors, which persists until other connections close.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read {}: {source}", path.display()))]
+    UnableToReadFile { source: io::Error, path: PathBuf },
+
+    #[snafu(display("Unable to parse PEM data: {source}"))]
+    UnableToParsePem { source: io::Error },
+
+    #[snafu(display("No private key found in the TLS key"))]
+    NoPrivateKey,
+
+    #[snafu(display("Invalid client CA certificate: {source}"))]
+    InvalidClientCaCertificate { source: rustls::Error },
+
+    #[snafu(display("Unable to build the client certificate verifier: {source}"))]
+    UnableToBuildClientVerifier { source: VerifierBuilderError },
+
+    #[snafu(display("Invalid TLS certificate or key: {source}"))]
+    InvalidCertificate { source: rustls::Error },
+
+    #[snafu(display("Unable to watch the TLS certificate files: {source}"))]
+    UnableToWatchFiles { source: notify::Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Where the private key for the TLS certificate comes from.
+#[derive(Debug, Clone)]
+pub enum TlsKey {
+    /// A PEM file, reloaded along with the certificate when it changes.
+    File(PathBuf),
+    /// PEM data loaded from the secret store.
+    Pem(String),
+}
+
+/// The certificate, key and optional client CA served by the runtime's listeners.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: TlsKey,
+    /// Clients must present a certificate signed by one of these CAs when set.
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    fn server_config(&self) -> Result<ServerConfig> {
+        let cert_pem = read_file(&self.cert)?;
+        let certs = rustls_pemfile::certs(&mut BufReader::new(cert_pem.as_slice()))
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+            .context(UnableToParsePemSnafu)?;
+
+        let key_pem = match &self.key {
+            TlsKey::File(path) => read_file(path)?,
+            TlsKey::Pem(pem) => pem.as_bytes().to_vec(),
+        };
+        let key: PrivateKeyDer<'static> =
+            rustls_pemfile::private_key(&mut BufReader::new(key_pem.as_slice()))
+                .context(UnableToParsePemSnafu)?
+                .context(NoPrivateKeySnafu)?;
+
+        let builder = ServerConfig::builder();
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let ca_pem = read_file(client_ca)?;
+                let mut roots = RootCertStore::empty();
+                for cert in rustls_pemfile::certs(&mut BufReader::new(ca_pem.as_slice())) {
+                    roots
+                        .add(cert.context(UnableToParsePemSnafu)?)
+                        .context(InvalidClientCaCertificateSnafu)?;
+                }
+                let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                    .build()
+                    .context(UnableToBuildClientVerifierSnafu)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .context(InvalidCertificateSnafu)?;
+        // gRPC requires HTTP/2, the HTTP API also serves HTTP/1.1 clients.
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(config)
+    }
+
+    fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.cert.clone()];
+        if let TlsKey::File(key) = &self.key {
+            files.push(key.clone());
+        }
+        if let Some(client_ca) = &self.client_ca {
+            files.push(client_ca.clone());
+        }
+        files
+    }
+}
+
+/// Performs the TLS handshake for connections accepted by the HTTP, Flight and OpenTelemetry listeners.
+///
+/// The certificate is reloaded when its files change, without restarting the listeners. Existing
+/// connections keep the certificate they were established with.
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    acceptor: RwLock<tokio_rustls::TlsAcceptor>,
+}
+
+impl TlsAcceptor {
+    pub fn new(config: TlsConfig) -> Result<Self> {
+        let server_config = config.server_config()?;
+        Ok(Self {
+            config,
+            acceptor: RwLock::new(tokio_rustls::TlsAcceptor::from(Arc::new(server_config))),
+        })
+    }
+
+    /// Reloads the certificate, key and client CA. The previous certificate stays in use if they're invalid.
+    pub fn reload(&self) -> Result<()> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(self.config.server_config()?));
+        match self.acceptor.write() {
+            Ok(mut current) => *current = acceptor,
+            Err(poisoned) => *poisoned.into_inner() = acceptor,
+        }
+        Ok(())
+    }
+
+    /// Watches the certificate files and reloads them when they change.
+    ///
+    /// The parent directories are watched so that certificates replaced by renaming a new file
+    /// into place (as Kubernetes does for mounted secrets) are also picked up. The returned
+    /// watcher must be kept alive for as long as changes should be detected.
+    pub fn watch(self: &Arc<Self>) -> Result<RecommendedWatcher> {
+        let files = self.config.watched_files();
+        let tls = Arc::clone(self);
+        let watched = files.clone();
+
+        let mut watcher =
+            notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
+                match res {
+                    Ok(event) => {
+                        if event.kind.is_access() || !event_affects(&event, &watched) {
+                            return;
+                        }
+                        match tls.reload() {
+                            Ok(()) => tracing::info!("Reloaded TLS certificate"),
+                            Err(e) => tracing::warn!(
+                                "Unable to reload TLS certificate, continuing to use the previous certificate: {e}"
+                            ),
+                        }
+                    }
+                    Err(e) => tracing::error!("TLS certificate watcher error: {e}"),
+                }
+            })
+            .context(UnableToWatchFilesSnafu)?;
+
+        let mut directories: Vec<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+        directories.sort();
+        directories.dedup();
+        for directory in directories {
+            let directory = if directory.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                directory
+            };
+            watcher
+                .watch(directory, RecursiveMode::NonRecursive)
+                .context(UnableToWatchFilesSnafu)?;
+        }
+
+        Ok(watcher)
+    }
+
+    /// Accepts connections from `listener` and yields those that complete the TLS handshake.
+    ///
+    /// Handshakes run concurrently so a slow client can't hold up other connections. Failed
+    /// handshakes are logged and dropped, so the stream never yields an error.
+    pub fn incoming(
+        self: &Arc<Self>,
+        listener: TcpListener,
+    ) -> ReceiverStream<Result<TlsStream, io::Error>> {
+        let (tx, rx) = mpsc::channel(128);
+        let tls = Arc::clone(self);
+
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (tcp_stream, remote_addr) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(e) if is_connection_error(&e) => {
+                        tracing::debug!("Unable to accept connection: {e}");
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "Unable to accept connection, retrying in {ACCEPT_ERROR_BACKOFF:?}: {e}"
+                        );
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+
+                let acceptor = tls.acceptor();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp_stream)).await
+                    {
+                        Ok(Ok(inner)) => {
+                            let _ = tx.send(Ok(TlsStream { inner, remote_addr })).await;
+                        }
+                        Ok(Err(e)) => {
+                            tracing::debug!("TLS handshake with {remote_addr} failed: {e}")
+                        }
+                        Err(_) => tracing::debug!("TLS handshake with {remote_addr} timed out"),
+                    }
+                });
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+
+    fn acceptor(&self) -> tokio_rustls::TlsAcceptor {
+        match self.acceptor.read() {
+            Ok(acceptor) => acceptor.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+/// A TLS connection accepted by [`TlsAcceptor::incoming`].
+pub struct TlsStream {
+    inner: tokio_rustls::server::TlsStream<TcpStream>,
+    remote_addr: SocketAddr,
+}
+
+impl TlsStream {
+    #[must_use]
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+impl AsyncRead for TlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+impl tonic::transport::server::Connected for TlsStream {
+    type ConnectInfo = SocketAddr;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.remote_addr
+    }
+}
+
+// The OpenTelemetry receiver is served by an older tonic release.
+impl tonic_0_9_0::transport::server::Connected for TlsStream {
+    type ConnectInfo = SocketAddr;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.remote_addr
+    }
+}
+
+/// Errors caused by a single connection, such as the client resetting it before it was accepted.
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).context(UnableToReadFileSnafu { path })
+}
+
+/// Kubernetes updates mounted secrets by swapping the `..data` symlink instead of the files themselves.
+fn event_affects(event: &notify::Event, files: &[PathBuf]) -> bool {
+    event.paths.iter().any(|path| {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        file_name.is_some_and(|name| name.starts_with(".."))
+            || files
+                .iter()
+                .any(|file| path == file || path.file_name() == file.file_name())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::CertifiedKey;
+    use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spice-tls-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).expect("temp dir is created");
+        dir
+    }
+
+    /// Generates a self-signed certificate for `localhost` and writes it to `cert.pem` and `key.pem` in `dir`.
+    fn write_certificate(dir: &Path) -> CertifiedKey {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .expect("certificate is generated");
+        fs::write(dir.join("cert.pem"), certified.cert.pem()).expect("certificate is written");
+        fs::write(dir.join("key.pem"), certified.key_pair.serialize_pem()).expect("key is written");
+        certified
+    }
+
+    fn config(dir: &Path) -> TlsConfig {
+        TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: TlsKey::File(dir.join("key.pem")),
+            client_ca: None,
+        }
+    }
+
+    /// Completes a TLS handshake with `tls`, trusting only `trusted`, and returns the certificate it served. `None`
+    /// if the handshake fails.
+    async fn served_certificate(tls: &Arc<TlsAcceptor>, trusted: &CertifiedKey) -> Option<Vec<u8>> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("local port binds");
+        let address = listener.local_addr().expect("listener has an address");
+        let _incoming = tls.incoming(listener);
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(trusted.cert.der().clone())
+            .expect("valid root certificate");
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        ));
+        let tcp_stream = TcpStream::connect(address)
+            .await
+            .expect("listener accepts connections");
+        let server_name = ServerName::try_from("localhost").expect("valid server name");
+        let stream = connector.connect(server_name, tcp_stream).await.ok()?;
+        stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| cert.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let dir = temp_dir();
+        let certified = write_certificate(&dir);
+
+        let tls = Arc::new(TlsAcceptor::new(config(&dir)).expect("certificate loads"));
+        assert_eq!(
+            served_certificate(&tls, &certified).await,
+            Some(certified.cert.der().to_vec())
+        );
+
+        let from_secret = TlsConfig {
+            key: TlsKey::Pem(certified.key_pair.serialize_pem()),
+            ..config(&dir)
+        };
+        assert!(TlsAcceptor::new(from_secret).is_ok());
+
+        let with_client_ca = TlsConfig {
+            client_ca: Some(dir.join("cert.pem")),
+            ..config(&dir)
+        };
+        assert!(TlsAcceptor::new(with_client_ca).is_ok());
+
+        let without_key = TlsConfig {
+            key: TlsKey::File(dir.join("cert.pem")),
+            ..config(&dir)
+        };
+        let Err(Error::NoPrivateKey) = TlsAcceptor::new(without_key) else {
+            panic!("a certificate is not a private key");
+        };
+
+        let missing = TlsConfig {
+            cert: dir.join("missing.pem"),
+            ..config(&dir)
+        };
+        let Err(Error::UnableToReadFile { .. }) = TlsAcceptor::new(missing) else {
+            panic!("the certificate doesn't exist");
+        };
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let dir = temp_dir();
+        let first = write_certificate(&dir);
+        let tls = Arc::new(TlsAcceptor::new(config(&dir)).expect("certificate loads"));
+
+        // A broken certificate is not loaded, the previous one is still served.
+        fs::write(dir.join("key.pem"), "not a key").expect("key is written");
+        assert!(tls.reload().is_err());
+        assert_eq!(
+            served_certificate(&tls, &first).await,
+            Some(first.cert.der().to_vec())
+        );
+
+        let second = write_certificate(&dir);
+        tls.reload().expect("certificate reloads");
+        assert_eq!(
+            served_certificate(&tls, &second).await,
+            Some(second.cert.der().to_vec())
+        );
+        assert_eq!(served_certificate(&tls, &first).await, None);
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+
+    #[tokio::test]
+    async fn test_watch_reloads_changed_files() {
+        let dir = temp_dir();
+        write_certificate(&dir);
+        let tls = Arc::new(TlsAcceptor::new(config(&dir)).expect("certificate loads"));
+        let _watcher = tls.watch().expect("files are watched");
+
+        let replaced = write_certificate(&dir);
+        let mut served = None;
+        for _ in 0..100 {
+            served = served_certificate(&tls, &replaced).await;
+            if served.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(served, Some(replaced.cert.der().to_vec()));
+
+        fs::remove_dir_all(dir).expect("temp dir is removed");
+    }
+}
@@ -21,6 +21,8 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use snafu::prelude::*;
 
 use crate::reader;
+pub mod access;
+pub mod auth;
 pub mod dataset;
 pub mod model;
 pub mod params;
@@ -0,0 +1,69 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// A role in the spicepod `access` section, granting its principals a set of operations on datasets.
+///
+/// Principals are the API key names and basic auth usernames configured in the `auth` section. A
+/// dataset of `*` matches every dataset. When no roles are configured, authenticated principals
+/// have access to every dataset.
+///
+/// Example:
+/// ```yaml
+/// access:
+///   - name: analysts
+///     principals: [alice, dashboards]
+///     datasets: ["*"]
+///     operations: [read]
+///   - name: ingest
+///     principals: [pipeline]
+///     datasets: [events]
+///     operations: [read, write, refresh]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Role {
+    pub name: String,
+
+    #[serde(default)]
+    pub principals: Vec<String>,
+
+    #[serde(default)]
+    pub datasets: Vec<String>,
+
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Read,
+    Write,
+    Refresh,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Operation::Read => write!(f, "read"),
+            Operation::Write => write!(f, "write"),
+            Operation::Refresh => write!(f, "refresh"),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+/*
+Copyright 2024 The Spice.ai OSS Authors
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use serde::{Deserialize, Serialize};
+
+/// The authentication configuration for a Spicepod.
+///
+/// Credentials are read from the configured secret store. Each entry of an API key secret maps a
+/// key name to the API key, and each entry of a basic auth secret maps a username to its password.
+///
+/// Example:
+/// ```yaml
+/// auth:
+///   api_key:
+///     secret: spice_api_keys
+///   basic:
+///     secret: spice_users
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Auth {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<AuthProvider>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic: Option<AuthProvider>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthProvider {
+    /// The name of the secret that holds the credentials.
+    pub secret: String,
+}
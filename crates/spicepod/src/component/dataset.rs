@@ -89,6 +89,14 @@ pub struct Dataset {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub acceleration: Option<acceleration::Acceleration>,
 
+    /// How writes with a schema that differs from the dataset's schema are handled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_evolution: Option<schema_evolution::SchemaEvolution>,
+
+    /// Drops the writes to the dataset that repeat a recent write.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deduplication: Option<deduplication::Deduplication>,
+
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(rename = "dependsOn", default)]
     pub depends_on: Vec<String>,
@@ -108,6 +116,8 @@ impl Dataset {
             time_column: None,
             time_format: None,
             acceleration: None,
+            schema_evolution: None,
+            deduplication: None,
             depends_on: Vec::default(),
         }
     }
@@ -224,6 +234,23 @@ impl Dataset {
         None
     }
 
+    pub fn deduplication_window(&self) -> Option<Duration> {
+        if let Some(deduplication) = &self.deduplication {
+            if let Some(window) = &deduplication.window {
+                if let Ok(duration) = fundu::parse_duration(window) {
+                    return Some(duration);
+                }
+                tracing::warn!(
+                    "Unable to parse deduplication window for dataset {}: {}",
+                    self.name,
+                    window
+                );
+            }
+        }
+
+        None
+    }
+
     pub fn retention_period(&self) -> Option<Duration> {
         if let Some(acceleration) = &self.acceleration {
             if let Some(retention_period) = &acceleration.retention_period {
@@ -320,6 +347,8 @@ impl WithDependsOn<Dataset> for Dataset {
             time_column: self.time_column.clone(),
             time_format: self.time_format.clone(),
             acceleration: self.acceleration.clone(),
+            schema_evolution: self.schema_evolution.clone(),
+            deduplication: self.deduplication.clone(),
             depends_on: depends_on.to_vec(),
         }
     }
@@ -412,6 +441,44 @@ pub mod acceleration {
     }
 }
 
+pub mod schema_evolution {
+    use serde::{Deserialize, Serialize};
+
+    /// The changes that writes can make to the schema of a writable dataset.
+    ///
+    /// Writes that leave out nullable columns or use narrower numeric types are always accepted.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct SchemaEvolution {
+        /// Add the columns that aren't in the dataset as nullable columns. Only the accelerator is altered, so the
+        /// added columns must already exist in the dataset source.
+        #[serde(default)]
+        pub add_columns: bool,
+
+        /// Widen the type of numeric columns when the data has a wider type, i.e. `Int32` to `Int64`.
+        #[serde(default)]
+        pub widen_types: bool,
+    }
+}
+
+pub mod deduplication {
+    use serde::{Deserialize, Serialize};
+
+    /// Drops repeated writes to a writable dataset within a window.
+    ///
+    /// A write that carries an idempotency token already written within the window is dropped. When `key` is set,
+    /// the rows whose `key` columns match a row written within the window are dropped too.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+    pub struct Deduplication {
+        /// The columns that identify a row.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub key: Vec<String>,
+
+        /// How long written tokens and keys are remembered, i.e. `1h`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub window: Option<String>,
+    }
+}
+
 pub mod replication {
     use serde::{Deserialize, Serialize};
 
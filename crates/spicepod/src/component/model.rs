@@ -14,7 +14,9 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use super::WithDependsOn;
+use std::time::Duration;
+
+use super::{params::Params, WithDependsOn};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -29,6 +31,79 @@ pub struct Model {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(rename = "datasets", default)]
     pub datasets: Vec<String>,
+
+    /// The format of the model artifact, which picks the runtime that loads it.
+    #[serde(default)]
+    pub format: Format,
+
+    /// Params for the model source and runtime, i.e. the `region` of an S3 bucket or `optimize: false` for an ONNX
+    /// model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Params>,
+
+    /// The name of the secret the source of `from` authenticates with. Without it, the secret named after the source
+    /// is used if there is one, and the model is pulled without credentials otherwise. Other versions name their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+
+    /// How the model's input and output tensors map to columns. Models without a mapping take a single
+    /// `[1, lookback, variates]` input and return a single `y` column, which fits the built-in forecasting models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tensors: Option<tensors::Tensors>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batching: Option<batching::Batching>,
+
+    /// Other versions of the model, loaded side by side with the version in `from`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<versions::Version>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inference_log: Option<inference_log::InferenceLog>,
+}
+
+/// The format of a model artifact.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Onnx,
+
+    /// A tree ensemble saved as JSON by XGBoost's `save_model`.
+    Xgboost,
+
+    /// A tree ensemble saved as JSON by LightGBM's `dump_model`.
+    Lightgbm,
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Onnx => write!(f, "onnx"),
+            Format::Xgboost => write!(f, "xgboost"),
+            Format::Lightgbm => write!(f, "lightgbm"),
+        }
+    }
+}
+
+impl Model {
+    #[must_use]
+    pub fn batching_max_wait(&self) -> Option<Duration> {
+        if let Some(batching) = &self.batching {
+            if let Some(max_wait) = &batching.max_wait {
+                if let Ok(duration) = fundu::parse_duration(max_wait) {
+                    return Some(duration);
+                }
+                tracing::warn!(
+                    "Unable to parse batching max wait for model {}: {}",
+                    self.name,
+                    max_wait
+                );
+            }
+        }
+
+        None
+    }
 }
 
 impl WithDependsOn<Model> for Model {
@@ -38,6 +113,124 @@ impl WithDependsOn<Model> for Model {
             name: self.name.clone(),
             files: depends_on.to_vec(),
             datasets: depends_on.to_vec(),
+            format: self.format,
+            params: self.params.clone(),
+            secret: self.secret.clone(),
+            tensors: self.tensors.clone(),
+            batching: self.batching.clone(),
+            versions: self.versions.clone(),
+            inference_log: self.inference_log.clone(),
         }
     }
 }
+
+pub mod tensors {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct Tensors {
+        pub inputs: Vec<Input>,
+
+        pub outputs: Vec<Output>,
+    }
+
+    /// The element type of a tensor.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+    #[serde(rename_all = "lowercase")]
+    pub enum DType {
+        #[default]
+        Float32,
+        Float64,
+        Int32,
+        Int64,
+    }
+
+    /// An input tensor built from the rows of the input data.
+    ///
+    /// Each row contributes the values of `columns` in order, and the values of list columns are flattened, i.e. the
+    /// rows of a `[-1, 4]` input have four values. A `-1` dimension takes the size that fits the input rows, and
+    /// a tensor with a fixed shape needs exactly as many rows as it holds.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct Input {
+        /// The name of the input in the model. Inputs without a name are passed in the order they are listed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+
+        pub columns: Vec<String>,
+
+        pub shape: Vec<i64>,
+
+        #[serde(default)]
+        pub dtype: DType,
+    }
+
+    /// Columns read from an output tensor.
+    ///
+    /// The first dimensions of the tensor are the rows. The last dimension is split across `columns` when it lists
+    /// one column per value, and otherwise a single column holds the values of each row as a list, i.e. an embedding.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct Output {
+        /// The name of the output in the model. Outputs without a name are read in the order they are listed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+
+        pub columns: Vec<String>,
+    }
+}
+
+pub mod batching {
+    use serde::{Deserialize, Serialize};
+
+    /// Merges the inputs of concurrent inference requests into one run of the model.
+    ///
+    /// Only models that return a row for each input row are batched.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct Batching {
+        /// The most input rows in a batch.
+        #[serde(default = "default_max_rows")]
+        pub max_rows: usize,
+
+        /// How long a request waits for others to join its batch, i.e. `5ms`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_wait: Option<String>,
+    }
+
+    const fn default_max_rows() -> usize {
+        1024
+    }
+}
+
+pub mod versions {
+    use serde::{Deserialize, Serialize};
+
+    /// A version of a model that serves a share of its predictions.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct Version {
+        pub from: String,
+
+        /// The name predictions report the version by. Defaults to the part of `from` after its last `:`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+
+        /// The percentage of predictions the version serves. The version in `from` serves the rest.
+        #[serde(default)]
+        pub weight: u32,
+
+        /// The name of the secret the version's source authenticates with, like the model's `secret`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub secret: Option<String>,
+    }
+}
+
+pub mod inference_log {
+    use serde::{Deserialize, Serialize};
+
+    /// Logs each prediction the model serves to a writable dataset.
+    ///
+    /// Each prediction is a row with the columns `ts`, `request_id`, `model`, `version`, `duration_ms`, `inputs`,
+    /// `outputs` and `error`. The inputs and outputs are JSON arrays of rows.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct InferenceLog {
+        pub dataset: String,
+    }
+}
@@ -21,6 +21,8 @@ use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
 use std::{fmt::Debug, path::PathBuf};
 
+use component::access::Role;
+use component::auth::Auth;
 use component::dataset::Dataset;
 use component::model::Model;
 use component::secrets::Secrets;
@@ -53,6 +55,10 @@ pub struct Spicepod {
 
     pub secrets: Secrets,
 
+    pub auth: Option<Auth>,
+
+    pub access: Vec<Role>,
+
     pub datasets: Vec<Dataset>,
 
     pub models: Vec<Model>,
@@ -134,6 +140,8 @@ fn from_definition(
         name: spicepod_definition.name,
         version: spicepod_definition.version,
         secrets: spicepod_definition.secrets,
+        auth: spicepod_definition.auth,
+        access: spicepod_definition.access,
         datasets,
         models,
         dependencies: spicepod_definition.dependencies,
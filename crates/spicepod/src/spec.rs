@@ -19,6 +19,8 @@ use serde_yaml::{self, Value};
 use std::fmt::{self, Display, Formatter};
 use std::{collections::HashMap, fmt::Debug};
 
+use crate::component::access::Role;
+use crate::component::auth::Auth;
 use crate::component::secrets::Secrets;
 use crate::component::{dataset::Dataset, model::Model, ComponentOrReference};
 
@@ -47,6 +49,17 @@ pub struct SpicepodDefinition {
     #[serde(default)]
     pub secrets: Secrets,
 
+    /// Optional authentication configuration for the runtime APIs
+    /// Authentication is disabled when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub auth: Option<Auth>,
+
+    /// Optional dataset access control for authenticated principals
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub access: Vec<Role>,
+
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     pub metadata: HashMap<String, Value>,